@@ -0,0 +1,73 @@
+use std::rc::{Rc};
+use core::types::{Size2};
+use core::db::{Db};
+use core::unit::{Unit};
+use core::game_state::{State};
+use core::{
+    CoreEvent,
+    GameType,
+    MapGenParams,
+    Options,
+    PlayerId,
+    ReactionFireMode,
+    TimeOfDay,
+    UnitId,
+    Weather,
+    ZocRule,
+    get_free_exact_pos,
+};
+
+pub fn big_state(db: Rc<Db>) -> State {
+    let mut params = MapGenParams::new(42);
+    params.size = Size2{w: 40, h: 40};
+    params.cities_count = 6;
+    params.forests_count = 12;
+    params.lakes_count = 4;
+    let options = Options {
+        game_type: GameType::Hotseat,
+        map_name: "generated".into(),
+        players_count: 2,
+        weather: Weather::Clear,
+        time_of_day: TimeOfDay::Day,
+        zoc_rule: ZocRule::Disabled,
+        map_gen_params: Some(params),
+    };
+    let mut state = State::new_full(db.clone(), &options);
+    let type_id = db.unit_type_id("soldier");
+    let unit_type = db.unit_type(type_id);
+    let mut id = 0;
+    for pos in state.map().get_iter() {
+        // roughly one squad per two tiles
+        if (pos.v.x + pos.v.y) % 2 != 0 {
+            continue;
+        }
+        let exact_pos = match get_free_exact_pos(&db, &state, type_id, pos) {
+            Some(exact_pos) => exact_pos,
+            None => continue,
+        };
+        id += 1;
+        let unit = Unit {
+            id: UnitId{id: id},
+            pos: exact_pos,
+            player_id: PlayerId{id: id % 2},
+            type_id: type_id,
+            move_points: Some(unit_type.move_points),
+            attack_points: Some(unit_type.attack_points),
+            reactive_attack_points: Some(unit_type.reactive_attack_points),
+            reaction_fire_mode: ReactionFireMode::Normal,
+            count: unit_type.count,
+            morale: 100,
+            passenger_id: None,
+            attached_unit_id: None,
+            is_alive: true,
+            is_loaded: false,
+            is_attached: false,
+            is_entrenched: false,
+            is_immobilized: false,
+            is_weapon_disabled: false,
+            is_crew_shaken: false,
+        };
+        state.apply_event(&CoreEvent::ShowUnit{unit_info: unit});
+    }
+    state
+}
@@ -0,0 +1,133 @@
+/// The float field of view that `core::fov` replaced,
+/// kept only as the baseline for the benchmarks
+
+use std::f32::consts::{PI};
+use cgmath::{InnerSpace, Vector2};
+use core::game_state::{State};
+use core::map::{Terrain, spiral_iter, distance};
+use core::geom;
+use core::{MapPos, ObjectClass, Distance};
+
+struct Shadow {
+    left: f32,
+    right: f32,
+}
+
+fn is_tile_visible(angle: f32, shadows: &[Shadow]) -> bool {
+    for shadow in shadows {
+        if shadow.left < angle && shadow.right > angle {
+            return false;
+        }
+    }
+    true
+}
+
+fn is_obstacle(state: &State, pos: MapPos) -> bool {
+    match *state.map().tile(pos){
+        Terrain::Trees | Terrain::City => return true,
+        Terrain::Plain | Terrain::Water => {},
+    }
+    for object in state.objects_at(pos) {
+        match object.class {
+            ObjectClass::Building |
+            ObjectClass::Smoke => return true,
+            ObjectClass::Rubble |
+            ObjectClass::ReinforcementSector |
+            ObjectClass::Road |
+            ObjectClass::Bridge |
+            ObjectClass::River |
+            ObjectClass::Wall |
+            ObjectClass::Hedge => {},
+        }
+    }
+    false
+}
+
+// Low walls block only some of the lines crossing them
+fn hexside_obstacle_radius(class: ObjectClass) -> Option<f32> {
+    match class {
+        ObjectClass::Hedge => Some(geom::HEX_IN_RADIUS * 0.6),
+        ObjectClass::Wall => Some(geom::HEX_IN_RADIUS * 0.3),
+        ObjectClass::Building |
+        ObjectClass::Rubble |
+        ObjectClass::Smoke |
+        ObjectClass::ReinforcementSector |
+        ObjectClass::Road |
+        ObjectClass::Bridge |
+        ObjectClass::River => None,
+    }
+}
+
+fn add_shadow(
+    shadows: &mut Vec<Shadow>,
+    origin3d: Vector2<f32>,
+    pos3d: Vector2<f32>,
+    obstacle_radius: f32,
+) {
+    let diff = pos3d - origin3d;
+    let distance = diff.magnitude();
+    let angle = diff.x.atan2(diff.y);
+    let a = (obstacle_radius / distance).asin();
+    let shadow = Shadow{left: angle - a, right: angle + a};
+    if shadow.right > PI {
+        shadows.push(Shadow{left: -PI, right: shadow.right - PI * 2.0});
+    }
+    shadows.push(shadow);
+}
+
+// Hexsides are processed from the closer tile, units
+// right behind the hexside can see over it.
+fn add_hexside_shadows(
+    state: &State,
+    shadows: &mut Vec<Shadow>,
+    origin: MapPos,
+    pos: MapPos,
+) {
+    let origin3d = geom::map_pos_to_world_pos(origin);
+    let pos3d = geom::map_pos_to_world_pos(pos);
+    for object in state.objects_at(pos) {
+        let obstacle_radius = match hexside_obstacle_radius(object.class) {
+            Some(radius) => radius,
+            None => continue,
+        };
+        for other_pos in object.pos.map_pos_iter() {
+            if other_pos == pos || other_pos == origin {
+                continue;
+            }
+            if distance(origin, other_pos) <= distance(origin, pos) {
+                continue;
+            }
+            let other_pos3d = geom::map_pos_to_world_pos(other_pos);
+            let edge3d = (pos3d + other_pos3d) / 2.0;
+            add_shadow(shadows, origin3d, edge3d, obstacle_radius);
+        }
+    }
+}
+
+pub fn fov(
+    state: &State,
+    origin: MapPos,
+    range: Distance,
+    callback: &mut FnMut(MapPos),
+) {
+    callback(origin);
+    let map = state.map();
+    let mut shadows = vec!();
+    let origin3d = geom::map_pos_to_world_pos(origin);
+    for pos in spiral_iter(origin, range) {
+        if !map.is_inboard(pos) {
+            continue;
+        }
+        let pos3d = geom::map_pos_to_world_pos(pos);
+        let diff = pos3d - origin3d;
+        let angle = diff.x.atan2(diff.y);
+        if is_tile_visible(angle, &shadows) {
+            callback(pos);
+        }
+        if is_obstacle(state, pos) {
+            let obstacle_radius = geom::HEX_IN_RADIUS * 1.1;
+            add_shadow(&mut shadows, origin3d, pos3d, obstacle_radius);
+        }
+        add_hexside_shadows(state, &mut shadows, origin, pos);
+    }
+}
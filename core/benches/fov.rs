@@ -0,0 +1,96 @@
+#[macro_use]
+extern crate bencher;
+extern crate cgmath;
+extern crate core;
+
+mod common;
+mod float_fov;
+
+use std::rc::{Rc};
+use bencher::{Bencher};
+use cgmath::{Vector2};
+use core::db::{Db};
+use core::fov::{fov, has_los};
+use core::map::{distance};
+use core::{MapPos, Distance};
+use common::{big_state};
+
+const RANGE: Distance = Distance{n: 8};
+
+fn full_fov(bencher: &mut Bencher) {
+    let state = big_state(Rc::new(Db::new()));
+    let origin = MapPos{v: Vector2{x: 20, y: 20}};
+    bencher.iter(|| {
+        let mut count = 0;
+        fov(&state, origin, RANGE, &mut |_| count += 1);
+        count
+    });
+}
+
+fn full_float_fov(bencher: &mut Bencher) {
+    let state = big_state(Rc::new(Db::new()));
+    let origin = MapPos{v: Vector2{x: 20, y: 20}};
+    bencher.iter(|| {
+        let mut count = 0;
+        float_fov::fov(&state, origin, RANGE, &mut |_| count += 1);
+        count
+    });
+}
+
+// That's how attack checks used to find out if the target is visible
+fn los_with_float_fov(bencher: &mut Bencher) {
+    let state = big_state(Rc::new(Db::new()));
+    let origin = MapPos{v: Vector2{x: 20, y: 20}};
+    let targets: Vec<_> = state.map().get_iter()
+        .filter(|&pos| distance(origin, pos) <= RANGE)
+        .collect();
+    bencher.iter(|| {
+        targets.iter().filter(|&&target| {
+            let mut is_visible = false;
+            float_fov::fov(&state, origin, RANGE, &mut |pos| if pos == target {
+                is_visible = true;
+            });
+            is_visible
+        }).count()
+    });
+}
+
+fn los_with_fov(bencher: &mut Bencher) {
+    let state = big_state(Rc::new(Db::new()));
+    let origin = MapPos{v: Vector2{x: 20, y: 20}};
+    let targets: Vec<_> = state.map().get_iter()
+        .filter(|&pos| distance(origin, pos) <= RANGE)
+        .collect();
+    bencher.iter(|| {
+        targets.iter().filter(|&&target| {
+            let mut is_visible = false;
+            fov(&state, origin, RANGE, &mut |pos| if pos == target {
+                is_visible = true;
+            });
+            is_visible
+        }).count()
+    });
+}
+
+fn los_point_to_point(bencher: &mut Bencher) {
+    let state = big_state(Rc::new(Db::new()));
+    let origin = MapPos{v: Vector2{x: 20, y: 20}};
+    let targets: Vec<_> = state.map().get_iter()
+        .filter(|&pos| distance(origin, pos) <= RANGE)
+        .collect();
+    bencher.iter(|| {
+        targets.iter()
+            .filter(|&&target| has_los(&state, origin, target))
+            .count()
+    });
+}
+
+benchmark_group!(
+    benches,
+    full_float_fov,
+    full_fov,
+    los_with_float_fov,
+    los_with_fov,
+    los_point_to_point
+);
+benchmark_main!(benches);
@@ -0,0 +1,92 @@
+#[macro_use]
+extern crate bencher;
+extern crate cgmath;
+extern crate core;
+
+mod common;
+
+use std::rc::{Rc};
+use bencher::{Bencher};
+use cgmath::{Vector2};
+use core::db::{Db};
+use core::unit::{Unit};
+use core::game_state::{State};
+use core::pathfinder::{Pathfinder};
+use core::map::{distance};
+use core::{ExactPos, MapPos, get_free_exact_pos};
+use common::{big_state};
+
+fn unit_near(state: &State, pos: MapPos) -> Unit {
+    state.units()
+        .map(|(_, unit)| unit)
+        .min_by_key(|unit| distance(unit.pos.map_pos, pos).n)
+        .unwrap()
+        .clone()
+}
+
+fn far_destination(db: &Db, state: &State, unit: &Unit) -> ExactPos {
+    let size = state.map().size();
+    let pos = MapPos{v: Vector2{x: size.w - 1, y: size.h - 1}};
+    state.map().get_iter()
+        .filter(|&p| distance(p, pos).n < 6)
+        .filter_map(|p| get_free_exact_pos(db, state, unit.type_id, p))
+        .next()
+        .unwrap()
+}
+
+fn fill_map(bencher: &mut Bencher) {
+    let db = Rc::new(Db::new());
+    let state = big_state(db.clone());
+    let mut pathfinder = Pathfinder::new(db, state.map());
+    let unit = unit_near(&state, MapPos{v: Vector2{x: 20, y: 20}});
+    bencher.iter(|| {
+        pathfinder.fill_map(&state, &unit);
+    });
+}
+
+fn fill_map_and_get_path(bencher: &mut Bencher) {
+    let db = Rc::new(Db::new());
+    let state = big_state(db.clone());
+    let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+    let unit = unit_near(&state, MapPos{v: Vector2{x: 0, y: 0}});
+    let destination = far_destination(&db, &state, &unit);
+    bencher.iter(|| {
+        pathfinder.fill_map(&state, &unit);
+        pathfinder.get_path(destination).unwrap()
+    });
+}
+
+fn find_path(bencher: &mut Bencher) {
+    let db = Rc::new(Db::new());
+    let state = big_state(db.clone());
+    let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+    let unit = unit_near(&state, MapPos{v: Vector2{x: 0, y: 0}});
+    let destination = far_destination(&db, &state, &unit);
+    bencher.iter(|| {
+        pathfinder.find_path(&state, &unit, destination).unwrap()
+    });
+}
+
+fn find_short_path(bencher: &mut Bencher) {
+    let db = Rc::new(Db::new());
+    let state = big_state(db.clone());
+    let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+    let unit = unit_near(&state, MapPos{v: Vector2{x: 20, y: 20}});
+    let destination = state.map().get_iter()
+        .filter(|&p| distance(p, unit.pos.map_pos).n == 3)
+        .filter_map(|p| get_free_exact_pos(&db, &state, unit.type_id, p))
+        .next()
+        .unwrap();
+    bencher.iter(|| {
+        pathfinder.find_path(&state, &unit, destination).unwrap()
+    });
+}
+
+benchmark_group!(
+    benches,
+    fill_map,
+    fill_map_and_get_path,
+    find_path,
+    find_short_path
+);
+benchmark_main!(benches);
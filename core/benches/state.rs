@@ -0,0 +1,41 @@
+#[macro_use]
+extern crate bencher;
+extern crate core;
+
+mod common;
+
+use std::rc::{Rc};
+use bencher::{Bencher, black_box};
+use core::db::{Db};
+use common::{big_state};
+
+fn units_at_all_tiles(bencher: &mut Bencher) {
+    let db = Rc::new(Db::new());
+    let state = big_state(db);
+    bencher.iter(|| {
+        let mut count = 0;
+        for pos in state.map().get_iter() {
+            count += state.units_at(pos).count();
+        }
+        black_box(count)
+    });
+}
+
+fn objects_at_all_tiles(bencher: &mut Bencher) {
+    let db = Rc::new(Db::new());
+    let state = big_state(db);
+    bencher.iter(|| {
+        let mut count = 0;
+        for pos in state.map().get_iter() {
+            count += state.objects_at(pos).count();
+        }
+        black_box(count)
+    });
+}
+
+benchmark_group!(
+    benches,
+    units_at_all_tiles,
+    objects_at_all_tiles
+);
+benchmark_main!(benches);
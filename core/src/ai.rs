@@ -2,7 +2,7 @@ use std::rc::{Rc};
 use rand::{thread_rng, Rng};
 use game_state::{State};
 use map::{distance};
-use pathfinder::{self, Pathfinder, path_cost, truncate_path};
+use pathfinder::{self, Pathfinder, PathMode, path_cost, truncate_path};
 use dir::{Dir, dirs};
 use unit::{Unit, UnitTypeId};
 use db::{Db};
@@ -33,11 +33,11 @@ pub struct Ai {
 impl Ai {
     pub fn new(db: Rc<Db>, options: &Options, id: PlayerId) -> Ai {
         let state = State::new_partial(db.clone(), options, id);
-        let map_size = state.map().size();
+        let pathfinder = Pathfinder::new(db.clone(), state.map());
         Ai {
             id: id,
             state: state,
-            pathfinder: Pathfinder::new(db.clone(), map_size),
+            pathfinder: pathfinder,
             db: db,
         }
     }
@@ -74,6 +74,9 @@ impl Ai {
                 if unit.pos.map_pos == pos {
                     return None;
                 }
+                if !self.state.map().is_inboard(pos) {
+                    continue;
+                }
                 if let Some((cost, pos)) = self.estimate_path(unit, pos) {
                     if best_cost.n > cost.n {
                         best_cost = cost;
@@ -152,7 +155,21 @@ impl Ai {
         None
     }
 
+    fn are_enemies_known(&self) -> bool {
+        self.state.units().any(|(_, unit)| {
+            unit.player_id != self.id && unit.is_alive
+        })
+    }
+
     pub fn try_get_move_command(&mut self) -> Option<Command> {
+        // don't walk into known kill zones, but don't pay
+        // for the danger map when there's nothing to avoid
+        let mode = if self.are_enemies_known() {
+            PathMode::Safe
+        } else {
+            PathMode::Fastest
+        };
+        self.pathfinder.set_mode(mode);
         for (_, unit) in self.state.units() {
             if unit.player_id != self.id {
                 continue;
@@ -254,3 +271,25 @@ impl Ai {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::{Rc};
+    use db::{Db};
+    use pathfinder::{PathMode};
+    use test_utils::{options, add_unit, map_pos};
+    use ::{PlayerId};
+    use super::{Ai};
+
+    #[test]
+    fn test_danger_is_avoided_only_with_known_enemies() {
+        let db = Rc::new(Db::new());
+        let mut ai = Ai::new(db.clone(), &options(), PlayerId{id: 1});
+        add_unit(&db, &mut ai.state, 1, "soldier", map_pos(9, 5));
+        ai.try_get_move_command();
+        assert_eq!(ai.pathfinder.mode(), PathMode::Fastest);
+        add_unit(&db, &mut ai.state, 0, "soldier", map_pos(2, 5));
+        ai.try_get_move_command();
+        assert_eq!(ai.pathfinder.mode(), PathMode::Safe);
+    }
+}
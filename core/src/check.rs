@@ -1,17 +1,25 @@
 use std::{fmt, error};
 use game_state::{State};
-use map::{distance};
-use pathfinder::{path_cost, tile_cost};
+use map::{Terrain, distance};
+use pathfinder::{path_cost, tile_cost, is_towing_blocked};
 use unit::{Unit};
 use db::{Db};
-use fov::{fov, simple_fov};
+use fov::{has_los};
 use ::{
     Command,
     FireMode,
     PlayerId,
     ObjectClass,
+    ExactPos,
+    ZocRule,
     is_exact_pos_free,
+    is_in_enemy_zoc,
+    is_loaded_or_attached,
+    is_abandoned,
+    CREW_COUNT,
     move_cost_modifier,
+    los_range,
+    structure_damage,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -49,6 +57,19 @@ pub enum CommandError {
     BadAttachedUnitType,
     NoAttachedUnit,
     TooManyAttachedUnits,
+    AlreadyEntrenched,
+    BadSquadSize,
+    UnitIsTooFarAway,
+    UnitIsImmobilized,
+    WeaponIsDisabled,
+    CrewIsShaken,
+    UnitIsNotAbandoned,
+    BadObjectId,
+    BadTerrain,
+    NoMoveOrder,
+    ZoneOfControl,
+    WeaponIsTooWeak,
+    TowingIsBlocked,
 }
 
 impl CommandError {
@@ -87,6 +108,19 @@ impl CommandError {
             CommandError::BadAttachedUnitType => "Bad attached unit type",
             CommandError::NoAttachedUnit => "No attached unit",
             CommandError::TooManyAttachedUnits => "too many attached units",
+            CommandError::AlreadyEntrenched => "Unit is already entrenched",
+            CommandError::BadSquadSize => "Bad squad size",
+            CommandError::UnitIsTooFarAway => "Unit is too far away",
+            CommandError::UnitIsImmobilized => "Unit is immobilized",
+            CommandError::WeaponIsDisabled => "Weapon is disabled",
+            CommandError::CrewIsShaken => "Crew is shaken",
+            CommandError::UnitIsNotAbandoned => "Unit is not abandoned",
+            CommandError::BadObjectId => "Bad object id",
+            CommandError::BadTerrain => "Bad terrain",
+            CommandError::NoMoveOrder => "No move order",
+            CommandError::ZoneOfControl => "Zone of control",
+            CommandError::WeaponIsTooWeak => "Weapon is too weak",
+            CommandError::TowingIsBlocked => "Towed unit can't pass there off-road",
         }
     }
 }
@@ -103,6 +137,84 @@ impl error::Error for CommandError {
     }
 }
 
+fn check_squad(
+    db: &Db,
+    player_id: PlayerId,
+    unit: &Unit,
+) -> Result<(), CommandError> {
+    if !unit.is_alive {
+        return Err(CommandError::UnitIsDead);
+    }
+    if unit.player_id != player_id {
+        return Err(CommandError::CanNotCommandEnemyUnits);
+    }
+    if is_loaded_or_attached(unit) {
+        return Err(CommandError::BadUnitId);
+    }
+    let unit_type = db.unit_type(unit.type_id);
+    if !unit_type.is_infantry || unit_type.can_be_towed {
+        return Err(CommandError::BadUnitType);
+    }
+    Ok(())
+}
+
+fn check_zoc(
+    db: &Db,
+    state: &State,
+    unit: &Unit,
+    path: &[ExactPos],
+) -> Result<(), CommandError> {
+    let unit_type = db.unit_type(unit.type_id);
+    if state.zoc_rule() == ZocRule::Disabled || unit_type.is_air {
+        return Ok(());
+    }
+    let last_index = path.len() - 1;
+    for (i, window) in path.windows(2).enumerate() {
+        let is_from_zoc = is_in_enemy_zoc(
+            db, state, unit.player_id, window[0].map_pos);
+        let is_to_zoc = is_in_enemy_zoc(
+            db, state, unit.player_id, window[1].map_pos);
+        if is_from_zoc && is_to_zoc {
+            return Err(CommandError::ZoneOfControl);
+        }
+        let is_last_step = i + 1 == last_index;
+        if state.zoc_rule() == ZocRule::Stop && is_to_zoc && !is_last_step {
+            return Err(CommandError::ZoneOfControl);
+        }
+    }
+    Ok(())
+}
+
+fn check_engineer(
+    db: &Db,
+    player_id: PlayerId,
+    unit: &Unit,
+) -> Result<(), CommandError> {
+    if !unit.is_alive {
+        return Err(CommandError::UnitIsDead);
+    }
+    if unit.player_id != player_id {
+        return Err(CommandError::CanNotCommandEnemyUnits);
+    }
+    if is_loaded_or_attached(unit) {
+        return Err(CommandError::BadUnitId);
+    }
+    let unit_type = db.unit_type(unit.type_id);
+    if !unit_type.is_engineer {
+        return Err(CommandError::BadUnitType);
+    }
+    // engineering work takes the whole turn
+    let move_points = unit.move_points.unwrap();
+    if move_points.n != unit_type.move_points.n {
+        return Err(CommandError::NotEnoughMovePoints);
+    }
+    let attack_points = unit.attack_points.unwrap();
+    if attack_points.n != unit_type.attack_points.n {
+        return Err(CommandError::NotEnoughAttackPoints);
+    }
+    Ok(())
+}
+
 pub fn check_command(
     db: &Db,
     player_id: PlayerId,
@@ -144,6 +256,9 @@ pub fn check_command(
             if unit.player_id != player_id {
                 return Err(CommandError::CanNotCommandEnemyUnits);
             }
+            if unit.is_immobilized {
+                return Err(CommandError::UnitIsImmobilized);
+            }
             if path.len() < 2 {
                 return Err(CommandError::BadPath);
             }
@@ -152,6 +267,12 @@ pub fn check_command(
                 if !is_exact_pos_free(db, state, unit.type_id, pos) {
                     return Err(CommandError::BadPath);
                 }
+                if is_towing_blocked(db, state, unit, window[0], pos) {
+                    return Err(CommandError::TowingIsBlocked);
+                }
+            }
+            if let Err(err) = check_zoc(db, state, unit, path) {
+                return Err(err);
             }
             let cost = path_cost(db, state, unit, path).n
                 * move_cost_modifier(mode);
@@ -161,6 +282,36 @@ pub fn check_command(
             }
             Ok(())
         },
+        Command::GroupMove{ref unit_ids, destination, ..} => {
+            if unit_ids.is_empty() {
+                return Err(CommandError::BadUnitId);
+            }
+            if !state.map().is_inboard(destination) {
+                return Err(CommandError::BadPath);
+            }
+            for (index, &unit_id) in unit_ids.iter().enumerate() {
+                if unit_ids[..index].contains(&unit_id) {
+                    return Err(CommandError::BadUnitId);
+                }
+                let unit = match state.unit_opt(unit_id) {
+                    Some(unit) => unit,
+                    None => return Err(CommandError::BadUnitId),
+                };
+                if !unit.is_alive {
+                    return Err(CommandError::UnitIsDead);
+                }
+                if unit.player_id != player_id {
+                    return Err(CommandError::CanNotCommandEnemyUnits);
+                }
+                if is_loaded_or_attached(unit) {
+                    return Err(CommandError::BadUnitId);
+                }
+                if unit.is_immobilized {
+                    return Err(CommandError::UnitIsImmobilized);
+                }
+            }
+            Ok(())
+        },
         Command::AttackUnit{attacker_id, defender_id} => {
             let attacker = match state.unit_opt(attacker_id) {
                 Some(attacker) => attacker,
@@ -181,6 +332,26 @@ pub fn check_command(
             }
             check_attack(db, state, attacker, defender, FireMode::Active)
         },
+        Command::CloseAssault{attacker_id, defender_id} => {
+            let attacker = match state.unit_opt(attacker_id) {
+                Some(attacker) => attacker,
+                None => return Err(CommandError::BadAttackerId),
+            };
+            let defender = match state.unit_opt(defender_id) {
+                Some(defender) => defender,
+                None => return Err(CommandError::BadDefenderId),
+            };
+            if !attacker.is_alive {
+                return Err(CommandError::UnitIsDead);
+            }
+            if attacker.player_id != player_id {
+                return Err(CommandError::CanNotCommandEnemyUnits);
+            }
+            if !defender.is_alive {
+                return Err(CommandError::UnitIsDead);
+            }
+            check_attack(db, state, attacker, defender, FireMode::CloseAssault)
+        },
         Command::LoadUnit{transporter_id, passenger_id} => {
             let passenger = match state.unit_opt(passenger_id) {
                 Some(passenger) => passenger,
@@ -275,6 +446,9 @@ pub fn check_command(
             if transporter_type.is_infantry || transporter_type.is_air {
                 return Err(CommandError::BadTransporterType);
             }
+            if transporter.is_immobilized {
+                return Err(CommandError::UnitIsImmobilized);
+            }
             if transporter.attached_unit_id.is_some() {
                 return Err(CommandError::TooManyAttachedUnits);
             }
@@ -373,6 +547,12 @@ pub fn check_command(
             if !weapon_type.smoke.is_some() {
                 return Err(CommandError::BadUnitType);
             }
+            if unit.is_weapon_disabled {
+                return Err(CommandError::WeaponIsDisabled);
+            }
+            if !state.map().is_inboard(pos) {
+                return Err(CommandError::OutOfRange);
+            }
             if distance(unit.pos.map_pos, pos) > weapon_type.max_distance {
                 return Err(CommandError::OutOfRange);
             }
@@ -382,22 +562,260 @@ pub fn check_command(
             }
             Ok(())
         },
+        Command::Entrench{unit_id} => {
+            let unit = match state.unit_opt(unit_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            if let Err(err) = check_squad(db, player_id, unit) {
+                return Err(err);
+            }
+            let unit_type = db.unit_type(unit.type_id);
+            if unit.is_entrenched {
+                return Err(CommandError::AlreadyEntrenched);
+            }
+            // digging in takes the whole turn
+            let move_points = unit.move_points.unwrap();
+            if move_points.n != unit_type.move_points.n {
+                return Err(CommandError::NotEnoughMovePoints);
+            }
+            let attack_points = unit.attack_points.unwrap();
+            if attack_points.n != unit_type.attack_points.n {
+                return Err(CommandError::NotEnoughAttackPoints);
+            }
+            Ok(())
+        },
+        Command::Demolish{unit_id, object_id} => {
+            let unit = match state.unit_opt(unit_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            if let Err(err) = check_engineer(db, player_id, unit) {
+                return Err(err);
+            }
+            let object = match state.objects().get(&object_id) {
+                Some(object) => object,
+                None => return Err(CommandError::BadObjectId),
+            };
+            if object.class != ObjectClass::Bridge {
+                return Err(CommandError::BadObjectId);
+            }
+            if distance(unit.pos.map_pos, object.pos.map_pos).n > 1 {
+                return Err(CommandError::OutOfRange);
+            }
+            Ok(())
+        },
+        Command::AttackObject{attacker_id, object_id} => {
+            let attacker = match state.unit_opt(attacker_id) {
+                Some(attacker) => attacker,
+                None => return Err(CommandError::BadAttackerId),
+            };
+            if attacker.player_id != player_id {
+                return Err(CommandError::CanNotCommandEnemyUnits);
+            }
+            if is_loaded_or_attached(attacker) {
+                return Err(CommandError::BadAttackerId);
+            }
+            let object = match state.objects().get(&object_id) {
+                Some(object) => object,
+                None => return Err(CommandError::BadObjectId),
+            };
+            // only buildings and bridges have structural points
+            if object.structural_points.is_none() {
+                return Err(CommandError::BadObjectId);
+            }
+            if let Err(err) = check_attacker(attacker, FireMode::Active) {
+                return Err(err);
+            }
+            let attacker_type = db.unit_type(attacker.type_id);
+            let weapon_type = db.weapon_type(attacker_type.weapon_type_id);
+            if structure_damage(weapon_type) <= 0 {
+                return Err(CommandError::WeaponIsTooWeak);
+            }
+            let from = attacker.pos.map_pos;
+            let to = object.pos.map_pos;
+            let distance = distance(from, to);
+            if distance > weapon_type.max_distance {
+                return Err(CommandError::OutOfRange);
+            }
+            if distance < weapon_type.min_distance {
+                return Err(CommandError::TooClose);
+            }
+            let is_los_ok = distance <= los_range(state, attacker_type)
+                && (attacker_type.is_air || has_los(state, from, to));
+            if !weapon_type.is_inderect && !is_los_ok {
+                return Err(CommandError::NoLos);
+            }
+            Ok(())
+        },
+        Command::BuildBridge{unit_id, pos} => {
+            let unit = match state.unit_opt(unit_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            if let Err(err) = check_engineer(db, player_id, unit) {
+                return Err(err);
+            }
+            if !state.map().is_inboard(pos) {
+                return Err(CommandError::OutOfRange);
+            }
+            if distance(unit.pos.map_pos, pos).n > 1 {
+                return Err(CommandError::OutOfRange);
+            }
+            if *state.map().tile(pos) != Terrain::Water {
+                return Err(CommandError::BadTerrain);
+            }
+            for object in state.objects_at(pos) {
+                if object.class == ObjectClass::Bridge {
+                    return Err(CommandError::TileIsOccupied);
+                }
+            }
+            Ok(())
+        },
+        Command::Split{unit_id, count, pos} => {
+            let unit = match state.unit_opt(unit_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            if let Err(err) = check_squad(db, player_id, unit) {
+                return Err(err);
+            }
+            if count <= 0 || count >= unit.count {
+                return Err(CommandError::BadSquadSize);
+            }
+            if distance(unit.pos.map_pos, pos.map_pos).n > 1 {
+                return Err(CommandError::OutOfRange);
+            }
+            if !is_exact_pos_free(db, state, unit.type_id, pos) {
+                return Err(CommandError::DestinationTileIsNotEmpty);
+            }
+            if unit.pos.map_pos != pos.map_pos {
+                let cost = tile_cost(db, state, unit, unit.pos, pos);
+                if cost.n > unit.move_points.unwrap().n {
+                    return Err(CommandError::NotEnoughMovePoints);
+                }
+            }
+            Ok(())
+        },
+        Command::Merge{unit_id, into_id} => {
+            let unit = match state.unit_opt(unit_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            let into = match state.unit_opt(into_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            if unit_id == into_id {
+                return Err(CommandError::BadUnitId);
+            }
+            if let Err(err) = check_squad(db, player_id, unit) {
+                return Err(err);
+            }
+            if let Err(err) = check_squad(db, player_id, into) {
+                return Err(err);
+            }
+            if unit.type_id != into.type_id {
+                return Err(CommandError::BadUnitType);
+            }
+            let unit_type = db.unit_type(unit.type_id);
+            if unit.count + into.count > unit_type.count {
+                return Err(CommandError::BadSquadSize);
+            }
+            if distance(unit.pos.map_pos, into.pos.map_pos).n > 1 {
+                return Err(CommandError::UnitIsTooFarAway);
+            }
+            if unit.pos.map_pos != into.pos.map_pos {
+                let cost = tile_cost(db, state, unit, unit.pos, into.pos);
+                if cost.n > unit.move_points.unwrap().n {
+                    return Err(CommandError::NotEnoughMovePoints);
+                }
+            }
+            Ok(())
+        },
+        Command::Capture{unit_id, target_id} => {
+            let unit = match state.unit_opt(unit_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            let target = match state.unit_opt(target_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            if let Err(err) = check_squad(db, player_id, unit) {
+                return Err(err);
+            }
+            if !is_abandoned(target) || is_loaded_or_attached(target) {
+                return Err(CommandError::UnitIsNotAbandoned);
+            }
+            let target_type = db.unit_type(target.type_id);
+            if target_type.is_infantry && !target_type.can_be_towed {
+                return Err(CommandError::BadUnitType);
+            }
+            if unit.count < CREW_COUNT {
+                return Err(CommandError::BadSquadSize);
+            }
+            if distance(unit.pos.map_pos, target.pos.map_pos).n > 1 {
+                return Err(CommandError::UnitIsTooFarAway);
+            }
+            if unit.pos.map_pos != target.pos.map_pos {
+                let cost = tile_cost(db, state, unit, unit.pos, target.pos);
+                if cost.n > unit.move_points.unwrap().n {
+                    return Err(CommandError::NotEnoughMovePoints);
+                }
+            }
+            Ok(())
+        },
+        Command::SetMoveOrder{unit_id, ref order} => {
+            let unit = match state.unit_opt(unit_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            if !unit.is_alive {
+                return Err(CommandError::UnitIsDead);
+            }
+            if unit.player_id != player_id {
+                return Err(CommandError::CanNotCommandEnemyUnits);
+            }
+            if is_loaded_or_attached(unit) {
+                return Err(CommandError::BadUnitId);
+            }
+            if unit.is_immobilized {
+                return Err(CommandError::UnitIsImmobilized);
+            }
+            if order.waypoints.is_empty() {
+                return Err(CommandError::BadPath);
+            }
+            for &pos in &order.waypoints {
+                if !state.map().is_inboard(pos) {
+                    return Err(CommandError::BadPath);
+                }
+            }
+            Ok(())
+        },
+        Command::CancelMoveOrder{unit_id} => {
+            let unit = match state.unit_opt(unit_id) {
+                Some(unit) => unit,
+                None => return Err(CommandError::BadUnitId),
+            };
+            if unit.player_id != player_id {
+                return Err(CommandError::CanNotCommandEnemyUnits);
+            }
+            if state.move_orders().get(&unit_id).is_none() {
+                return Err(CommandError::NoMoveOrder);
+            }
+            Ok(())
+        },
     }
 }
 
-pub fn check_attack(
-    db: &Db,
-    state: &State,
+fn check_attacker(
     attacker: &Unit,
-    defender: &Unit,
     fire_mode: FireMode,
 ) -> Result<(), CommandError> {
     if !attacker.is_alive {
         return Err(CommandError::UnitIsDead);
     }
-    if !defender.is_alive {
-        return Err(CommandError::UnitIsDead);
-    }
     let attack_points = attacker.attack_points.unwrap();
     let reactive_attack_points = attacker.reactive_attack_points.unwrap();
     match fire_mode {
@@ -407,14 +825,52 @@ pub fn check_attack(
         FireMode::Reactive => if reactive_attack_points.n <= 0 {
             return Err(CommandError::NotEnoughReactiveAttackPoints);
         },
+        FireMode::CloseAssault => {
+            if attack_points.n <= 0 {
+                return Err(CommandError::NotEnoughAttackPoints);
+            }
+            if attacker.move_points.unwrap().n <= 0 {
+                return Err(CommandError::NotEnoughMovePoints);
+            }
+        },
     }
     let minimal_ok_morale = 50;
     if attacker.morale < minimal_ok_morale {
         return Err(CommandError::BadMorale);
     }
+    if attacker.is_crew_shaken {
+        return Err(CommandError::CrewIsShaken);
+    }
+    if attacker.is_weapon_disabled && fire_mode != FireMode::CloseAssault {
+        return Err(CommandError::WeaponIsDisabled);
+    }
+    Ok(())
+}
+
+pub fn check_attack(
+    db: &Db,
+    state: &State,
+    attacker: &Unit,
+    defender: &Unit,
+    fire_mode: FireMode,
+) -> Result<(), CommandError> {
+    if let Err(err) = check_attacker(attacker, fire_mode) {
+        return Err(err);
+    }
+    if !defender.is_alive {
+        return Err(CommandError::UnitIsDead);
+    }
     let attacker_type = db.unit_type(attacker.type_id);
     let defender_type = db.unit_type(defender.type_id);
-    let weapon_type = db.weapon_type(attacker_type.weapon_type_id);
+    let weapon_type_id = if fire_mode == FireMode::CloseAssault {
+        match attacker_type.close_assault_weapon_type_id {
+            Some(id) => id,
+            None => return Err(CommandError::BadUnitType),
+        }
+    } else {
+        attacker_type.weapon_type_id
+    };
+    let weapon_type = db.weapon_type(weapon_type_id);
     let distance =  distance(attacker.pos.map_pos, defender.pos.map_pos);
     if defender_type.is_air {
         if let Some(max_air_distance) = weapon_type.max_air_distance {
@@ -439,7 +895,6 @@ pub fn check_attack(
     Ok(())
 }
 
-// TODO: profile and optimize!
 fn los(
     db: &Db,
     state: &State,
@@ -450,13 +905,426 @@ fn los(
     let defender_type = db.unit_type(defender.type_id);
     let from = attacker.pos.map_pos;
     let to = defender.pos.map_pos;
-    let range = attacker_type.los_range;
-    let mut v = false;
-    let f = if attacker_type.is_air || defender_type.is_air {
-        simple_fov
-    } else {
-        fov
+    let range = los_range(state, attacker_type);
+    if distance(from, to) > range {
+        return false;
+    }
+    attacker_type.is_air || defender_type.is_air || has_los(state, from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_utils::{
+        options,
+        new_state,
+        add_unit,
+        add_object,
+        big_building,
+        map_pos,
+        map01_options,
+        to_partial,
     };
-    f(state, from, range, &mut |p| if p == to { v = true });
-    v
+    use ::{
+        CoreEvent,
+        Command,
+        PlayerId,
+        ExactPos,
+        SlotId,
+        AttackInfo,
+        FireMode,
+        MoveMode,
+        VehicleDamage,
+        ObjectClass,
+        Options,
+        ZocRule,
+        get_free_exact_pos,
+    };
+    use dir::{Dir};
+    use pathfinder::{Pathfinder, path_cost};
+    use super::{check_command, check_zoc, CommandError};
+
+    #[test]
+    fn test_entrench() {
+        let (db, mut state) = new_state(&options());
+        let player_id = PlayerId{id: 0};
+        let soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 5));
+        let tank_id = add_unit(&db, &mut state, 0, "medium_tank", map_pos(6, 5));
+        let gun_id = add_unit(&db, &mut state, 0, "field_gun", map_pos(7, 5));
+        to_partial(&db, &mut state, 0);
+        let entrench = |id| Command::Entrench{unit_id: id};
+        assert_eq!(check_command(&db, player_id, &state, &entrench(soldier_id)), Ok(()));
+        assert_eq!(
+            check_command(&db, player_id, &state, &entrench(tank_id)),
+            Err(CommandError::BadUnitType),
+        );
+        assert_eq!(
+            check_command(&db, player_id, &state, &entrench(gun_id)),
+            Err(CommandError::BadUnitType),
+        );
+        state.apply_event(&CoreEvent::Entrench{unit_id: soldier_id});
+        assert_eq!(
+            check_command(&db, player_id, &state, &entrench(soldier_id)),
+            Err(CommandError::AlreadyEntrenched),
+        );
+    }
+
+    #[test]
+    fn test_split_and_merge_sizes() {
+        let (db, mut state) = new_state(&options());
+        let player_id = PlayerId{id: 0};
+        let soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 5));
+        let other_soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(6, 5));
+        let smg_id = add_unit(&db, &mut state, 0, "smg", map_pos(5, 6));
+        to_partial(&db, &mut state, 0);
+        let pos = ExactPos{map_pos: map_pos(4, 5), slot_id: SlotId::Id(0)};
+        for &(count, result) in &[
+            (0, Err(CommandError::BadSquadSize)),
+            (1, Ok(())),
+            (3, Ok(())),
+            (4, Err(CommandError::BadSquadSize)),
+        ] {
+            let command = Command::Split{unit_id: soldier_id, count: count, pos: pos};
+            assert_eq!(check_command(&db, player_id, &state, &command), result);
+        }
+        let far_pos = ExactPos{map_pos: map_pos(8, 5), slot_id: SlotId::Id(0)};
+        let command = Command::Split{unit_id: soldier_id, count: 1, pos: far_pos};
+        assert_eq!(
+            check_command(&db, player_id, &state, &command),
+            Err(CommandError::OutOfRange),
+        );
+        // both squads are full
+        let command = Command::Merge{unit_id: soldier_id, into_id: other_soldier_id};
+        assert_eq!(
+            check_command(&db, player_id, &state, &command),
+            Err(CommandError::BadSquadSize),
+        );
+        let command = Command::Merge{unit_id: smg_id, into_id: soldier_id};
+        assert_eq!(
+            check_command(&db, player_id, &state, &command),
+            Err(CommandError::BadUnitType),
+        );
+    }
+
+    #[test]
+    fn test_close_assault() {
+        let (db, mut state) = new_state(&options());
+        let player_id = PlayerId{id: 0};
+        let soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 5));
+        let tank_id = add_unit(&db, &mut state, 0, "medium_tank", map_pos(5, 6));
+        let near_enemy_id = add_unit(&db, &mut state, 1, "medium_tank", map_pos(6, 5));
+        let far_enemy_id = add_unit(&db, &mut state, 1, "medium_tank", map_pos(3, 5));
+        to_partial(&db, &mut state, 0);
+        let assault = |attacker_id, defender_id| Command::CloseAssault {
+            attacker_id: attacker_id,
+            defender_id: defender_id,
+        };
+        assert_eq!(
+            check_command(&db, player_id, &state, &assault(soldier_id, near_enemy_id)),
+            Ok(()),
+        );
+        assert_eq!(
+            check_command(&db, player_id, &state, &assault(soldier_id, far_enemy_id)),
+            Err(CommandError::OutOfRange),
+        );
+        // vehicles have no close combat weapons
+        assert_eq!(
+            check_command(&db, player_id, &state, &assault(tank_id, near_enemy_id)),
+            Err(CommandError::BadUnitType),
+        );
+    }
+
+    #[test]
+    fn test_damaged_vehicle() {
+        let (db, mut state) = new_state(&options());
+        let player_id = PlayerId{id: 0};
+        let tank_id = add_unit(&db, &mut state, 0, "medium_tank", map_pos(5, 5));
+        let enemy_id = add_unit(&db, &mut state, 1, "medium_tank", map_pos(7, 5));
+        for &damage in &[VehicleDamage::Immobilized, VehicleDamage::WeaponDisabled] {
+            state.apply_event(&CoreEvent::AttackUnit {
+                attack_info: AttackInfo {
+                    attacker_id: None,
+                    defender_id: tank_id,
+                    mode: FireMode::Active,
+                    killed: 0,
+                    suppression: 0,
+                    remove_move_points: false,
+                    is_ambush: false,
+                    is_inderect: false,
+                    leave_wrecks: true,
+                    attacker_suppression: 0,
+                    damage: Some(damage),
+                },
+            });
+        }
+        to_partial(&db, &mut state, 0);
+        let from = state.unit(tank_id).pos;
+        let to = ExactPos{map_pos: map_pos(4, 5), slot_id: from.slot_id};
+        let command = Command::Move {
+            unit_id: tank_id,
+            path: vec![from, to],
+            mode: MoveMode::Fast,
+        };
+        assert_eq!(
+            check_command(&db, player_id, &state, &command),
+            Err(CommandError::UnitIsImmobilized),
+        );
+        let command = Command::AttackUnit{attacker_id: tank_id, defender_id: enemy_id};
+        assert_eq!(
+            check_command(&db, player_id, &state, &command),
+            Err(CommandError::WeaponIsDisabled),
+        );
+    }
+
+    #[test]
+    fn test_capture() {
+        let (db, mut state) = new_state(&options());
+        let player_id = PlayerId{id: 0};
+        let soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 5));
+        let tank_id = add_unit(&db, &mut state, 1, "medium_tank", map_pos(6, 5));
+        let gun_id = add_unit(&db, &mut state, 1, "field_gun", map_pos(5, 6));
+        to_partial(&db, &mut state, 0);
+        let capture = |target_id| Command::Capture {
+            unit_id: soldier_id,
+            target_id: target_id,
+        };
+        assert_eq!(
+            check_command(&db, player_id, &state, &capture(tank_id)),
+            Err(CommandError::UnitIsNotAbandoned),
+        );
+        state.apply_event(&CoreEvent::AttackUnit {
+            attack_info: AttackInfo {
+                attacker_id: None,
+                defender_id: gun_id,
+                mode: FireMode::Active,
+                killed: 0,
+                suppression: 0,
+                remove_move_points: false,
+                is_ambush: false,
+                is_inderect: false,
+                leave_wrecks: true,
+                attacker_suppression: 0,
+                damage: Some(VehicleDamage::Abandoned),
+            },
+        });
+        assert_eq!(check_command(&db, player_id, &state, &capture(gun_id)), Ok(()));
+    }
+
+    #[test]
+    fn test_attack_object() {
+        let (db, mut state) = new_state(&options());
+        let player_id = PlayerId{id: 0};
+        let tank_id = add_unit(&db, &mut state, 0, "medium_tank", map_pos(3, 5));
+        let soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(4, 5));
+        let building_id = add_object(&mut state, big_building(map_pos(6, 5)));
+        to_partial(&db, &mut state, 0);
+        let attack = |attacker_id| Command::AttackObject {
+            attacker_id: attacker_id,
+            object_id: building_id,
+        };
+        assert_eq!(check_command(&db, player_id, &state, &attack(tank_id)), Ok(()));
+        assert_eq!(
+            check_command(&db, player_id, &state, &attack(soldier_id)),
+            Err(CommandError::WeaponIsTooWeak),
+        );
+    }
+
+    #[test]
+    fn test_engineering() {
+        let (db, mut state) = new_state(&map01_options());
+        let player_id = PlayerId{id: 0};
+        let engineer_id = add_unit(&db, &mut state, 0, "engineer", map_pos(5, 7));
+        let soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(6, 6));
+        to_partial(&db, &mut state, 0);
+        let build = |unit_id, pos| Command::BuildBridge {
+            unit_id: unit_id,
+            pos: pos,
+        };
+        assert_eq!(
+            check_command(&db, player_id, &state, &build(engineer_id, map_pos(6, 7))),
+            Ok(()),
+        );
+        assert_eq!(
+            check_command(&db, player_id, &state, &build(soldier_id, map_pos(6, 7))),
+            Err(CommandError::BadUnitType),
+        );
+        assert_eq!(
+            check_command(&db, player_id, &state, &build(engineer_id, map_pos(4, 7))),
+            Err(CommandError::BadTerrain),
+        );
+        assert_eq!(
+            check_command(&db, player_id, &state, &build(engineer_id, map_pos(5, 8))),
+            Err(CommandError::TileIsOccupied),
+        );
+        let object_id = |class| *state.objects().iter()
+            .find(|&(_, object)| object.class == class
+                && object.pos.map_pos == map_pos(5, 8))
+            .unwrap().0;
+        let demolish = |object_id| Command::Demolish {
+            unit_id: engineer_id,
+            object_id: object_id,
+        };
+        assert_eq!(
+            check_command(&db, player_id, &state, &demolish(object_id(ObjectClass::Bridge))),
+            Ok(()),
+        );
+        assert_eq!(
+            check_command(&db, player_id, &state, &demolish(object_id(ObjectClass::Road))),
+            Err(CommandError::BadObjectId),
+        );
+    }
+
+    #[test]
+    fn test_zoc() {
+        let enemy_pos = map_pos(6, 5);
+        let zoc_pos = Dir::get_neighbour_pos(enemy_pos, Dir::West);
+        let other_zoc_pos = Dir::get_neighbour_pos(enemy_pos, Dir::NorthWest);
+        let before_pos = Dir::get_neighbour_pos(zoc_pos, Dir::West);
+        let after_pos = Dir::get_neighbour_pos(zoc_pos, Dir::SouthWest);
+        let exact = |pos| ExactPos{map_pos: pos, slot_id: SlotId::WholeTile};
+        let entering = [exact(before_pos), exact(zoc_pos)];
+        let passing = [exact(before_pos), exact(zoc_pos), exact(after_pos)];
+        let slipping = [exact(zoc_pos), exact(other_zoc_pos)];
+        let zoc_error = Err(CommandError::ZoneOfControl);
+        for &rule in &[ZocRule::Disabled, ZocRule::Tax, ZocRule::Stop] {
+            let options = Options{zoc_rule: rule, .. options()};
+            let (db, mut state) = new_state(&options);
+            let id = add_unit(&db, &mut state, 0, "soldier", before_pos);
+            add_unit(&db, &mut state, 1, "soldier", enemy_pos);
+            to_partial(&db, &mut state, 0);
+            let unit = state.unit(id);
+            let check = |path: &[ExactPos]| check_zoc(&db, &state, unit, path);
+            assert_eq!(check(&entering), Ok(()));
+            match rule {
+                ZocRule::Disabled => {
+                    assert_eq!(check(&passing), Ok(()));
+                    assert_eq!(check(&slipping), Ok(()));
+                },
+                ZocRule::Tax => {
+                    assert_eq!(check(&passing), Ok(()));
+                    assert_eq!(check(&slipping), zoc_error);
+                },
+                ZocRule::Stop => {
+                    assert_eq!(check(&passing), zoc_error);
+                    assert_eq!(check(&slipping), zoc_error);
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn test_towing_in_trees() {
+        let (db, mut state) = new_state(&map01_options());
+        let player_id = PlayerId{id: 0};
+        // (4, 4) and (4, 5) are forests
+        let truck_id = add_unit(&db, &mut state, 0, "truck", map_pos(3, 5));
+        let towing_truck_id = add_unit(&db, &mut state, 0, "truck", map_pos(2, 4));
+        let gun_id = add_unit(&db, &mut state, 0, "field_gun", map_pos(3, 4));
+        state.apply_event(&CoreEvent::Attach {
+            transporter_id: towing_truck_id,
+            attached_unit_id: gun_id,
+            from: state.unit(towing_truck_id).pos,
+            to: state.unit(gun_id).pos,
+        });
+        state.apply_event(&CoreEvent::EndTurn {
+            old_id: PlayerId{id: 1},
+            new_id: player_id,
+        });
+        to_partial(&db, &mut state, 0);
+        let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+        for &(unit_id, pos, is_ok) in &[
+            (truck_id, map_pos(4, 5), true),
+            (towing_truck_id, map_pos(4, 4), false),
+        ] {
+            let unit = state.unit(unit_id);
+            let to = get_free_exact_pos(&db, &state, unit.type_id, pos).unwrap();
+            let path = vec![unit.pos, to];
+            pathfinder.fill_map(&state, unit);
+            let command = Command::Move {
+                unit_id: unit_id,
+                path: path.clone(),
+                mode: MoveMode::Fast,
+            };
+            let result = check_command(&db, player_id, &state, &command);
+            if is_ok {
+                let cost = pathfinder.get_map().tile(pos).cost();
+                assert_eq!(path_cost(&db, &state, unit, &path), cost);
+                assert_eq!(result, Ok(()));
+            } else {
+                assert_eq!(pathfinder.get_path(to), None);
+                assert_eq!(result, Err(CommandError::TowingIsBlocked));
+            }
+        }
+    }
+
+    #[test]
+    fn test_towing_route_agrees_with_check() {
+        let (db, mut state) = new_state(&map01_options());
+        let player_id = PlayerId{id: 0};
+        let truck_id = add_unit(&db, &mut state, 0, "truck", map_pos(2, 4));
+        let gun_id = add_unit(&db, &mut state, 0, "field_gun", map_pos(3, 4));
+        state.apply_event(&CoreEvent::Attach {
+            transporter_id: truck_id,
+            attached_unit_id: gun_id,
+            from: state.unit(truck_id).pos,
+            to: state.unit(gun_id).pos,
+        });
+        state.apply_event(&CoreEvent::EndTurn {
+            old_id: PlayerId{id: 1},
+            new_id: player_id,
+        });
+        to_partial(&db, &mut state, 0);
+        let unit = state.unit(truck_id);
+        let move_points = unit.move_points.unwrap();
+        let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+        pathfinder.fill_map(&state, unit);
+        let mut routes_count = 0;
+        for pos in state.map().get_iter() {
+            let tile = pathfinder.get_map().tile(pos);
+            let destination = ExactPos{map_pos: pos, slot_id: tile.slot_id()};
+            let path = match pathfinder.get_path(destination) {
+                Some(ref path) if path.len() > 1 => path.clone(),
+                _ => continue,
+            };
+            assert_eq!(path_cost(&db, &state, unit, &path), tile.cost());
+            let command = Command::Move {
+                unit_id: truck_id,
+                path: path,
+                mode: MoveMode::Fast,
+            };
+            let result = check_command(&db, player_id, &state, &command);
+            if tile.cost().n > move_points.n {
+                assert_eq!(result, Err(CommandError::NotEnoughMovePoints));
+            } else {
+                assert_eq!(result, Ok(()));
+                routes_count += 1;
+            }
+        }
+        assert!(routes_count > 0);
+    }
+
+    #[test]
+    fn test_group_move() {
+        let (db, mut state) = new_state(&options());
+        let player_id = PlayerId{id: 0};
+        let soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(2, 5));
+        let tank_id = add_unit(&db, &mut state, 0, "medium_tank", map_pos(2, 6));
+        let enemy_id = add_unit(&db, &mut state, 1, "soldier", map_pos(4, 5));
+        to_partial(&db, &mut state, 0);
+        let group_move = |unit_ids: &[_], destination| Command::GroupMove {
+            unit_ids: unit_ids.to_vec(),
+            destination: destination,
+            mode: MoveMode::Fast,
+        };
+        let pos = map_pos(5, 7);
+        for &(ref unit_ids, destination, result) in &[
+            (vec![soldier_id, tank_id], pos, Ok(())),
+            (vec![], pos, Err(CommandError::BadUnitId)),
+            (vec![soldier_id, soldier_id], pos, Err(CommandError::BadUnitId)),
+            (vec![soldier_id, enemy_id], pos, Err(CommandError::CanNotCommandEnemyUnits)),
+            (vec![soldier_id], map_pos(-1, 7), Err(CommandError::BadPath)),
+        ] {
+            let command = group_move(unit_ids, destination);
+            assert_eq!(check_command(&db, player_id, &state, &command), result);
+        }
+    }
 }
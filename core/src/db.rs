@@ -1,4 +1,5 @@
-use unit::{UnitType, WeaponType, UnitTypeId, WeaponTypeId};
+use unit::{UnitType, WeaponType, SmokeInfo, UnitTypeId, WeaponTypeId};
+use dir::{Dir};
 use ::{MovePoints, AttackPoints, ReinforcementPoints, Distance};
 
 fn weapon_type_id(weapon_types: &[WeaponType], name: &str)
@@ -25,7 +26,10 @@ fn get_weapon_types() -> Vec<WeaponType> {
             min_distance: Distance{n: 1},
             is_inderect: true,
             reaction_fire: false,
-            smoke: Some(3),
+            smoke: Some(SmokeInfo {
+                duration: 3,
+                pattern: vec![Dir::SouthEast, Dir::East, Dir::NorthEast],
+            }),
         },
         WeaponType {
             name: "super_heavy_tank_gun".to_owned(),
@@ -99,6 +103,18 @@ fn get_weapon_types() -> Vec<WeaponType> {
             reaction_fire: true,
             smoke: None,
         },
+        WeaponType {
+            name: "grenades".to_owned(),
+            damage: 8,
+            ap: 8,
+            accuracy: 3,
+            max_distance: Distance{n: 1},
+            max_air_distance: None,
+            min_distance: Distance{n: 0},
+            is_inderect: false,
+            reaction_fire: false,
+            smoke: None,
+        },
         WeaponType {
             name: "machine_gun".to_owned(),
             damage: 5,
@@ -125,6 +141,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 9,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "super_heavy_tank_gun"),
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 5},
             attack_points: AttackPoints{n: 1},
             reactive_attack_points: AttackPoints{n: 1},
@@ -135,6 +152,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: false,
             can_be_towed: false,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 16},
         },
         UnitType {
@@ -145,6 +163,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 9,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "heavy_tank_gun"),
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 7},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -155,6 +174,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: false,
             can_be_towed: true,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 10},
         },
         UnitType {
@@ -165,6 +185,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 9,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "medium_tank_gun"),
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 8},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -175,6 +196,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: false,
             can_be_towed: true,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 8},
         },
         UnitType {
@@ -185,6 +207,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 9,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "light_tank_gun"),
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 10},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -195,6 +218,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: false,
             can_be_towed: true,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 6},
         },
         UnitType {
@@ -205,6 +229,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 9,
             weapon_skill: 7,
             weapon_type_id: weapon_type_id(weapon_types, "medium_tank_gun"),
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 10},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -215,6 +240,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: false,
             can_be_towed: true,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 6},
         },
         UnitType {
@@ -226,6 +252,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             weapon_skill: 7,
             // TODO: "tank_gun" on field gun??
             weapon_type_id: weapon_type_id(weapon_types, "medium_tank_gun"),
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 7},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -236,6 +263,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: true,
             can_be_towed: true,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 5},
         },
         UnitType {
@@ -246,6 +274,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 3,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "machine_gun"),
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 12},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -256,6 +285,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: false,
             can_be_towed: true,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 4},
         },
         UnitType {
@@ -266,6 +296,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 3,
             weapon_skill: 0,
             weapon_type_id: weapon_type_id(weapon_types, "machine_gun"), // TODO: remove hack
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 10},
             attack_points: AttackPoints{n: 0},
             reactive_attack_points: AttackPoints{n: 0},
@@ -276,6 +307,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: false,
             can_be_towed: true,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 4},
         },
         UnitType {
@@ -286,6 +318,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 3,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "machine_gun"),
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 10},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -296,6 +329,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: true,
             is_infantry: false,
             can_be_towed: false,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 10},
         },
         UnitType {
@@ -306,6 +340,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 2,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "rifle"),
+            close_assault_weapon_type_id: Some(weapon_type_id(weapon_types, "grenades")),
             move_points: MovePoints{n: 9},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -316,6 +351,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: true,
             can_be_towed: false,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 2},
         },
         UnitType {
@@ -326,6 +362,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 2,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "submachine_gun"),
+            close_assault_weapon_type_id: Some(weapon_type_id(weapon_types, "grenades")),
             move_points: MovePoints{n: 9},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -336,6 +373,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: true,
             can_be_towed: false,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 2},
         },
         UnitType {
@@ -346,6 +384,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 2,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "rifle"),
+            close_assault_weapon_type_id: Some(weapon_type_id(weapon_types, "grenades")),
             move_points: MovePoints{n: 11},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 1},
@@ -356,6 +395,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: true,
             can_be_towed: false,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 3},
         },
         UnitType {
@@ -366,6 +406,7 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             toughness: 2,
             weapon_skill: 5,
             weapon_type_id: weapon_type_id(weapon_types, "mortar"),
+            close_assault_weapon_type_id: None,
             move_points: MovePoints{n: 7},
             attack_points: AttackPoints{n: 2},
             reactive_attack_points: AttackPoints{n: 0},
@@ -376,8 +417,31 @@ fn get_unit_types(weapon_types: &[WeaponType]) -> Vec<UnitType> {
             is_air: false,
             is_infantry: true,
             can_be_towed: false,
+            is_engineer: false,
             cost: ReinforcementPoints{n: 4},
         },
+        UnitType {
+            name: "engineer".to_owned(),
+            size: 4,
+            count: 3,
+            armor: 1,
+            toughness: 2,
+            weapon_skill: 5,
+            weapon_type_id: weapon_type_id(weapon_types, "rifle"),
+            close_assault_weapon_type_id: Some(weapon_type_id(weapon_types, "grenades")),
+            move_points: MovePoints{n: 9},
+            attack_points: AttackPoints{n: 2},
+            reactive_attack_points: AttackPoints{n: 1},
+            los_range: Distance{n: 6},
+            cover_los_range: Distance{n: 1},
+            is_transporter: false,
+            is_big: false,
+            is_air: false,
+            is_infantry: true,
+            can_be_towed: false,
+            is_engineer: true,
+            cost: ReinforcementPoints{n: 3},
+        },
     ]
 }
 
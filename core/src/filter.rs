@@ -9,6 +9,7 @@ use ::{
     PlayerId,
     MoveMode,
     MovePoints,
+    is_loaded_or_attached,
 };
 
 fn filtered_unit(unit: &Unit) -> Unit {
@@ -21,6 +22,25 @@ fn filtered_unit(unit: &Unit) -> Unit {
     }
 }
 
+// Towed units are never seen apart from their transporters
+fn show_unit(
+    state: &State,
+    unit: &Unit,
+    events: &mut Vec<CoreEvent>,
+    active_unit_ids: &mut HashSet<UnitId>,
+) {
+    events.push(CoreEvent::ShowUnit {
+        unit_info: filtered_unit(unit),
+    });
+    if let Some(attached_unit_id) = unit.attached_unit_id {
+        active_unit_ids.insert(attached_unit_id);
+        let attached_unit = state.unit(attached_unit_id);
+        events.push(CoreEvent::ShowUnit {
+            unit_info: filtered_unit(attached_unit),
+        });
+    }
+}
+
 pub fn get_visible_enemies(
     state: &State,
     fow: &Fow,
@@ -28,10 +48,16 @@ pub fn get_visible_enemies(
 ) -> HashSet<UnitId> {
     let mut visible_enemies = HashSet::new();
     for (&id, unit) in state.units() {
-        if unit.player_id != player_id
-            && fow.is_visible(unit)
-        {
+        // passengers are never seen, towed units
+        // are seen together with their transporters
+        if unit.player_id == player_id || is_loaded_or_attached(unit) {
+            continue;
+        }
+        if fow.is_visible(unit) {
             visible_enemies.insert(id);
+            if let Some(attached_unit_id) = unit.attached_unit_id {
+                visible_enemies.insert(attached_unit_id);
+            }
         }
     }
     visible_enemies
@@ -64,6 +90,25 @@ pub fn show_or_hide_passive_enemies(
     events
 }
 
+/// Syncs objects of the player's state with what the player sees
+pub fn show_or_hide_objects(state: &State, fow: &Fow) -> Vec<CoreEvent> {
+    let mut events = Vec::new();
+    for (&id, object) in state.objects() {
+        if !fow.is_object_known(id) && fow.is_object_pos_visible(object.pos) {
+            events.push(CoreEvent::CreateObject {
+                id: id,
+                object: object.clone(),
+            });
+        }
+    }
+    for (id, pos) in fow.known_objects() {
+        if state.objects().get(&id).is_none() && fow.is_object_pos_visible(pos) {
+            events.push(CoreEvent::RemoveObject{id: id});
+        }
+    }
+    events
+}
+
 pub fn filter_events(
     state: &State,
     player_id: PlayerId,
@@ -120,58 +165,66 @@ pub fn filter_events(
             }
         },
         CoreEvent::AttackUnit{ref attack_info} => {
-            let attacker_id = attack_info.attacker_id
-                .expect("Core must know about everything");
-            let attacker = state.unit(attacker_id);
-            if player_id != attacker.player_id && !attack_info.is_ambush {
-                // show attacker if this is not ambush
+            active_unit_ids.insert(attack_info.defender_id); // if defender is killed
+            if let Some(attacker_id) = attack_info.attacker_id {
                 let attacker = state.unit(attacker_id);
-                if !fow.is_visible(attacker) {
-                    events.push(CoreEvent::ShowUnit {
-                        unit_info: filtered_unit(attacker),
-                    });
+                if player_id != attacker.player_id && !attack_info.is_ambush {
+                    // show attacker if this is not ambush
+                    if !fow.is_visible(attacker) {
+                        show_unit(state, attacker, &mut events, &mut active_unit_ids);
+                    }
+                    active_unit_ids.insert(attacker_id);
+                }
+                let is_attacker_visible = player_id == attacker.player_id
+                    || !attack_info.is_ambush;
+                let attack_info = AttackInfo {
+                    attacker_id: if is_attacker_visible {
+                        Some(attacker_id)
+                    } else {
+                        None
+                    },
+                    .. attack_info.clone()
+                };
+                events.push(CoreEvent::AttackUnit{attack_info: attack_info});
+            } else {
+                // no attacker (e.g. collapsed building)
+                let defender = state.unit(attack_info.defender_id);
+                if defender.player_id == player_id || fow.is_visible(defender) {
+                    events.push(event.clone());
                 }
-                active_unit_ids.insert(attacker_id);
             }
-            active_unit_ids.insert(attack_info.defender_id); // if defender is killed
-            let is_attacker_visible = player_id == attacker.player_id
-                || !attack_info.is_ambush;
-            let attack_info = AttackInfo {
-                attacker_id: if is_attacker_visible {
-                    Some(attacker_id)
-                } else {
-                    None
-                },
-                .. attack_info.clone()
-            };
-            events.push(CoreEvent::AttackUnit{attack_info: attack_info});
+        },
+        CoreEvent::DamageObject{id, attacker_id, ..} => {
+            if let Some(attacker_id) = attacker_id {
+                let attacker = state.unit(attacker_id);
+                // shelling gives away the position
+                if player_id != attacker.player_id {
+                    if !fow.is_visible(attacker) {
+                        show_unit(state, attacker, &mut events, &mut active_unit_ids);
+                    }
+                    active_unit_ids.insert(attacker_id);
+                }
+            }
+            if fow.is_object_known(id) {
+                events.push(event.clone());
+            }
         },
         CoreEvent::Reveal{ref unit_info} => {
             if unit_info.player_id != player_id {
-                events.push(CoreEvent::ShowUnit {
-                    unit_info: filtered_unit(unit_info),
-                });
+                show_unit(state, unit_info, &mut events, &mut active_unit_ids);
             }
         },
         CoreEvent::ShowUnit{..} |
         CoreEvent::HideUnit{..} => panic!(),
         CoreEvent::LoadUnit{passenger_id, from, to, transporter_id} => {
             let passenger = state.unit(passenger_id);
-            let transporter = state.unit(transporter_id.unwrap());
-            let is_transporter_vis = fow.is_visible(transporter);
-            let is_passenger_vis = fow.is_visible_at(passenger, from);
             if passenger.player_id == player_id {
                 events.push(event.clone());
-            } else if is_passenger_vis || is_transporter_vis {
-                if !fow.is_visible_at(passenger, from) {
-                    events.push(CoreEvent::ShowUnit {
-                        unit_info: Unit {
-                            pos: from,
-                            .. filtered_unit(passenger)
-                        },
-                    });
-                }
-                let filtered_transporter_id = if is_transporter_vis {
+            } else if fow.is_visible_at(passenger, from) {
+                // the enemy sees somebody boarding but not
+                // who is inside, so the passenger is hidden
+                let transporter = state.unit(transporter_id.unwrap());
+                let filtered_transporter_id = if fow.is_visible(transporter) {
                     transporter_id
                 } else {
                     None
@@ -182,40 +235,46 @@ pub fn filter_events(
                     from: from,
                     to: to,
                 });
+                events.push(CoreEvent::HideUnit {
+                    unit_id: passenger_id,
+                });
                 active_unit_ids.insert(passenger_id);
             }
         },
         CoreEvent::UnloadUnit{ref unit_info, transporter_id, from, to} => {
             active_unit_ids.insert(unit_info.id);
             let passenger = state.unit(unit_info.id);
-            let transporter = state.unit(transporter_id.unwrap());
-            let is_transporter_vis = fow.is_visible_at(transporter, from);
-            let is_passenger_vis = fow.is_visible_at(passenger, to);
             if passenger.player_id == player_id {
                 events.push(event.clone());
-            } else if is_passenger_vis || is_transporter_vis {
-                let filtered_transporter_id = if is_transporter_vis {
+            } else if fow.is_visible_at(passenger, to) {
+                // the passenger is revealed by unloading
+                let transporter = state.unit(transporter_id.unwrap());
+                let filtered_transporter_id = if fow.is_visible_at(transporter, from) {
                     transporter_id
                 } else {
                     None
                 };
                 events.push(CoreEvent::UnloadUnit {
                     transporter_id: filtered_transporter_id,
+                    unit_info: filtered_unit(unit_info),
+                    from: from,
+                    to: to,
+                });
+            } else if fow.is_visible_at(state.unit(transporter_id.unwrap()), from) {
+                // somebody is seen leaving the transporter and vanishing
+                // in the fog, so the last known position is the transporter's tile
+                events.push(CoreEvent::UnloadUnit {
+                    transporter_id: transporter_id,
                     unit_info: Unit {
-                        move_points: None,
-                        attack_points: None,
-                        reactive_attack_points: None,
-                        passenger_id: None,
-                        .. unit_info.clone()
+                        pos: from,
+                        .. filtered_unit(unit_info)
                     },
                     from: from,
-                    to: to,
+                    to: from,
+                });
+                events.push(CoreEvent::HideUnit {
+                    unit_id: unit_info.id,
                 });
-                if !is_passenger_vis {
-                    events.push(CoreEvent::HideUnit {
-                        unit_id: passenger.id,
-                    });
-                }
             }
         },
         CoreEvent::Attach{transporter_id, attached_unit_id, from, to} => {
@@ -224,10 +283,12 @@ pub fn filter_events(
                 events.push(event.clone())
             } else {
                 active_unit_ids.insert(transporter_id);
+                active_unit_ids.insert(attached_unit_id);
                 let attached_unit = state.unit(attached_unit_id);
                 let is_attached_unit_vis = fow.is_visible_at(attached_unit, to);
                 let is_transporter_vis = fow.is_visible_at(transporter, from);
-                if is_attached_unit_vis {
+                if fow.is_visible_at(transporter, to) {
+                    // the towed unit is seen together with its transporter
                     if !is_transporter_vis {
                         events.push(CoreEvent::ShowUnit {
                             unit_info: Unit {
@@ -237,18 +298,33 @@ pub fn filter_events(
                             },
                         });
                     }
+                    if !is_attached_unit_vis {
+                        events.push(CoreEvent::ShowUnit {
+                            unit_info: Unit {
+                                is_attached: false,
+                                .. filtered_unit(attached_unit)
+                            },
+                        });
+                    }
                     events.push(event.clone())
-                } else if is_transporter_vis {
-                    events.push(CoreEvent::Move {
-                        unit_id: transporter_id,
-                        mode: MoveMode::Fast,
-                        cost: MovePoints{n: 0},
-                        from: from,
-                        to: to,
-                    });
-                    events.push(CoreEvent::HideUnit {
-                        unit_id: transporter_id,
-                    });
+                } else {
+                    if is_transporter_vis {
+                        events.push(CoreEvent::Move {
+                            unit_id: transporter_id,
+                            mode: MoveMode::Fast,
+                            cost: MovePoints{n: 0},
+                            from: from,
+                            to: to,
+                        });
+                        events.push(CoreEvent::HideUnit {
+                            unit_id: transporter_id,
+                        });
+                    }
+                    if is_attached_unit_vis {
+                        events.push(CoreEvent::HideUnit {
+                            unit_id: attached_unit_id,
+                        });
+                    }
                 }
             }
         },
@@ -285,31 +361,447 @@ pub fn filter_events(
                 }
             }
         },
-        CoreEvent::SetReactionFireMode{unit_id, ..} => {
+        CoreEvent::SetReactionFireMode{unit_id, ..} |
+        CoreEvent::SetMoveOrder{unit_id, ..} |
+        CoreEvent::CancelMoveOrder{unit_id} => {
             let unit = state.unit(unit_id);
             if unit.player_id == player_id {
                 events.push(event.clone());
             }
         },
-        CoreEvent::Smoke{id, pos, unit_id} => {
-            let unit_id = unit_id.expect("Core must know about everything");
+        CoreEvent::BailOut{ref unit_info, transporter_id, from, to} => {
+            if unit_info.player_id == player_id {
+                events.push(event.clone());
+                active_unit_ids.insert(unit_info.id);
+            } else if fow.is_visible_at(unit_info, to) {
+                events.push(CoreEvent::BailOut {
+                    unit_info: filtered_unit(unit_info),
+                    transporter_id: transporter_id,
+                    from: from,
+                    to: to,
+                });
+                active_unit_ids.insert(unit_info.id);
+            }
+        },
+        CoreEvent::KillPassenger{transporter_id, ..} => {
+            // enemies don't know who was inside and wrecks
+            // forget that they were occupied on their own
+            let transporter = state.unit(transporter_id);
+            if transporter.player_id == player_id {
+                events.push(event.clone());
+            }
+        },
+        CoreEvent::Split{unit_id, count, ref unit_info} => {
             let unit = state.unit(unit_id);
-            if fow.is_visible(unit) {
+            let unit_info = unit_info.as_ref().unwrap();
+            if unit.player_id == player_id {
                 events.push(event.clone());
-            } else {
-                events.push(CoreEvent::Smoke {
-                    id: id,
-                    pos: pos,
-                    unit_id: None,
+                active_unit_ids.insert(unit_info.id);
+            } else if fow.is_visible(unit) {
+                // only the squad's new size is known
+                // if the detached part went into the fog
+                let filtered_unit_info = if fow.is_visible(unit_info) {
+                    Some(filtered_unit(unit_info))
+                } else {
+                    None
+                };
+                events.push(CoreEvent::Split {
+                    unit_id: unit_id,
+                    count: count,
+                    unit_info: filtered_unit_info,
+                });
+                active_unit_ids.insert(unit_info.id);
+            } else if fow.is_visible(unit_info) {
+                events.push(CoreEvent::ShowUnit {
+                    unit_info: filtered_unit(unit_info),
+                });
+                active_unit_ids.insert(unit_info.id);
+            }
+        },
+        CoreEvent::Merge{unit_id, from, ref unit_info} => {
+            if unit_info.player_id == player_id {
+                events.push(event.clone());
+            } else if fow.is_visible(unit_info) {
+                events.push(CoreEvent::Merge {
+                    unit_id: unit_id,
+                    from: from,
+                    unit_info: filtered_unit(unit_info),
+                });
+            } else if fow.is_visible_at(unit_info, from) {
+                // merged squads share the same type
+                events.push(CoreEvent::HideUnit {
+                    unit_id: unit_id,
                 });
             }
+            active_unit_ids.insert(unit_id);
+        },
+        CoreEvent::Capture{unit_id, crew_count, old_player_id, ref unit_info} => {
+            if unit_info.player_id == player_id {
+                events.push(event.clone());
+            } else if fow.is_visible(unit_info) {
+                events.push(CoreEvent::Capture {
+                    unit_id: unit_id,
+                    crew_count: crew_count,
+                    old_player_id: old_player_id,
+                    unit_info: filtered_unit(unit_info),
+                });
+            } else if old_player_id == player_id {
+                // the former owner must forget about the lost unit
+                events.push(CoreEvent::HideUnit {
+                    unit_id: unit_info.id,
+                });
+            }
+            active_unit_ids.insert(unit_info.id);
+        },
+        CoreEvent::Entrench{unit_id} |
+        CoreEvent::EngineeringWork{unit_id} |
+        CoreEvent::FireSmoke{unit_id} => {
+            let unit = state.unit(unit_id);
+            if unit.player_id == player_id || fow.is_visible(unit) {
+                events.push(event.clone());
+            }
+        },
+        CoreEvent::CreateObject{ref object, ..} => {
+            // objects created in the fog are shown
+            // by `show_or_hide_objects` when their tiles are seen
+            if fow.is_object_pos_visible(object.pos) {
+                events.push(event.clone());
+            }
+        },
+        CoreEvent::RemoveObject{id} => {
+            // removed objects are forgotten when their tiles are seen
+            if let Some(pos) = fow.known_object_pos(id) {
+                if fow.is_object_pos_visible(pos) {
+                    events.push(event.clone());
+                }
+            }
         },
         CoreEvent::EndTurn{..} |
-        CoreEvent::RemoveSmoke{..} |
         CoreEvent::VictoryPoint{..} |
+        CoreEvent::WeatherChanged{..} |
         CoreEvent::SectorOwnerChanged{..} => {
             events.push(event.clone());
         },
     }
     (events, active_unit_ids)
 }
+
+#[cfg(test)]
+mod tests {
+    use test_utils::{
+        options,
+        map01_options,
+        new_state,
+        new_fow,
+        to_partial,
+        add_unit,
+        add_core_unit,
+        add_object,
+        load_unit,
+        killing_attack,
+        map_pos,
+        smoke,
+    };
+    use game_state::{State};
+    use fow::{Fow};
+    use unit::{Unit};
+    use ::{
+        Core,
+        CoreEvent,
+        ExactPos,
+        MapPos,
+        MoveMode,
+        MovePoints,
+        ObjectId,
+        Options,
+        PlayerId,
+        SlotId,
+        UnitId,
+        get_free_exact_pos,
+    };
+    use super::{filter_events, show_or_hide_objects};
+
+    /// Filters the event for the second player like `Core` does
+    fn filter(state: &State, fow: &mut Fow, event: &CoreEvent) -> Vec<CoreEvent> {
+        let (events, _) = filter_events(state, PlayerId{id: 1}, fow, event);
+        for event in &events {
+            fow.apply_event(state, event);
+        }
+        events
+    }
+
+    fn move_unit(state: &mut State, fow: &mut Fow, id: UnitId, pos: MapPos) {
+        let event = CoreEvent::Move {
+            unit_id: id,
+            from: state.unit(id).pos,
+            to: ExactPos{map_pos: pos, slot_id: SlotId::Id(0)},
+            mode: MoveMode::Fast,
+            cost: MovePoints{n: 0},
+        };
+        state.apply_event(&event);
+        fow.apply_event(state, &event);
+    }
+
+    fn split_event(unit: &Unit, pos: ExactPos) -> CoreEvent {
+        CoreEvent::Split {
+            unit_id: unit.id,
+            count: 1,
+            unit_info: Some(Unit {
+                id: UnitId{id: 100},
+                pos: pos,
+                count: 1,
+                .. unit.clone()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_split_in_sight() {
+        let (db, mut state) = new_state(&map01_options());
+        let squad_id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 3));
+        add_unit(&db, &mut state, 1, "soldier", map_pos(8, 3));
+        let pos = ExactPos{map_pos: map_pos(6, 3), slot_id: SlotId::Id(0)};
+        let event = split_event(state.unit(squad_id), pos);
+        state.apply_event(&event);
+        let fow = new_fow(&db, &state, 1);
+        let (events, _) = filter_events(&state, PlayerId{id: 1}, &fow, &event);
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            CoreEvent::Split{unit_id, count, unit_info: Some(ref unit_info)} => {
+                assert_eq!(unit_id, squad_id);
+                assert_eq!(count, 1);
+                assert_eq!(unit_info.pos, pos);
+                assert_eq!(unit_info.move_points, None);
+            },
+            ref event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_split_into_fog() {
+        let (db, mut state) = new_state(&map01_options());
+        let squad_id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 3));
+        add_unit(&db, &mut state, 1, "soldier", map_pos(8, 3));
+        // infantry in the forest is hidden from afar
+        let pos = ExactPos{map_pos: map_pos(4, 3), slot_id: SlotId::Id(0)};
+        let event = split_event(state.unit(squad_id), pos);
+        state.apply_event(&event);
+        let fow = new_fow(&db, &state, 1);
+        let (events, _) = filter_events(&state, PlayerId{id: 1}, &fow, &event);
+        assert_eq!(events, vec![CoreEvent::Split {
+            unit_id: squad_id,
+            count: 1,
+            unit_info: None,
+        }]);
+    }
+
+    #[test]
+    fn test_objects_in_fog() {
+        let (db, mut state) = new_state(&options());
+        let unit_id = add_unit(&db, &mut state, 1, "soldier", map_pos(1, 5));
+        let mut fow = new_fow(&db, &state, 1);
+        let near = CoreEvent::CreateObject {
+            id: ObjectId{id: 100},
+            object: smoke(map_pos(3, 5), 2),
+        };
+        let far = CoreEvent::CreateObject {
+            id: ObjectId{id: 101},
+            object: smoke(map_pos(10, 5), 2),
+        };
+        state.apply_event(&near);
+        assert_eq!(filter(&state, &mut fow, &near), vec![near.clone()]);
+        state.apply_event(&far);
+        assert_eq!(filter(&state, &mut fow, &far), vec![]);
+        assert_eq!(show_or_hide_objects(&state, &fow), vec![]);
+        // the smoke is shown when its tile is seen
+        move_unit(&mut state, &mut fow, unit_id, map_pos(8, 5));
+        assert_eq!(show_or_hide_objects(&state, &fow), vec![far]);
+    }
+
+    #[test]
+    fn test_removed_objects_in_fog() {
+        let (db, mut state) = new_state(&options());
+        let unit_id = add_unit(&db, &mut state, 1, "soldier", map_pos(1, 5));
+        let id = add_object(&mut state, smoke(map_pos(10, 5), 2));
+        let mut fow = new_fow(&db, &state, 1);
+        let event = CoreEvent::RemoveObject{id: id};
+        state.apply_event(&event);
+        assert_eq!(filter(&state, &mut fow, &event), vec![]);
+        assert_eq!(show_or_hide_objects(&state, &fow), vec![]);
+        // the player forgets the object only when its tile is seen
+        move_unit(&mut state, &mut fow, unit_id, map_pos(8, 5));
+        assert_eq!(show_or_hide_objects(&state, &fow), vec![event]);
+    }
+
+    fn take_enemy_events(core: &mut Core) -> Vec<CoreEvent> {
+        let i = core.players_info.get_mut(&PlayerId{id: 1}).unwrap();
+        i.events.drain(..).collect()
+    }
+
+    /// The second player's own copy of the state, knows only the given units
+    fn enemy_view(core: &Core, options: &Options, unit_ids: &[UnitId]) -> State {
+        let (db, mut state) = new_state(options);
+        for &id in unit_ids {
+            state.apply_event(&CoreEvent::ShowUnit {
+                unit_info: core.state.unit(id).clone(),
+            });
+        }
+        to_partial(&db, &mut state, 1);
+        state
+    }
+
+    fn shown_units(events: &[CoreEvent]) -> Vec<&Unit> {
+        events.iter().filter_map(|event| match *event {
+            CoreEvent::ShowUnit{ref unit_info}
+                | CoreEvent::UnloadUnit{ref unit_info, ..} => Some(unit_info),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_filter_load_unit() {
+        let options = map01_options();
+        let mut core = Core::new(&options);
+        let truck_id = add_core_unit(&mut core, 0, "truck", map_pos(5, 3));
+        let soldier_id = add_core_unit(&mut core, 0, "soldier", map_pos(6, 3));
+        let enemy_id = add_core_unit(&mut core, 1, "soldier", map_pos(8, 3));
+        let mut view = enemy_view(
+            &core, &options, &[truck_id, soldier_id, enemy_id]);
+        take_enemy_events(&mut core);
+        load_unit(&mut core, truck_id, soldier_id);
+        let events = take_enemy_events(&mut core);
+        assert_eq!(events, vec![
+            CoreEvent::LoadUnit {
+                transporter_id: Some(truck_id),
+                passenger_id: soldier_id,
+                from: view.unit(soldier_id).pos,
+                to: view.unit(truck_id).pos,
+            },
+            CoreEvent::HideUnit{unit_id: soldier_id},
+        ]);
+        for event in &events {
+            view.apply_event(event);
+        }
+        // the enemy knows that somebody is inside, but not who
+        assert!(view.unit_opt(soldier_id).is_none());
+        let truck = view.unit(truck_id);
+        assert_eq!(truck.passenger_id, None);
+        assert!(view.is_transporter_occupied(truck));
+    }
+
+    #[test]
+    fn test_filter_unload_unit() {
+        let options = map01_options();
+        let mut core = Core::new(&options);
+        let truck_id = add_core_unit(&mut core, 0, "truck", map_pos(5, 3));
+        let soldier_id = add_core_unit(&mut core, 0, "soldier", map_pos(6, 3));
+        add_core_unit(&mut core, 1, "soldier", map_pos(8, 3));
+        load_unit(&mut core, truck_id, soldier_id);
+        take_enemy_events(&mut core);
+        let type_id = core.state.unit(soldier_id).type_id;
+        let from = core.state.unit(truck_id).pos;
+        let to = get_free_exact_pos(
+            &core.db, &core.state, type_id, map_pos(6, 3)).unwrap();
+        core.do_core_event(&CoreEvent::UnloadUnit {
+            unit_info: Unit {
+                pos: to,
+                is_loaded: false,
+                .. core.state.unit(soldier_id).clone()
+            },
+            transporter_id: Some(truck_id),
+            from: from,
+            to: to,
+        });
+        let events = take_enemy_events(&mut core);
+        let units = shown_units(&events);
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].id, soldier_id);
+        assert_eq!(units[0].pos, to);
+        assert_eq!(units[0].move_points, None);
+        assert_eq!(units[0].attack_points, None);
+    }
+
+    #[test]
+    fn test_filter_unload_unit_into_fog() {
+        let options = map01_options();
+        let mut core = Core::new(&options);
+        let truck_id = add_core_unit(&mut core, 0, "truck", map_pos(5, 3));
+        let soldier_id = add_core_unit(&mut core, 0, "soldier", map_pos(6, 3));
+        let enemy_id = add_core_unit(&mut core, 1, "soldier", map_pos(8, 3));
+        let mut view = enemy_view(
+            &core, &options, &[truck_id, soldier_id, enemy_id]);
+        load_unit(&mut core, truck_id, soldier_id);
+        let type_id = core.state.unit(soldier_id).type_id;
+        let from = core.state.unit(truck_id).pos;
+        // infantry in the forest is hidden from afar
+        let to = get_free_exact_pos(
+            &core.db, &core.state, type_id, map_pos(4, 3)).unwrap();
+        core.do_core_event(&CoreEvent::UnloadUnit {
+            unit_info: Unit {
+                pos: to,
+                is_loaded: false,
+                .. core.state.unit(soldier_id).clone()
+            },
+            transporter_id: Some(truck_id),
+            from: from,
+            to: to,
+        });
+        let events = take_enemy_events(&mut core);
+        for unit in shown_units(&events) {
+            assert_eq!(unit.pos, from);
+        }
+        assert!(events.contains(&CoreEvent::HideUnit{unit_id: soldier_id}));
+        for event in &events {
+            view.apply_event(event);
+        }
+        // the transporter is known to be empty now
+        assert!(view.unit_opt(soldier_id).is_none());
+        assert!(!view.is_transporter_occupied(view.unit(truck_id)));
+    }
+
+    #[test]
+    fn test_filter_attach() {
+        let mut core = Core::new(&options());
+        let truck_id = add_core_unit(&mut core, 0, "truck", map_pos(7, 5));
+        let soldier_id = add_core_unit(&mut core, 0, "soldier", map_pos(8, 5));
+        let gun_id = add_core_unit(&mut core, 0, "field_gun", map_pos(5, 5));
+        add_core_unit(&mut core, 1, "soldier", map_pos(0, 5));
+        load_unit(&mut core, truck_id, soldier_id);
+        take_enemy_events(&mut core);
+        // the truck comes out of the fog to pick up the gun
+        core.do_core_event(&CoreEvent::Attach {
+            transporter_id: truck_id,
+            attached_unit_id: gun_id,
+            from: core.state.unit(truck_id).pos,
+            to: core.state.unit(gun_id).pos,
+        });
+        let events = take_enemy_events(&mut core);
+        let units = shown_units(&events);
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].id, truck_id);
+        assert_eq!(units[0].passenger_id, None);
+        assert_eq!(units[0].attached_unit_id, None);
+        assert_eq!(units[0].move_points, None);
+    }
+
+    #[test]
+    fn test_filter_kill_passenger() {
+        let mut core = Core::new(&options());
+        let truck_id = add_core_unit(&mut core, 0, "truck", map_pos(5, 5));
+        let soldier_id = add_core_unit(&mut core, 0, "soldier", map_pos(5, 5));
+        let tank_id = add_core_unit(&mut core, 1, "medium_tank", map_pos(5, 3));
+        load_unit(&mut core, truck_id, soldier_id);
+        take_enemy_events(&mut core);
+        core.do_attack_event(&killing_attack(tank_id, truck_id, false));
+        let events = take_enemy_events(&mut core);
+        for event in &events {
+            if let CoreEvent::KillPassenger{..} = *event {
+                panic!("The enemy must not know who was inside");
+            }
+        }
+        for unit in shown_units(&events) {
+            assert!(unit.id != soldier_id);
+            assert_eq!(unit.passenger_id, None);
+        }
+    }
+}
@@ -1,24 +1,104 @@
 /// Field of View
+///
+/// Lines of sight are traced with integer hex lines, so the result
+/// is deterministic and symmetric: if A sees B then B sees A.
 
-use std::f32::consts::{PI};
-use cgmath::{InnerSpace};
+use std::cell::{RefCell};
+use std::collections::{HashMap};
+use std::rc::{Rc};
+use cgmath::{Vector2};
 use game_state::{State};
 use map::{Terrain, spiral_iter};
-use geom;
-use ::{MapPos, ObjectClass, Distance};
+use ::{MapPos, ObjectClass, Distance, hexside_between, is_hexside};
 
-struct Shadow {
-    left: f32,
-    right: f32,
+/// Cube coordinates that agree with `map::distance`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct Cube {
+    x: i32,
+    y: i32,
+    z: i32,
 }
 
-fn is_tile_visible(angle: f32, shadows: &[Shadow]) -> bool {
-    for shadow in shadows {
-        if shadow.left < angle && shadow.right > angle {
-            return false;
+impl Cube {
+    fn add(self, other: Cube) -> Cube {
+        Cube {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
         }
     }
-    true
+
+    fn sub(self, other: Cube) -> Cube {
+        Cube {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+        }
+    }
+}
+
+fn div_floor(n: i32, d: i32) -> i32 {
+    let q = n / d;
+    if n % d != 0 && (n < 0) != (d < 0) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn round_div(n: i32, d: i32) -> i32 {
+    div_floor(2 * n + d, 2 * d)
+}
+
+fn to_cube(pos: MapPos) -> Cube {
+    let x = pos.v.x + div_floor(pos.v.y, 2);
+    let z = -pos.v.y;
+    Cube{x: x, y: -x - z, z: z}
+}
+
+fn from_cube(cube: Cube) -> MapPos {
+    let y = -cube.z;
+    MapPos{v: Vector2{x: cube.x - div_floor(y, 2), y: y}}
+}
+
+// Interpolated points are scaled by `LINE_SCALE * distance`.
+// The scale is big enough for the nudge to break
+// exact ties without changing any other rounding.
+const LINE_SCALE: i32 = 12;
+
+fn cube_round(x: i32, y: i32, z: i32, d: i32) -> Cube {
+    let mut rx = round_div(x, d);
+    let mut ry = round_div(y, d);
+    let mut rz = round_div(z, d);
+    let dx = (rx * d - x).abs();
+    let dy = (ry * d - y).abs();
+    let dz = (rz * d - z).abs();
+    if dx > dy && dx > dz {
+        rx = -ry - rz;
+    } else if dy > dz {
+        ry = -rx - rz;
+    } else {
+        rz = -rx - ry;
+    }
+    Cube{x: rx, y: ry, z: rz}
+}
+
+/// Tiles of the line between two tiles including the ends.
+/// The line is shifted a little to one side (`nudge` is 1 or -1)
+/// so it never goes exactly along a hexside.
+fn hex_line(from: Cube, to: Cube, nudge: i32) -> Vec<Cube> {
+    let diff = to.sub(from);
+    let n = (diff.x.abs() + diff.y.abs() + diff.z.abs()) / 2;
+    if n == 0 {
+        return vec![from];
+    }
+    let d = n * LINE_SCALE;
+    (0 .. n + 1).map(|i| {
+        let x = (from.x * (n - i) + to.x * i) * LINE_SCALE + nudge;
+        let y = (from.y * (n - i) + to.y * i) * LINE_SCALE + 2 * nudge;
+        let z = (from.z * (n - i) + to.z * i) * LINE_SCALE - 3 * nudge;
+        cube_round(x, y, z, d)
+    }).collect()
 }
 
 fn is_obstacle(state: &State, pos: MapPos) -> bool {
@@ -30,43 +110,184 @@ fn is_obstacle(state: &State, pos: MapPos) -> bool {
         match object.class {
             ObjectClass::Building |
             ObjectClass::Smoke => return true,
+            ObjectClass::Rubble |
             ObjectClass::ReinforcementSector |
-            ObjectClass::Road => {},
+            ObjectClass::Road |
+            ObjectClass::Bridge |
+            ObjectClass::River |
+            ObjectClass::Wall |
+            ObjectClass::Hedge => {},
+        }
+    }
+    false
+}
+
+// Only hedges and walls block the view,
+// rivers are hexsides too but lie flat
+fn is_hexside_obstacle(class: ObjectClass) -> bool {
+    match class {
+        ObjectClass::Hedge | ObjectClass::Wall => true,
+        ObjectClass::Building |
+        ObjectClass::Rubble |
+        ObjectClass::Smoke |
+        ObjectClass::ReinforcementSector |
+        ObjectClass::Road |
+        ObjectClass::Bridge |
+        ObjectClass::River => false,
+    }
+}
+
+fn has_hexsides(state: &State, pos: MapPos) -> bool {
+    state.objects_at(pos).any(|object| is_hexside(object.class))
+}
+
+#[derive(Clone, Copy, Debug)]
+struct LocalTile {
+    pos: MapPos,
+    is_inboard: bool,
+    is_obstacle: bool,
+    has_hexsides: bool,
+}
+
+// Nothing can be seen through a hole in the board
+fn local_tile(state: &State, pos: MapPos) -> LocalTile {
+    let is_inboard = state.map().is_inboard(pos);
+    let is_void = state.map().is_void(pos);
+    LocalTile {
+        pos: pos,
+        is_inboard: is_inboard,
+        is_obstacle: is_void || (is_inboard && is_obstacle(state, pos)),
+        has_hexsides: is_inboard && has_hexsides(state, pos),
+    }
+}
+
+// Tiles between the ends block the line, as do hedges and walls
+// that don't touch the ends: units right behind a hexside
+// can see over it and can be seen.
+fn is_line_clear<F>(state: &State, len: usize, tile: F) -> bool
+    where F: Fn(usize) -> LocalTile
+{
+    let last = len - 1;
+    for i in 1 .. last {
+        let t = tile(i);
+        if t.is_obstacle {
+            return false;
+        }
+        if i + 1 == last || !t.has_hexsides {
+            continue;
+        }
+        let next = tile(i + 1);
+        if !next.is_inboard {
+            continue;
+        }
+        if let Some(class) = hexside_between(state, t.pos, next.pos) {
+            if is_hexside_obstacle(class) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Point-to-point line of sight check ignoring the distance.
+/// The target is visible if any of the two nudged lines is clear.
+pub fn has_los(state: &State, from: MapPos, to: MapPos) -> bool {
+    let from = to_cube(from);
+    let to = to_cube(to);
+    for &nudge in &[1, -1] {
+        let line: Vec<_> = hex_line(from, to, nudge).into_iter()
+            .map(|cube| local_tile(state, from_cube(cube)))
+            .collect();
+        if is_line_clear(state, line.len(), |i| line[i]) {
+            return true;
         }
     }
     false
 }
 
-// TODO: precalculate all 'atan2' and 'asin' stuff
+/// Precalculated lines from the origin to every tile around it.
+/// Tiles are stored in `spiral_iter` order so the table for any
+/// radius is a prefix of the table for a bigger one.
+#[derive(Debug)]
+struct FovTable {
+    radius: Distance,
+    /// Positions relative to the origin, the origin itself is the first
+    offsets: Vec<Cube>,
+    /// Two nudged lines per tile as indices into `offsets`
+    lines: Vec<[Vec<usize>; 2]>,
+}
+
+impl FovTable {
+    fn new(radius: Distance) -> FovTable {
+        let origin = MapPos{v: Vector2{x: 0, y: 0}};
+        let origin_cube = to_cube(origin);
+        let mut offsets = vec![Cube{x: 0, y: 0, z: 0}];
+        for pos in spiral_iter(origin, radius) {
+            offsets.push(to_cube(pos).sub(origin_cube));
+        }
+        let indices: HashMap<Cube, usize> = offsets.iter()
+            .enumerate()
+            .map(|(i, &offset)| (offset, i))
+            .collect();
+        let origin_offset = offsets[0];
+        let line_indices = |offset: Cube, nudge: i32| -> Vec<usize> {
+            hex_line(origin_offset, offset, nudge).iter()
+                .map(|cube| indices[cube])
+                .collect()
+        };
+        let lines = offsets.iter()
+            .map(|&offset| [line_indices(offset, 1), line_indices(offset, -1)])
+            .collect();
+        FovTable {
+            radius: radius,
+            offsets: offsets,
+            lines: lines,
+        }
+    }
+
+    fn tiles_count(radius: Distance) -> usize {
+        let r = radius.n as usize;
+        1 + 3 * r * (r + 1)
+    }
+}
+
+thread_local! {
+    static FOV_TABLE: RefCell<Rc<FovTable>>
+        = RefCell::new(Rc::new(FovTable::new(Distance{n: 1})));
+}
+
+fn fov_table(radius: Distance) -> Rc<FovTable> {
+    FOV_TABLE.with(|table| {
+        if table.borrow().radius < radius {
+            *table.borrow_mut() = Rc::new(FovTable::new(radius));
+        }
+        table.borrow().clone()
+    })
+}
+
 pub fn fov(
     state: &State,
     origin: MapPos,
     range: Distance,
     callback: &mut FnMut(MapPos),
 ) {
+    let table = fov_table(range);
+    let count = FovTable::tiles_count(range);
+    let origin_cube = to_cube(origin);
+    let tiles: Vec<_> = table.offsets[.. count].iter()
+        .map(|&offset| local_tile(state, from_cube(origin_cube.add(offset))))
+        .collect();
     callback(origin);
-    let map = state.map();
-    let mut shadows = vec!();
-    let origin3d = geom::map_pos_to_world_pos(origin);
-    for pos in spiral_iter(origin, range) {
-        if !map.is_inboard(pos) {
+    for i in 1 .. count {
+        // void tiles can't be seen, they only block the lines
+        if !tiles[i].is_inboard {
             continue;
         }
-        let pos3d = geom::map_pos_to_world_pos(pos);
-        let diff = pos3d - origin3d;
-        let distance = diff.magnitude();
-        let angle = diff.x.atan2(diff.y); // TODO: optimize
-        if is_tile_visible(angle, &shadows) {
-            callback(pos);
-        }
-        if is_obstacle(state, pos) {
-            let obstacle_radius = geom::HEX_IN_RADIUS * 1.1;
-            let a = (obstacle_radius / distance).asin();
-            let shadow = Shadow{left: angle - a, right: angle + a};
-            if shadow.right > PI {
-                shadows.push(Shadow{left: -PI, right: shadow.right - PI * 2.0});
-            }
-            shadows.push(shadow);
+        let is_visible = table.lines[i].iter().any(|line| {
+            is_line_clear(state, line.len(), |k| tiles[line[k]])
+        });
+        if is_visible {
+            callback(tiles[i].pos);
         }
     }
 }
@@ -84,3 +305,160 @@ pub fn simple_fov(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::{Rc};
+    use std::collections::{HashSet};
+    use cgmath::{Vector2};
+    use game_state::{State};
+    use map::{distance};
+    use db::{Db};
+    use dir::{Dir, dirs};
+    use test_utils::{options, map06_options, new_state, add_object, hexside, map_pos};
+    use ::{
+        MapPos,
+        ObjectClass,
+        Distance,
+        Options,
+        GameType,
+        MapGenParams,
+        Weather,
+        TimeOfDay,
+        ZocRule,
+    };
+    use super::{fov, has_los, hex_line, to_cube, from_cube};
+
+    fn generated_state(seed: u32) -> State {
+        let options = Options {
+            game_type: GameType::Hotseat,
+            map_name: "generated".into(),
+            players_count: 2,
+            weather: Weather::Clear,
+            time_of_day: TimeOfDay::Day,
+            zoc_rule: ZocRule::Disabled,
+            map_gen_params: Some(MapGenParams::new(seed)),
+        };
+        State::new_full(Rc::new(Db::new()), &options)
+    }
+
+    #[test]
+    fn test_cube_roundtrip() {
+        for y in -5 .. 5 {
+            for x in -5 .. 5 {
+                let pos = MapPos{v: Vector2{x: x, y: y}};
+                assert_eq!(pos, from_cube(to_cube(pos)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_hex_line_is_connected() {
+        let from = MapPos{v: Vector2{x: 3, y: 4}};
+        for y in 0 .. 10 {
+            for x in 0 .. 10 {
+                let to = MapPos{v: Vector2{x: x, y: y}};
+                let n = distance(from, to).n as usize;
+                for &nudge in &[1, -1] {
+                    let line = hex_line(to_cube(from), to_cube(to), nudge);
+                    assert_eq!(line.len(), n + 1);
+                    assert_eq!(from_cube(line[0]), from);
+                    assert_eq!(from_cube(line[n]), to);
+                    for window in line.windows(2) {
+                        let a = from_cube(window[0]);
+                        let b = from_cube(window[1]);
+                        assert_eq!(distance(a, b).n, 1);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_los_is_symmetric() {
+        for seed in 0 .. 5 {
+            let state = generated_state(seed);
+            let positions: Vec<_> = state.map().get_iter().collect();
+            for &from in &positions {
+                for &to in &positions {
+                    assert_eq!(
+                        has_los(&state, from, to),
+                        has_los(&state, to, from),
+                        "{:?} {:?}", from, to,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fov_agrees_with_los() {
+        let range = Distance{n: 6};
+        for seed in 0 .. 5 {
+            let state = generated_state(seed);
+            for origin in state.map().get_iter() {
+                let mut visible = HashSet::new();
+                fov(&state, origin, range, &mut |pos| {
+                    visible.insert(pos);
+                });
+                for pos in state.map().get_iter() {
+                    let expected = distance(origin, pos) <= range
+                        && has_los(&state, origin, pos);
+                    assert_eq!(visible.contains(&pos), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_wall_blocks_los() {
+        let (_, mut state) = new_state(&options());
+        let wall = hexside(ObjectClass::Wall, map_pos(4, 5), Dir::East);
+        add_object(&mut state, wall);
+        assert!(!has_los(&state, map_pos(2, 5), map_pos(8, 5)));
+        // units right behind the wall can see over it
+        assert!(has_los(&state, map_pos(4, 5), map_pos(8, 5)));
+        assert!(has_los(&state, map_pos(2, 5), map_pos(5, 5)));
+    }
+
+    #[test]
+    fn test_river_does_not_block_los() {
+        let (_, mut state) = new_state(&options());
+        let river = hexside(ObjectClass::River, map_pos(4, 5), Dir::East);
+        add_object(&mut state, river);
+        assert!(has_los(&state, map_pos(2, 5), map_pos(8, 5)));
+    }
+
+    #[test]
+    fn test_void_blocks_los() {
+        let (_, state) = new_state(&map06_options());
+        let hole = map_pos(7, 3);
+        let mut lines_count = 0;
+        for dir in dirs() {
+            let opposite_dir = Dir::from_int((dir.to_int() + 3) % 6);
+            let from = Dir::get_neighbour_pos(hole, opposite_dir);
+            let to = Dir::get_neighbour_pos(hole, dir);
+            if !state.map().is_inboard(from) || !state.map().is_inboard(to) {
+                continue;
+            }
+            assert!(!has_los(&state, from, to), "{:?} {:?}", from, to);
+            let mut visible = HashSet::new();
+            fov(&state, from, Distance{n: 2}, &mut |pos| {
+                visible.insert(pos);
+            });
+            assert!(!visible.contains(&to));
+            assert!(!visible.contains(&hole));
+            lines_count += 1;
+        }
+        assert!(lines_count > 0);
+    }
+
+    #[test]
+    fn test_fov_skips_board_edges() {
+        let (_, state) = new_state(&map06_options());
+        let origin = map_pos(5, 1);
+        fov(&state, origin, Distance{n: 6}, &mut |pos| {
+            assert!(state.map().is_inboard(pos), "{:?}", pos);
+        });
+    }
+}
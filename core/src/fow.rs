@@ -1,12 +1,23 @@
 use std::default::{Default};
+use std::collections::{HashMap};
 use std::rc::{Rc};
-use types::{Size2};
 use game_state::{State};
 use map::{Map, Terrain, distance};
 use fov::{fov, simple_fov};
 use db::{Db};
-use unit::{Unit, UnitType};
-use ::{CoreEvent, PlayerId, MapPos, ExactPos, ObjectClass, SlotId};
+use unit::{Unit, UnitType, UnitTypeId};
+use ::{
+    CoreEvent,
+    PlayerId,
+    UnitId,
+    MapPos,
+    ExactPos,
+    ObjectId,
+    ObjectClass,
+    SlotId,
+    los_range,
+    cover_los_range,
+};
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
 pub enum TileVisibility {
@@ -27,12 +38,17 @@ fn calc_visibility(
     pos: MapPos,
 ) -> TileVisibility {
     let distance = distance(origin, pos);
-    if distance > unit_type.los_range {
+    if distance > los_range(state, unit_type) {
         return TileVisibility::No;
     }
-    if distance <= unit_type.cover_los_range {
+    if distance <= cover_los_range(state, unit_type) {
         return TileVisibility::Excellent;
     }
+    tile_visibility(state, pos)
+}
+
+/// Best visibility that terrain and objects of the tile allow from afar
+fn tile_visibility(state: &State, pos: MapPos) -> TileVisibility {
     let mut vis = match *state.map().tile(pos) {
         Terrain::City | Terrain::Trees => TileVisibility::Normal,
         Terrain::Plain | Terrain::Water => TileVisibility::Excellent,
@@ -40,11 +56,17 @@ fn calc_visibility(
     for object in state.objects_at(pos) {
         match object.class {
             // TODO: Removed Terrain::City and Terrain::Trees, use Smoke-like objects in logic
-            ObjectClass::Building | ObjectClass::Smoke => {
+            ObjectClass::Building |
+            ObjectClass::Rubble |
+            ObjectClass::Smoke => {
                 vis = TileVisibility::Normal;
             }
             ObjectClass::Road |
-            ObjectClass::ReinforcementSector => {},
+            ObjectClass::Bridge |
+            ObjectClass::ReinforcementSector |
+            ObjectClass::River |
+            ObjectClass::Wall |
+            ObjectClass::Hedge => {},
         }
     }
     vis
@@ -55,15 +77,22 @@ fn calc_visibility(
 pub struct Fow {
     map: Map<TileVisibility>,
     air_map: Map<TileVisibility>,
+    /// Enemies that gave away their positions by firing.
+    /// Other units in their tiles stay hidden.
+    revealed_units: HashMap<UnitId, ExactPos>,
+    /// Objects the player knows about and where they were seen
+    known_objects: HashMap<ObjectId, ExactPos>,
     player_id: PlayerId,
     db: Rc<Db>,
 }
 
 impl Fow {
-    pub fn new(db: Rc<Db>, map_size: Size2, player_id: PlayerId) -> Fow {
+    pub fn new(db: Rc<Db>, map: &Map<Terrain>, player_id: PlayerId) -> Fow {
         Fow {
-            map: Map::new(map_size),
-            air_map: Map::new(map_size),
+            map: Map::new_like(map),
+            air_map: Map::new_like(map),
+            revealed_units: HashMap::new(),
+            known_objects: HashMap::new(),
             player_id: player_id,
             db: db,
         }
@@ -77,15 +106,47 @@ impl Fow {
         }
     }
 
+    /// Objects of the map are known to everybody from the start
+    pub fn remember_objects(&mut self, state: &State) {
+        for (&id, object) in state.objects() {
+            self.known_objects.insert(id, object.pos);
+        }
+    }
+
+    pub fn known_object_pos(&self, id: ObjectId) -> Option<ExactPos> {
+        self.known_objects.get(&id).cloned()
+    }
+
+    pub fn known_objects(&self) -> Vec<(ObjectId, ExactPos)> {
+        self.known_objects.iter().map(|(&id, &pos)| (id, pos)).collect()
+    }
+
+    pub fn is_object_known(&self, id: ObjectId) -> bool {
+        self.known_objects.contains_key(&id)
+    }
+
+    /// Is any tile of the object's position visible?
+    pub fn is_object_pos_visible(&self, pos: ExactPos) -> bool {
+        pos.map_pos_iter().any(|map_pos| {
+            self.map.is_inboard(map_pos) && self.is_ground_tile_visible(map_pos)
+        })
+    }
+
     pub fn is_visible(&self, unit: &Unit) -> bool {
         self.is_visible_at(unit, unit.pos)
     }
 
     pub fn is_visible_at(&self, unit: &Unit, pos: ExactPos) -> bool {
+        self.revealed_units.get(&unit.id) == Some(&pos)
+            || self.is_type_visible_at(unit.type_id, pos)
+    }
+
+    /// Would a unit of this type be visible at the position?
+    pub fn is_type_visible_at(&self, type_id: UnitTypeId, pos: ExactPos) -> bool {
         if pos.slot_id == SlotId::Air {
             *self.air_map.tile(pos.map_pos) != TileVisibility::No
         } else {
-            let unit_type = self.db.unit_type(unit.type_id);
+            let unit_type = self.db.unit_type(type_id);
             match *self.map.tile(pos.map_pos) {
                 TileVisibility::Excellent => true,
                 TileVisibility::Normal => !unit_type.is_infantry,
@@ -98,7 +159,7 @@ impl Fow {
         assert!(unit.is_alive);
         let origin = unit.pos.map_pos;
         let unit_type = self.db.unit_type(unit.type_id);
-        let range = unit_type.los_range;
+        let range = los_range(state, unit_type);
         let ground_fow = &mut self.map;
         let ground_cb = &mut |pos| {
             let vis = calc_visibility(state, unit_type, origin, pos);
@@ -126,6 +187,11 @@ impl Fow {
 
     fn reset(&mut self, state: &State) {
         self.clear();
+        self.look_around(state);
+    }
+
+    /// Adds what all units see now to what was seen earlier this turn
+    fn look_around(&mut self, state: &State) {
         for (_, unit) in state.units() {
             if unit.player_id == self.player_id && unit.is_alive {
                 self.fov_unit(state, unit);
@@ -133,6 +199,31 @@ impl Fow {
         }
     }
 
+    /// Objects change visibility only around themselves, so
+    /// what was seen earlier this turn doesn't have to be forgotten
+    fn update_object_area(&mut self, state: &State, pos: ExactPos) {
+        for map_pos in pos.map_pos_iter() {
+            if !self.map.is_inboard(map_pos) {
+                continue;
+            }
+            let max_vis = tile_visibility(state, map_pos);
+            let vis = self.map.tile_mut(map_pos);
+            if *vis > max_vis {
+                *vis = max_vis;
+            }
+        }
+        for (_, unit) in state.units() {
+            if unit.player_id != self.player_id || !unit.is_alive {
+                continue;
+            }
+            let range = los_range(state, self.db.unit_type(unit.type_id));
+            let origin = unit.pos.map_pos;
+            if pos.map_pos_iter().any(|p| distance(origin, p) <= range) {
+                self.fov_unit(state, unit);
+            }
+        }
+    }
+
     pub fn apply_event(
         &mut self,
         state: &State,
@@ -147,6 +238,7 @@ impl Fow {
             },
             CoreEvent::EndTurn{new_id, ..} => {
                 if self.player_id == new_id {
+                    self.revealed_units.clear();
                     self.reset(state);
                 }
             },
@@ -160,33 +252,194 @@ impl Fow {
                 if let Some(attacker_id) = attack_info.attacker_id {
                     if !attack_info.is_ambush {
                         let pos = state.unit(attacker_id).pos;
-                        // TODO: do not give away all units in this tile!
-                        *self.map.tile_mut(pos) = TileVisibility::Excellent;
+                        self.revealed_units.insert(attacker_id, pos);
                     }
                 }
             },
+            CoreEvent::DamageObject{attacker_id: Some(attacker_id), ..} => {
+                let pos = state.unit(attacker_id).pos;
+                self.revealed_units.insert(attacker_id, pos);
+            },
             CoreEvent::UnloadUnit{ref unit_info, ..} => {
                 if self.player_id == unit_info.player_id {
                     let unit = state.unit(unit_info.id);
                     self.fov_unit(state, unit);
                 }
             },
+            CoreEvent::BailOut{ref unit_info, ..} => {
+                if self.player_id == unit_info.player_id {
+                    let unit = state.unit(unit_info.id);
+                    self.fov_unit(state, unit);
+                }
+            },
+            CoreEvent::Split{unit_info: Some(ref unit_info), ..} => {
+                if self.player_id == unit_info.player_id {
+                    let unit = state.unit(unit_info.id);
+                    self.fov_unit(state, unit);
+                }
+            },
+            CoreEvent::Capture{ref unit_info, ..} => {
+                if self.player_id == unit_info.player_id {
+                    let unit = state.unit(unit_info.id);
+                    self.fov_unit(state, unit);
+                }
+            },
             CoreEvent::Detach{transporter_id, ..} => {
                 let transporter = state.unit(transporter_id);
                 if self.player_id == transporter.player_id {
                     self.fov_unit(state, transporter);
                 }
             },
+            CoreEvent::CreateObject{id, ref object} => {
+                self.known_objects.insert(id, object.pos);
+                self.update_object_area(state, object.pos);
+            },
+            CoreEvent::RemoveObject{id} => {
+                if let Some(pos) = self.known_objects.remove(&id) {
+                    self.update_object_area(state, pos);
+                }
+            },
+            CoreEvent::WeatherChanged{..} => {
+                self.look_around(state);
+            },
             CoreEvent::Reveal{..} |
             CoreEvent::ShowUnit{..} |
             CoreEvent::HideUnit{..} |
             CoreEvent::LoadUnit{..} |
             CoreEvent::Attach{..} |
             CoreEvent::SetReactionFireMode{..} |
+            CoreEvent::SetMoveOrder{..} |
+            CoreEvent::CancelMoveOrder{..} |
             CoreEvent::SectorOwnerChanged{..} |
-            CoreEvent::Smoke{..} |
-            CoreEvent::RemoveSmoke{..} |
+            CoreEvent::DamageObject{..} |
+            CoreEvent::Entrench{..} |
+            CoreEvent::EngineeringWork{..} |
+            CoreEvent::FireSmoke{..} |
+            CoreEvent::KillPassenger{..} |
+            CoreEvent::Split{unit_info: None, ..} |
+            CoreEvent::Merge{..} |
             CoreEvent::VictoryPoint{..} => {},
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use test_utils::{
+        options,
+        map01_options,
+        new_state,
+        new_fow,
+        add_unit,
+        add_core_unit,
+        map_pos,
+        smoke,
+    };
+    use ::{
+        AttackInfo,
+        Core,
+        CoreEvent,
+        ExactPos,
+        FireMode,
+        MoveMode,
+        MovePoints,
+        ObjectId,
+        PlayerId,
+        SlotId,
+        UnitId,
+    };
+
+    #[test]
+    fn test_objects_keep_what_was_seen() {
+        let (db, mut state) = new_state(&options());
+        let unit_id = add_unit(&db, &mut state, 0, "soldier", map_pos(1, 5));
+        let mut fow = new_fow(&db, &state, 0);
+        let seen_pos = map_pos(6, 5);
+        assert!(fow.is_ground_tile_visible(seen_pos));
+        // the soldier walks away, but what it saw this turn stays seen
+        let event = CoreEvent::Move {
+            unit_id: unit_id,
+            from: state.unit(unit_id).pos,
+            to: ExactPos{map_pos: map_pos(1, 12), slot_id: SlotId::Id(0)},
+            mode: MoveMode::Fast,
+            cost: MovePoints{n: 0},
+        };
+        state.apply_event(&event);
+        fow.apply_event(&state, &event);
+        assert!(fow.is_ground_tile_visible(seen_pos));
+        let event = CoreEvent::CreateObject {
+            id: ObjectId{id: 100},
+            object: smoke(map_pos(2, 12), 2),
+        };
+        state.apply_event(&event);
+        fow.apply_event(&state, &event);
+        assert!(fow.is_ground_tile_visible(seen_pos));
+    }
+
+    #[test]
+    fn test_smoke_hides_infantry() {
+        let (db, mut state) = new_state(&options());
+        add_unit(&db, &mut state, 0, "soldier", map_pos(1, 5));
+        let mut fow = new_fow(&db, &state, 0);
+        let type_id = db.unit_type_id("soldier");
+        let pos = ExactPos{map_pos: map_pos(4, 5), slot_id: SlotId::Id(0)};
+        assert!(fow.is_type_visible_at(type_id, pos));
+        let event = CoreEvent::CreateObject {
+            id: ObjectId{id: 100},
+            object: smoke(pos.map_pos, 2),
+        };
+        state.apply_event(&event);
+        fow.apply_event(&state, &event);
+        assert!(fow.is_ground_tile_visible(pos.map_pos));
+        assert!(!fow.is_type_visible_at(type_id, pos));
+        let event = CoreEvent::RemoveObject{id: ObjectId{id: 100}};
+        state.apply_event(&event);
+        fow.apply_event(&state, &event);
+        assert!(fow.is_type_visible_at(type_id, pos));
+    }
+
+    fn shot(attacker_id: UnitId, defender_id: UnitId, is_ambush: bool) -> CoreEvent {
+        CoreEvent::AttackUnit {
+            attack_info: AttackInfo {
+                attacker_id: Some(attacker_id),
+                defender_id: defender_id,
+                mode: FireMode::Active,
+                killed: 0,
+                suppression: 0,
+                remove_move_points: false,
+                is_ambush: is_ambush,
+                is_inderect: false,
+                leave_wrecks: true,
+                attacker_suppression: 0,
+                damage: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_only_firing_unit_is_revealed() {
+        let mut core = Core::new(&map01_options());
+        let unit_id = add_core_unit(&mut core, 0, "soldier", map_pos(1, 4));
+        // infantry in the forest is hidden from afar
+        let forest_pos = map_pos(4, 4);
+        let attacker_id = add_core_unit(&mut core, 1, "soldier", forest_pos);
+        let other_id = add_core_unit(&mut core, 1, "soldier", forest_pos);
+        let player_id = PlayerId{id: 0};
+        let is_visible = |core: &Core, id| {
+            core.players_info[&player_id].visible_enemies.contains(&id)
+        };
+        assert!(!is_visible(&core, attacker_id));
+        assert!(!is_visible(&core, other_id));
+        core.do_core_event(&shot(attacker_id, unit_id, true));
+        assert!(!is_visible(&core, attacker_id));
+        core.do_core_event(&shot(attacker_id, unit_id, false));
+        assert!(is_visible(&core, attacker_id));
+        assert!(!is_visible(&core, other_id));
+        // the revealed unit is hidden again when the player's turn begins
+        core.do_core_event(&CoreEvent::EndTurn {
+            old_id: PlayerId{id: 1},
+            new_id: player_id,
+        });
+        assert!(!is_visible(&core, attacker_id));
+    }
+}
@@ -1,16 +1,19 @@
 use std::collections::hash_map::{self, HashMap};
 use std::collections::{HashSet};
+use std::slice;
 use std::rc::{Rc};
 use cgmath::{Vector2};
 use types::{Size2};
 use unit::{Unit};
 use db::{Db};
-use map::{Map, Terrain};
+use map::{Map, Terrain, distance};
 use dir::{Dir};
 use fow::{Fow};
+use mapgen::{generate_map};
 use ::{
     CoreEvent,
     FireMode,
+    VehicleDamage,
     UnitId,
     ObjectId,
     Object,
@@ -23,51 +26,48 @@ use ::{
     PlayerId,
     Score,
     MovePoints,
+    MoveOrder,
+    Ghost,
+    GHOST_LIFETIME,
+    Distance,
     ReinforcementPoints,
     AttackPoints,
     Options,
+    Weather,
+    TimeOfDay,
+    ZocRule,
     get_free_slot_for_building,
+    is_new_round,
+    is_loaded_or_attached,
 };
 
 #[derive(Clone)]
 pub struct ObjectsAtIter<'a> {
-    it: hash_map::Iter<'a, ObjectId, Object>,
-    pos: MapPos,
-}
-
-impl<'a> ObjectsAtIter<'a> {
-    pub fn new(objects: &HashMap<ObjectId, Object>, pos: MapPos) -> ObjectsAtIter {
-        ObjectsAtIter{it: objects.iter(), pos: pos}
-    }
+    ids: slice::Iter<'a, ObjectId>,
+    objects: &'a HashMap<ObjectId, Object>,
 }
 
 impl<'a> Iterator for ObjectsAtIter<'a> {
     type Item = &'a Object;
 
     fn next(&mut self) -> Option<Self::Item> {
-        for (_, object) in &mut self.it {
-            for map_pos in object.pos.map_pos_iter() {
-                if self.pos == map_pos {
-                    return Some(object);
-                }
-            }
-        }
-        None
+        let objects = self.objects;
+        self.ids.next().map(|id| &objects[id])
     }
 }
 
 #[derive(Clone)]
 pub struct UnitsAtIter<'a> {
-    it: UnitIter<'a>,
-    pos: MapPos,
+    ids: slice::Iter<'a, UnitId>,
+    state: &'a State,
 }
 
 impl<'a> Iterator for UnitsAtIter<'a> {
     type Item = &'a Unit;
 
     fn next(&mut self) -> Option<Self::Item> {
-        for (_, unit) in &mut self.it {
-            if self.pos == unit.pos.map_pos {
+        for &id in &mut self.ids {
+            if let Some(unit) = self.state.unit_opt(id) {
                 return Some(unit);
             }
         }
@@ -75,6 +75,30 @@ impl<'a> Iterator for UnitsAtIter<'a> {
     }
 }
 
+fn add_object_to_index(
+    index: &mut Map<Vec<ObjectId>>,
+    id: ObjectId,
+    object: &Object,
+) {
+    for pos in object.pos.map_pos_iter() {
+        if index.is_inboard(pos) {
+            index.tile_mut(pos).push(id);
+        }
+    }
+}
+
+fn remove_object_from_index(
+    index: &mut Map<Vec<ObjectId>>,
+    id: ObjectId,
+    object: &Object,
+) {
+    for pos in object.pos.map_pos_iter() {
+        if index.is_inboard(pos) {
+            index.tile_mut(pos).retain(|&object_id| object_id != id);
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct UnitIter<'a> {
     iter: hash_map::Iter<'a, UnitId, Unit>,
@@ -103,7 +127,9 @@ pub struct State {
     sectors: HashMap<SectorId, Sector>,
     score: HashMap<PlayerId, Score>,
     reinforcement_points: HashMap<PlayerId, ReinforcementPoints>,
-    players_count: i32,
+    weather: Weather,
+    time_of_day: TimeOfDay,
+    zoc_rule: ZocRule,
     db: Rc<Db>,
 
     // If this field is None then the State is considered "Full State"
@@ -117,6 +143,24 @@ pub struct State {
 
     /// Hack for not filtering fogged units from ShowUnit events
     shown_unit_ids: HashSet<UnitId>,
+
+    // Per-tile index of units and objects (including fogged units),
+    // so `units_at` and `objects_at` don't have to scan everything.
+    // Maintained by `apply_event`.
+    unit_ids_at: Map<Vec<UnitId>>,
+    object_ids_at: Map<Vec<ObjectId>>,
+
+    move_orders: HashMap<UnitId, MoveOrder>,
+
+    // Last known positions of enemies that went out of sight.
+    // Only partial states have them.
+    ghosts: HashMap<UnitId, Ghost>,
+
+    // Enemy transporters that were seen taking somebody aboard.
+    // Passengers themselves are never kept in partial states.
+    occupied_transporter_ids: HashSet<UnitId>,
+
+    round: i32,
 }
 
 fn basic_state(db: Rc<Db>, options: &Options) -> State {
@@ -126,19 +170,39 @@ fn basic_state(db: Rc<Db>, options: &Options) -> State {
     let mut reinforcement_points = HashMap::new();
     reinforcement_points.insert(PlayerId{id: 0}, ReinforcementPoints{n: 10});
     reinforcement_points.insert(PlayerId{id: 1}, ReinforcementPoints{n: 10});
-    let (map, objects, sectors) = load_map(&options.map_name);
-    State {
+    let (map, objects, sectors) = load_map(options);
+    let unit_ids_at = Map::new_like(&map);
+    let object_ids_at = Map::new_like(&map);
+    let mut state = State {
         units: HashMap::new(),
-        objects: objects,
+        objects: HashMap::new(),
         map: map,
         sectors: sectors,
         score: score,
         reinforcement_points: reinforcement_points,
-        players_count: options.players_count,
+        weather: options.weather,
+        time_of_day: options.time_of_day,
+        zoc_rule: options.zoc_rule,
         db: db,
         fow: None,
         shown_unit_ids: HashSet::new(),
+        unit_ids_at: unit_ids_at,
+        object_ids_at: object_ids_at,
+        move_orders: HashMap::new(),
+        ghosts: HashMap::new(),
+        occupied_transporter_ids: HashSet::new(),
+        round: 1,
+    };
+    // map objects are created just like the ones spawned during the game
+    let mut ids: Vec<_> = objects.keys().cloned().collect();
+    ids.sort_by_key(|id| id.id);
+    for id in ids {
+        state.apply_event(&CoreEvent::CreateObject {
+            id: id,
+            object: objects[&id].clone(),
+        });
     }
+    state
 }
 
 impl State {
@@ -148,7 +212,8 @@ impl State {
 
     pub fn new_partial(db: Rc<Db>, options: &Options, id: PlayerId) -> State {
         let mut state = basic_state(db.clone(), options);
-        let fow = Fow::new(db, state.map().size(), id);
+        let mut fow = Fow::new(db, state.map(), id);
+        fow.remember_objects(&state);
         state.to_partial(fow);
         state
     }
@@ -167,6 +232,23 @@ impl State {
         self.fow.is_some()
     }
 
+    /// Towed unit can't move on its own so it's left behind
+    fn abandon_attached_unit(&mut self, transporter_id: UnitId) {
+        let attached_unit_id = match self.units.get_mut(&transporter_id) {
+            Some(transporter) => transporter.attached_unit_id.take(),
+            None => return,
+        };
+        if let Some(attached_unit_id) = attached_unit_id {
+            if let Some(attached_unit) = self.units.get_mut(&attached_unit_id) {
+                attached_unit.is_attached = false;
+                attached_unit.is_alive = false;
+                attached_unit.attack_points = Some(AttackPoints{n: 0});
+                attached_unit.reactive_attack_points = Some(AttackPoints{n: 0});
+                attached_unit.move_points = Some(MovePoints{n: 0});
+            }
+        }
+    }
+
     /// Converts active ap (attack points) to reactive
     fn convert_ap(&mut self, player_id: PlayerId) {
         for (_, unit) in &mut self.units {
@@ -186,6 +268,15 @@ impl State {
         }
     }
 
+    /// Shaken crews recover after a whole turn of inaction
+    fn rally_crews(&mut self, player_id: PlayerId) {
+        for (_, unit) in &mut self.units {
+            if unit.player_id == player_id {
+                unit.is_crew_shaken = false;
+            }
+        }
+    }
+
     fn refresh_units(&mut self, player_id: PlayerId) {
         for (_, unit) in &mut self.units {
             if unit.player_id == player_id {
@@ -211,6 +302,18 @@ impl State {
     fn add_unit(&mut self, unit: &Unit) {
         assert!(self.units.get(&unit.id).is_none());
         self.units.insert(unit.id, unit.clone());
+        self.ghosts.remove(&unit.id);
+    }
+
+    /// Forgets ghosts whose tiles are seen empty now
+    fn update_ghosts(&mut self) {
+        let fow = match self.fow {
+            Some(ref fow) => fow,
+            None => return,
+        };
+        self.ghosts.retain(|_, ghost| {
+            !fow.is_type_visible_at(ghost.type_id, ghost.pos)
+        });
     }
 
     pub fn units(&self) -> UnitIter {
@@ -243,11 +346,17 @@ impl State {
     }
 
     pub fn units_at(&self, pos: MapPos) -> UnitsAtIter {
-        UnitsAtIter{it: self.units(), pos: pos}
+        UnitsAtIter {
+            ids: self.unit_ids_at.tile(pos).iter(),
+            state: self,
+        }
     }
 
     pub fn objects_at(&self, pos: MapPos) -> ObjectsAtIter {
-        ObjectsAtIter::new(self.objects(), pos)
+        ObjectsAtIter {
+            ids: self.object_ids_at.tile(pos).iter(),
+            objects: &self.objects,
+        }
     }
 
     pub fn unit_at_opt(&self, pos: ExactPos) -> Option<&Unit> {
@@ -275,6 +384,25 @@ impl State {
         &self.sectors
     }
 
+    pub fn move_orders(&self) -> &HashMap<UnitId, MoveOrder> {
+        &self.move_orders
+    }
+
+    pub fn ghosts(&self) -> &HashMap<UnitId, Ghost> {
+        &self.ghosts
+    }
+
+    /// Enemy transporters are shown without their passengers,
+    /// so for them this only tells if somebody was seen boarding
+    pub fn is_transporter_occupied(&self, transporter: &Unit) -> bool {
+        transporter.passenger_id.is_some()
+            || self.occupied_transporter_ids.contains(&transporter.id)
+    }
+
+    pub fn round(&self) -> i32 {
+        self.round
+    }
+
     pub fn score(&self) -> &HashMap<PlayerId, Score> {
         &self.score
     }
@@ -283,6 +411,18 @@ impl State {
         &self.reinforcement_points
     }
 
+    pub fn weather(&self) -> Weather {
+        self.weather
+    }
+
+    pub fn zoc_rule(&self) -> ZocRule {
+        self.zoc_rule
+    }
+
+    pub fn time_of_day(&self) -> TimeOfDay {
+        self.time_of_day
+    }
+
     pub fn is_ground_tile_visible(&self, pos: MapPos) -> bool {
         if let Some(ref fow) = self.fow {
             fow.is_ground_tile_visible(pos)
@@ -291,12 +431,83 @@ impl State {
         }
     }
 
+    /// Units whose position or existence may be changed by the event
+    fn affected_unit_ids(&self, event: &CoreEvent) -> Vec<UnitId> {
+        let mut ids = match *event {
+            CoreEvent::Move{unit_id, ..}
+                | CoreEvent::HideUnit{unit_id} => vec![unit_id],
+            CoreEvent::CreateUnit{ref unit_info}
+                | CoreEvent::ShowUnit{ref unit_info}
+                | CoreEvent::UnloadUnit{ref unit_info, ..}
+                | CoreEvent::BailOut{ref unit_info, ..} => vec![unit_info.id],
+            CoreEvent::Split{ref unit_info, ..} => {
+                unit_info.iter().map(|unit| unit.id).collect()
+            },
+            CoreEvent::AttackUnit{ref attack_info} => {
+                vec![attack_info.defender_id]
+            },
+            CoreEvent::LoadUnit{passenger_id, ..}
+                | CoreEvent::KillPassenger{passenger_id, ..} => {
+                vec![passenger_id]
+            },
+            CoreEvent::Attach{transporter_id, attached_unit_id, ..} => {
+                vec![transporter_id, attached_unit_id]
+            },
+            CoreEvent::Detach{transporter_id, ..} => vec![transporter_id],
+            CoreEvent::Merge{unit_id, ref unit_info, ..}
+                | CoreEvent::Capture{unit_id, ref unit_info, ..} => {
+                vec![unit_id, unit_info.id]
+            },
+            CoreEvent::EndTurn{..}
+                | CoreEvent::SetMoveOrder{..}
+                | CoreEvent::CancelMoveOrder{..}
+                | CoreEvent::Reveal{..}
+                | CoreEvent::SetReactionFireMode{..}
+                | CoreEvent::SectorOwnerChanged{..}
+                | CoreEvent::WeatherChanged{..}
+                | CoreEvent::VictoryPoint{..}
+                | CoreEvent::CreateObject{..}
+                | CoreEvent::RemoveObject{..}
+                | CoreEvent::DamageObject{..}
+                | CoreEvent::Entrench{..}
+                | CoreEvent::EngineeringWork{..}
+                | CoreEvent::FireSmoke{..} => vec![],
+        };
+        // passengers and attached units share the position of their transporter
+        let mut linked_ids = Vec::new();
+        for id in &ids {
+            if let Some(unit) = self.units.get(id) {
+                linked_ids.extend(unit.passenger_id);
+                linked_ids.extend(unit.attached_unit_id);
+            }
+        }
+        ids.extend(linked_ids);
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    fn update_units_index(&mut self, old_positions: &[(UnitId, Option<MapPos>)]) {
+        for &(id, old_pos) in old_positions {
+            if let Some(pos) = old_pos {
+                self.unit_ids_at.tile_mut(pos).retain(|&unit_id| unit_id != id);
+            }
+            if let Some(unit) = self.units.get(&id) {
+                self.unit_ids_at.tile_mut(unit.pos.map_pos).push(id);
+            }
+        }
+    }
+
     pub fn apply_event(&mut self, event: &CoreEvent) {
+        let old_positions: Vec<_> = self.affected_unit_ids(event).into_iter()
+            .map(|id| (id, self.units.get(&id).map(|unit| unit.pos.map_pos)))
+            .collect();
         match *event {
             CoreEvent::Move{unit_id, to, cost, ..} => {
                 {
                     let unit = self.units.get_mut(&unit_id).unwrap();
                     unit.pos = to;
+                    unit.is_entrenched = false;
                     if let Some(ref mut move_points) = unit.move_points {
                         assert!(move_points.n > 0);
                         move_points.n -= cost.n;
@@ -321,12 +532,19 @@ impl State {
                 }
                 self.refresh_units(new_id);
                 self.convert_ap(old_id);
-                // TODO: timer ticks on every player's turn! O.o
-                for (_, object) in &mut self.objects {
-                    if let Some(ref mut timer) = object.timer {
-                        *timer -= 1;
-                        assert!(*timer >= 0);
+                self.rally_crews(old_id);
+                if is_new_round(new_id) {
+                    for (_, object) in &mut self.objects {
+                        if let Some(ref mut timer) = object.timer {
+                            *timer -= 1;
+                            assert!(*timer > 0);
+                        }
                     }
+                    self.round += 1;
+                    let round = self.round;
+                    self.ghosts.retain(|_, ghost| {
+                        round - ghost.round < GHOST_LIFETIME
+                    });
                 }
             },
             CoreEvent::CreateUnit{ref unit_info} => {
@@ -351,26 +569,40 @@ impl State {
                             move_points.n = 0;
                         }
                     }
+                    match attack_info.damage {
+                        Some(VehicleDamage::Immobilized) => {
+                            unit.is_immobilized = true;
+                        },
+                        Some(VehicleDamage::WeaponDisabled) => {
+                            unit.is_weapon_disabled = true;
+                        },
+                        Some(VehicleDamage::CrewShaken) => {
+                            unit.is_crew_shaken = true;
+                        },
+                        Some(VehicleDamage::Abandoned) => {
+                            // passengers and crew are handled
+                            // by BailOut or KillPassenger events
+                            unit.is_alive = false;
+                            unit.is_crew_shaken = false;
+                            unit.move_points = Some(MovePoints{n: 0});
+                            unit.attack_points = Some(AttackPoints{n: 0});
+                            unit.reactive_attack_points = Some(AttackPoints{n: 0});
+                        },
+                        None => {},
+                    }
                     count = unit.count;
                 }
+                if attack_info.damage == Some(VehicleDamage::Abandoned) {
+                    self.abandon_attached_unit(attack_info.defender_id);
+                    self.occupied_transporter_ids.remove(&attack_info.defender_id);
+                }
                 if count <= 0 {
-                    if let Some(passenger_id)
-                        = self.unit(attack_info.defender_id).passenger_id
-                    {
-                        self.units.remove(&passenger_id).unwrap();
-                    }
-                    if let Some(attached_unit_id)
-                        = self.unit(attack_info.defender_id).attached_unit_id
-                    {
-                        let attached_unit = self.units.get_mut(&attached_unit_id).unwrap();
-                        attached_unit.attack_points = Some(AttackPoints{n: 0});
-                        attached_unit.reactive_attack_points = Some(AttackPoints{n: 0});
-                        attached_unit.move_points = Some(MovePoints{n: 0});
-                    }
+                    // passengers are handled by BailOut
+                    // or KillPassenger events
+                    self.abandon_attached_unit(attack_info.defender_id);
+                    self.occupied_transporter_ids.remove(&attack_info.defender_id);
                     if attack_info.leave_wrecks {
                         let unit = self.units.get_mut(&attack_info.defender_id).unwrap();
-                        unit.attached_unit_id = None;
-                        unit.passenger_id = None;
                         unit.is_alive = false;
                     } else {
                         assert!(self.units.get(&attack_info.defender_id).is_some());
@@ -379,6 +611,7 @@ impl State {
                 }
                 if let Some(attacker_id) = attack_info.attacker_id {
                     if let Some(unit) = self.units.get_mut(&attacker_id) {
+                        unit.morale -= attack_info.attacker_suppression;
                         match attack_info.mode {
                             FireMode::Active => {
                                 if let Some(ref mut attack_points)
@@ -396,6 +629,19 @@ impl State {
                                     reactive_attack_points.n -= 1;
                                 }
                             },
+                            FireMode::CloseAssault => {
+                                if let Some(ref mut attack_points)
+                                    = unit.attack_points
+                                {
+                                    assert!(attack_points.n >= 1);
+                                    attack_points.n -= 1;
+                                }
+                                if let Some(ref mut move_points)
+                                    = unit.move_points
+                                {
+                                    move_points.n = 0;
+                                }
+                            },
                         }
                     }
                 }
@@ -407,10 +653,29 @@ impl State {
             },
             CoreEvent::HideUnit{unit_id} => {
                 assert!(self.units.get(&unit_id).is_some());
-                self.units.remove(&unit_id);
+                let unit = self.units.remove(&unit_id).unwrap();
+                // passengers and towed guns are remembered
+                // as a part of their transporter
+                if unit.is_alive && !is_loaded_or_attached(&unit) {
+                    self.ghosts.insert(unit_id, Ghost {
+                        type_id: unit.type_id,
+                        player_id: unit.player_id,
+                        pos: unit.pos,
+                        round: self.round,
+                    });
+                }
+                // an enemy that was seen boarding is hidden right away,
+                // only the fact that the transporter is occupied remains
+                if unit.is_loaded {
+                    for (&id, transporter) in &mut self.units {
+                        if transporter.passenger_id == Some(unit_id) {
+                            transporter.passenger_id = None;
+                            self.occupied_transporter_ids.insert(id);
+                        }
+                    }
+                }
             },
             CoreEvent::LoadUnit{passenger_id, transporter_id, to, ..} => {
-                // TODO: hide info about passenger from enemy player
                 if let Some(transporter_id) = transporter_id {
                     self.units.get_mut(&transporter_id)
                         .expect("Bad transporter_id")
@@ -420,6 +685,7 @@ impl State {
                     .expect("Bad passenger_id");
                 passenger.pos = to;
                 passenger.is_loaded = true;
+                passenger.is_entrenched = false;
                 if let Some(ref mut move_points) = passenger.move_points {
                     move_points.n = 0;
                 }
@@ -429,6 +695,7 @@ impl State {
                     self.units.get_mut(&transporter_id)
                         .expect("Bad transporter_id")
                         .passenger_id = None;
+                    self.occupied_transporter_ids.remove(&transporter_id);
                 }
                 if self.unit_opt(unit_info.id).is_some() {
                     let unit = self.units.get_mut(&unit_info.id).unwrap();
@@ -446,6 +713,7 @@ impl State {
                 {
                     let attached_unit = self.units.get_mut(&attached_unit_id).unwrap();
                     attached_unit.is_attached = true;
+                    attached_unit.is_entrenched = false;
                 }
                 let transporter = self.units.get_mut(&transporter_id).unwrap();
                 transporter.pos = to;
@@ -482,48 +750,142 @@ impl State {
                 let sector = self.sectors.get_mut(&sector_id).unwrap();
                 sector.owner_id = new_owner_id;
             },
+            CoreEvent::WeatherChanged{weather, time_of_day} => {
+                self.weather = weather;
+                self.time_of_day = time_of_day;
+            },
             CoreEvent::VictoryPoint{player_id, count, ..} => {
                 self.score.get_mut(&player_id).unwrap().n += count;
             },
-            CoreEvent::Smoke{pos, id, unit_id} => {
-                if let Some(unit_id) = unit_id {
-                    if let Some(unit) = self.units.get_mut(&unit_id) {
+            CoreEvent::CreateObject{id, ref object} => {
+                add_object_to_index(&mut self.object_ids_at, id, object);
+                self.objects.insert(id, object.clone());
+            },
+            CoreEvent::RemoveObject{id} => {
+                if let Some(object) = self.objects.remove(&id) {
+                    remove_object_from_index(&mut self.object_ids_at, id, &object);
+                }
+            },
+            CoreEvent::DamageObject{id, damage, attacker_id} => {
+                {
+                    let object = self.objects.get_mut(&id).unwrap();
+                    if let Some(ref mut structural_points) = object.structural_points {
+                        *structural_points -= damage;
+                    }
+                }
+                if let Some(attacker_id) = attacker_id {
+                    if let Some(unit) = self.units.get_mut(&attacker_id) {
                         if let Some(ref mut attack_points) = unit.attack_points {
-                            attack_points.n = 0;
+                            assert!(attack_points.n >= 1);
+                            attack_points.n -= 1;
                         }
                     }
                 }
-                let smoke_duration_in_turns = 3; // TODO: get from config
-                let timer = smoke_duration_in_turns * self.players_count - 1;
-                self.objects.insert(id, Object {
-                    class: ObjectClass::Smoke,
-                    pos: ExactPos {
-                        map_pos: pos,
-                        slot_id: SlotId::WholeTile,
+            },
+            CoreEvent::BailOut{transporter_id, ref unit_info, ..} => {
+                if let Some(transporter) = self.units.get_mut(&transporter_id) {
+                    if transporter.passenger_id == Some(unit_info.id) {
+                        transporter.passenger_id = None;
+                    }
+                }
+                self.occupied_transporter_ids.remove(&transporter_id);
+                self.units.insert(unit_info.id, unit_info.clone());
+            },
+            CoreEvent::KillPassenger{transporter_id, passenger_id} => {
+                if let Some(transporter) = self.units.get_mut(&transporter_id) {
+                    transporter.passenger_id = None;
+                }
+                self.occupied_transporter_ids.remove(&transporter_id);
+                self.units.remove(&passenger_id);
+            },
+            CoreEvent::Split{unit_id, count, ref unit_info} => {
+                {
+                    let unit = self.units.get_mut(&unit_id).unwrap();
+                    unit.count -= count;
+                }
+                if let Some(ref unit_info) = *unit_info {
+                    self.units.insert(unit_info.id, unit_info.clone());
+                }
+            },
+            CoreEvent::Merge{unit_id, ref unit_info, ..} => {
+                self.units.remove(&unit_id);
+                self.units.insert(unit_info.id, unit_info.clone());
+            },
+            CoreEvent::Capture{unit_id, crew_count, ref unit_info, ..} => {
+                let is_squad_empty = match self.units.get_mut(&unit_id) {
+                    Some(unit) => {
+                        // crewing a vehicle takes the rest of the turn
+                        unit.count -= crew_count;
+                        if let Some(ref mut move_points) = unit.move_points {
+                            move_points.n = 0;
+                        }
+                        if let Some(ref mut attack_points) = unit.attack_points {
+                            attack_points.n = 0;
+                        }
+                        unit.count <= 0
                     },
-                    timer: Some(timer),
-                    owner_id: None,
-                });
+                    None => false,
+                };
+                if is_squad_empty {
+                    self.units.remove(&unit_id);
+                }
+                self.units.insert(unit_info.id, unit_info.clone());
+            },
+            CoreEvent::Entrench{unit_id} => {
+                let unit = self.units.get_mut(&unit_id).unwrap();
+                unit.is_entrenched = true;
+                if let Some(ref mut move_points) = unit.move_points {
+                    move_points.n = 0;
+                }
+                if let Some(ref mut attack_points) = unit.attack_points {
+                    attack_points.n = 0;
+                }
+            },
+            CoreEvent::SetMoveOrder{unit_id, ref order} => {
+                self.move_orders.insert(unit_id, order.clone());
             },
-            CoreEvent::RemoveSmoke{id} => {
-                self.objects.remove(&id);
+            CoreEvent::CancelMoveOrder{unit_id} => {
+                self.move_orders.remove(&unit_id);
+            },
+            CoreEvent::FireSmoke{unit_id} => {
+                let unit = self.units.get_mut(&unit_id).unwrap();
+                if let Some(ref mut attack_points) = unit.attack_points {
+                    attack_points.n = 0;
+                }
+            },
+            CoreEvent::EngineeringWork{unit_id} => {
+                let unit = self.units.get_mut(&unit_id).unwrap();
+                if let Some(ref mut move_points) = unit.move_points {
+                    move_points.n = 0;
+                }
+                if let Some(ref mut attack_points) = unit.attack_points {
+                    attack_points.n = 0;
+                }
             },
         }
+        self.update_units_index(&old_positions);
+        // orders of removed units are not needed anymore
+        for &(id, _) in &old_positions {
+            if self.units.get(&id).is_none() {
+                self.move_orders.remove(&id);
+            }
+        }
         if self.fow.is_some() {
             let mut fow = self.to_full();
             fow.apply_event(self, event);
             self.to_partial(fow);
+            self.update_ghosts();
         }
     }
 }
 
-// TODO: create trees, buildings and roads like units - using event system
-fn add_object(objects: &mut HashMap<ObjectId, Object>, object: Object) {
+// Map builders collect objects here, `State` gets them as `CreateObject` events
+pub fn add_object(objects: &mut HashMap<ObjectId, Object>, object: Object) {
     let id = ObjectId{id: objects.len() as i32 + 1};
     objects.insert(id, object);
 }
 
-fn add_road(objects: &mut HashMap<ObjectId, Object>, path: &[MapPos]) {
+pub fn add_road(objects: &mut HashMap<ObjectId, Object>, path: &[MapPos]) {
     for window in path.windows(2) {
         let from = window[0];
         let to = window[1];
@@ -536,12 +898,48 @@ fn add_road(objects: &mut HashMap<ObjectId, Object>, path: &[MapPos]) {
             },
             timer: None,
             owner_id: None,
+            structural_points: None,
         };
         add_object(objects, object);
     }
 }
 
-fn add_reinforcement_sector(
+fn add_hexside(
+    objects: &mut HashMap<ObjectId, Object>,
+    class: ObjectClass,
+    pos: MapPos,
+    dir: Dir,
+) {
+    let object = Object {
+        class: class,
+        pos: ExactPos {
+            map_pos: pos,
+            slot_id: SlotId::TwoTiles(dir),
+        },
+        timer: None,
+        owner_id: None,
+        structural_points: None,
+    };
+    add_object(objects, object);
+}
+
+pub fn add_bridge(objects: &mut HashMap<ObjectId, Object>, pos: MapPos) {
+    // strong enough for tanks
+    let structural_points = 10;
+    let object = Object {
+        class: ObjectClass::Bridge,
+        pos: ExactPos {
+            map_pos: pos,
+            slot_id: SlotId::WholeTile,
+        },
+        timer: None,
+        owner_id: None,
+        structural_points: Some(structural_points),
+    };
+    add_object(objects, object);
+}
+
+pub fn add_reinforcement_sector(
     objects: &mut HashMap<ObjectId, Object>,
     pos: MapPos,
     owner_id: Option<PlayerId>,
@@ -554,17 +952,19 @@ fn add_reinforcement_sector(
         },
         timer: None,
         owner_id: owner_id,
+        structural_points: None,
     };
     add_object(objects, object);
 }
 
-fn add_buildings(
+pub fn add_buildings(
     map: &mut Map<Terrain>,
     objects: &mut HashMap<ObjectId, Object>,
     pos: MapPos,
     count: i32,
 ) {
     *map.tile_mut(pos) = Terrain::City;
+    let structural_points = 6;
     for _ in 0 .. count {
         let slot_id = get_free_slot_for_building(map, objects, pos).unwrap();
         let obj_pos = ExactPos{map_pos: pos, slot_id: slot_id};
@@ -573,17 +973,19 @@ fn add_buildings(
             pos: obj_pos,
             timer: None,
             owner_id: None,
+            structural_points: Some(structural_points),
         };
         add_object(objects, object);
     }
 }
 
-fn add_big_building(
+pub fn add_big_building(
     map: &mut Map<Terrain>,
     objects: &mut HashMap<ObjectId, Object>,
     pos: MapPos,
 ) {
     *map.tile_mut(pos) = Terrain::City;
+    let structural_points = 12;
     let object = Object {
         class: ObjectClass::Building,
         pos: ExactPos {
@@ -592,20 +994,27 @@ fn add_big_building(
         },
         timer: None,
         owner_id: None,
+        structural_points: Some(structural_points),
     };
     add_object(objects, object);
 }
 
-type MapInfo = (Map<Terrain>, HashMap<ObjectId, Object>, HashMap<SectorId, Sector>);
+pub type MapInfo = (Map<Terrain>, HashMap<ObjectId, Object>, HashMap<SectorId, Sector>);
 
 // TODO: read from scenario.json?
-fn load_map(map_name: &str) -> MapInfo {
-    match map_name {
+fn load_map(options: &Options) -> MapInfo {
+    match options.map_name.as_str() {
+        "generated" => {
+            let params = options.map_gen_params.as_ref()
+                .expect("No map generator params");
+            generate_map(params)
+        },
         "map01" => load_map_01(),
         "map02" => load_map_02(),
         "map03" => load_map_03(),
         "map04" => load_map_04(),
         "map05" => load_map_05(),
+        "map06" => load_map_06(),
         _ => unimplemented!(),
     }
 }
@@ -680,6 +1089,24 @@ fn load_map_01() -> MapInfo {
         MapPos{v: Vector2{x: 6, y: 10}},
         MapPos{v: Vector2{x: 7, y: 11}},
     ]);
+    add_bridge(&mut objects, MapPos{v: Vector2{x: 5, y: 8}});
+    for &((x, y), dir, class) in &[
+        ((7, 8), Dir::SouthWest, ObjectClass::River),
+        ((7, 8), Dir::SouthEast, ObjectClass::River),
+        ((8, 8), Dir::SouthWest, ObjectClass::River),
+        ((8, 8), Dir::SouthEast, ObjectClass::River),
+        ((9, 8), Dir::SouthWest, ObjectClass::River),
+        ((2, 4), Dir::East, ObjectClass::Hedge),
+        ((2, 4), Dir::NorthEast, ObjectClass::Hedge),
+        ((2, 5), Dir::East, ObjectClass::Hedge),
+        ((1, 3), Dir::NorthEast, ObjectClass::Hedge),
+        ((7, 4), Dir::East, ObjectClass::Wall),
+        ((7, 5), Dir::NorthEast, ObjectClass::Wall),
+        ((7, 6), Dir::East, ObjectClass::Wall),
+    ] {
+        let pos = MapPos{v: Vector2{x: x, y: y}};
+        add_hexside(&mut objects, class, pos, dir);
+    }
     for &((x, y), player_index) in &[
         ((0, 1), 0),
         ((0, 7), 0),
@@ -832,3 +1259,388 @@ fn load_map_05() -> MapInfo {
     }
     (map, objects, sectors)
 }
+
+// Hex-shaped board with holes
+fn load_map_06() -> MapInfo {
+    let map_size = Size2{w: 11, h: 11};
+    let mut objects = HashMap::new();
+    let mut map = Map::new(map_size);
+    let mut sectors = HashMap::new();
+    let center = MapPos{v: Vector2{x: 5, y: 5}};
+    let radius = Distance{n: 5};
+    for pos in map.get_iter() {
+        if distance(center, pos) > radius {
+            map.set_void(pos);
+        }
+    }
+    for &(x, y) in &[
+        (3, 7),
+        (7, 3),
+    ] {
+        map.set_void(MapPos{v: Vector2{x: x, y: y}});
+    }
+    for &((x, y), terrain) in &[
+        ((2, 5), Terrain::Water),
+        ((8, 5), Terrain::Water),
+        ((4, 2), Terrain::Trees),
+        ((3, 3), Terrain::Trees),
+        ((6, 8), Terrain::Trees),
+        ((7, 7), Terrain::Trees),
+    ] {
+        *map.tile_mut(MapPos{v: Vector2{x: x, y: y}}) = terrain;
+    }
+    for &((x, y), count) in &[
+        ((4, 5), 2),
+        ((5, 5), 3),
+        ((6, 5), 2),
+    ] {
+        let pos = MapPos{v: Vector2{x: x, y: y}};
+        add_buildings(&mut map, &mut objects, pos, count);
+    }
+    let road: Vec<MapPos> = (0 .. map_size.w)
+        .map(|x| MapPos{v: Vector2{x: x, y: 5}})
+        .collect();
+    add_road(&mut objects, &road);
+    for &(x, y) in &[
+        (2, 5),
+        (8, 5),
+    ] {
+        add_bridge(&mut objects, MapPos{v: Vector2{x: x, y: y}});
+    }
+    for &((x, y), player_index) in &[
+        ((0, 5), 0),
+        ((10, 5), 1),
+    ] {
+        add_reinforcement_sector(
+            &mut objects,
+            MapPos{v: Vector2{x: x, y: y}},
+            Some(PlayerId{id: player_index}),
+        );
+    }
+    sectors.insert(
+        SectorId{id: 0},
+        Sector {
+            positions: vec![
+                MapPos{v: Vector2{x: 4, y: 4}},
+                MapPos{v: Vector2{x: 5, y: 4}},
+                MapPos{v: Vector2{x: 4, y: 5}},
+                MapPos{v: Vector2{x: 5, y: 5}},
+                MapPos{v: Vector2{x: 6, y: 5}},
+                MapPos{v: Vector2{x: 4, y: 6}},
+                MapPos{v: Vector2{x: 5, y: 6}},
+            ],
+            owner_id: None,
+        },
+    );
+    (map, objects, sectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_utils::{
+        options,
+        new_state,
+        add_unit,
+        add_object,
+        big_building,
+        map_pos,
+        map01_options,
+        to_partial,
+        new_unit,
+    };
+    use ::{
+        CoreEvent,
+        AttackInfo,
+        FireMode,
+        VehicleDamage,
+        UnitId,
+        ExactPos,
+        MoveMode,
+        MovePoints,
+        AttackPoints,
+        PlayerId,
+        SlotId,
+        MapPos,
+        GHOST_LIFETIME,
+    };
+    use super::{State};
+
+    fn ids_at(state: &State, pos: MapPos) -> Vec<UnitId> {
+        let mut ids: Vec<_> = state.units_at(pos).map(|unit| unit.id).collect();
+        ids.sort();
+        ids
+    }
+
+    fn damage_event(defender_id: UnitId, damage: VehicleDamage) -> CoreEvent {
+        CoreEvent::AttackUnit {
+            attack_info: AttackInfo {
+                attacker_id: None,
+                defender_id: defender_id,
+                mode: FireMode::Active,
+                killed: 0,
+                suppression: 0,
+                remove_move_points: false,
+                is_ambush: false,
+                is_inderect: false,
+                leave_wrecks: true,
+                attacker_suppression: 0,
+                damage: Some(damage),
+            },
+        }
+    }
+
+    #[test]
+    fn test_entrench_takes_whole_turn() {
+        let (db, mut state) = new_state(&options());
+        let id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 5));
+        state.apply_event(&CoreEvent::Entrench{unit_id: id});
+        let unit = state.unit(id);
+        assert!(unit.is_entrenched);
+        assert_eq!(unit.move_points, Some(MovePoints{n: 0}));
+        assert_eq!(unit.attack_points, Some(AttackPoints{n: 0}));
+    }
+
+    #[test]
+    fn test_moving_leaves_trench() {
+        let (db, mut state) = new_state(&options());
+        let id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 5));
+        state.apply_event(&CoreEvent::Entrench{unit_id: id});
+        let from = state.unit(id).pos;
+        let to = ExactPos{map_pos: map_pos(6, 5), slot_id: SlotId::Id(0)};
+        // entrenched units have no move points left until the next turn
+        state.apply_event(&CoreEvent::EndTurn {
+            old_id: PlayerId{id: 0},
+            new_id: PlayerId{id: 0},
+        });
+        state.apply_event(&CoreEvent::Move {
+            unit_id: id,
+            from: from,
+            to: to,
+            mode: MoveMode::Fast,
+            cost: MovePoints{n: 1},
+        });
+        assert!(!state.unit(id).is_entrenched);
+    }
+
+    #[test]
+    fn test_close_assault_costs_move_points() {
+        let (db, mut state) = new_state(&options());
+        let attacker_id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 5));
+        let defender_id = add_unit(&db, &mut state, 1, "medium_tank", map_pos(6, 5));
+        state.apply_event(&CoreEvent::AttackUnit {
+            attack_info: AttackInfo {
+                attacker_id: Some(attacker_id),
+                defender_id: defender_id,
+                mode: FireMode::CloseAssault,
+                killed: 0,
+                suppression: 0,
+                remove_move_points: false,
+                is_ambush: false,
+                is_inderect: false,
+                leave_wrecks: true,
+                attacker_suppression: 20,
+                damage: None,
+            },
+        });
+        let attacker = state.unit(attacker_id);
+        assert_eq!(attacker.move_points, Some(MovePoints{n: 0}));
+        assert_eq!(attacker.attack_points, Some(AttackPoints{n: 1}));
+        assert_eq!(attacker.morale, 80);
+    }
+
+    #[test]
+    fn test_vehicle_damage() {
+        let (db, mut state) = new_state(&options());
+        let tank_id = add_unit(&db, &mut state, 0, "medium_tank", map_pos(5, 5));
+        state.apply_event(&damage_event(tank_id, VehicleDamage::Immobilized));
+        state.apply_event(&damage_event(tank_id, VehicleDamage::WeaponDisabled));
+        state.apply_event(&damage_event(tank_id, VehicleDamage::CrewShaken));
+        {
+            let tank = state.unit(tank_id);
+            assert!(tank.is_alive);
+            assert!(tank.is_immobilized);
+            assert!(tank.is_weapon_disabled);
+            assert!(tank.is_crew_shaken);
+        }
+        // only the crew recovers
+        state.apply_event(&CoreEvent::EndTurn {
+            old_id: PlayerId{id: 0},
+            new_id: PlayerId{id: 1},
+        });
+        let tank = state.unit(tank_id);
+        assert!(tank.is_immobilized);
+        assert!(tank.is_weapon_disabled);
+        assert!(!tank.is_crew_shaken);
+    }
+
+    #[test]
+    fn test_abandoned_vehicle() {
+        let (db, mut state) = new_state(&options());
+        let tank_id = add_unit(&db, &mut state, 0, "medium_tank", map_pos(5, 5));
+        state.apply_event(&damage_event(tank_id, VehicleDamage::Abandoned));
+        let tank = state.unit(tank_id);
+        assert!(!tank.is_alive);
+        assert_eq!(tank.move_points, Some(MovePoints{n: 0}));
+        assert_eq!(tank.attack_points, Some(AttackPoints{n: 0}));
+    }
+
+    #[test]
+    fn test_fire_smoke_takes_attack_points() {
+        let (db, mut state) = new_state(&options());
+        let id = add_unit(&db, &mut state, 0, "mortar", map_pos(5, 5));
+        state.apply_event(&CoreEvent::FireSmoke{unit_id: id});
+        let unit = state.unit(id);
+        assert_eq!(unit.attack_points, Some(AttackPoints{n: 0}));
+    }
+
+    #[test]
+    fn test_objects_index() {
+        let (_, mut state) = new_state(&options());
+        let pos = map_pos(5, 5);
+        assert_eq!(state.objects_at(pos).count(), 0);
+        let id = add_object(&mut state, big_building(pos));
+        assert_eq!(state.objects_at(pos).count(), 1);
+        state.apply_event(&CoreEvent::RemoveObject{id: id});
+        assert_eq!(state.objects_at(pos).count(), 0);
+    }
+
+    #[test]
+    fn test_map_objects_are_indexed() {
+        let (_, state) = new_state(&map01_options());
+        assert!(!state.objects().is_empty());
+        for (id, object) in state.objects() {
+            for pos in object.pos.map_pos_iter() {
+                if state.map().is_inboard(pos) {
+                    assert!(state.object_ids_at.tile(pos).contains(id));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_units_index() {
+        let (db, mut state) = new_state(&options());
+        let truck_id = add_unit(&db, &mut state, 0, "truck", map_pos(5, 5));
+        let soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(4, 5));
+        assert_eq!(ids_at(&state, map_pos(5, 5)), vec![truck_id]);
+        assert_eq!(ids_at(&state, map_pos(4, 5)), vec![soldier_id]);
+        let truck_pos = state.unit(truck_id).pos;
+        state.apply_event(&CoreEvent::LoadUnit {
+            transporter_id: Some(truck_id),
+            passenger_id: soldier_id,
+            from: state.unit(soldier_id).pos,
+            to: truck_pos,
+        });
+        assert_eq!(ids_at(&state, map_pos(4, 5)), vec![]);
+        assert_eq!(ids_at(&state, map_pos(5, 5)), vec![truck_id, soldier_id]);
+        // the passenger moves with its transporter
+        let new_truck_pos = ExactPos{map_pos: map_pos(6, 5), .. truck_pos};
+        state.apply_event(&CoreEvent::Move {
+            unit_id: truck_id,
+            from: truck_pos,
+            to: new_truck_pos,
+            mode: MoveMode::Fast,
+            cost: MovePoints{n: 1},
+        });
+        assert_eq!(ids_at(&state, map_pos(5, 5)), vec![]);
+        assert_eq!(ids_at(&state, map_pos(6, 5)), vec![truck_id, soldier_id]);
+        let unload_pos = ExactPos{map_pos: map_pos(6, 6), slot_id: SlotId::Id(0)};
+        let unit_info = {
+            let mut soldier = state.unit(soldier_id).clone();
+            soldier.pos = unload_pos;
+            soldier
+        };
+        state.apply_event(&CoreEvent::UnloadUnit {
+            unit_info: unit_info,
+            transporter_id: Some(truck_id),
+            from: new_truck_pos,
+            to: unload_pos,
+        });
+        assert_eq!(ids_at(&state, map_pos(6, 5)), vec![truck_id]);
+        assert_eq!(ids_at(&state, map_pos(6, 6)), vec![soldier_id]);
+        state.apply_event(&CoreEvent::AttackUnit {
+            attack_info: AttackInfo {
+                attacker_id: None,
+                defender_id: soldier_id,
+                mode: FireMode::Active,
+                killed: 4,
+                suppression: 0,
+                remove_move_points: false,
+                is_ambush: false,
+                is_inderect: false,
+                leave_wrecks: false,
+                attacker_suppression: 0,
+                damage: None,
+            },
+        });
+        assert_eq!(ids_at(&state, map_pos(6, 6)), vec![]);
+    }
+
+    fn end_round(state: &mut State) {
+        state.apply_event(&CoreEvent::EndTurn {
+            old_id: PlayerId{id: 1},
+            new_id: PlayerId{id: 0},
+        });
+    }
+
+    #[test]
+    fn test_ghosts() {
+        let (db, mut state) = new_state(&options());
+        let id = add_unit(&db, &mut state, 1, "soldier", map_pos(5, 5));
+        let pos = state.unit(id).pos;
+        state.apply_event(&CoreEvent::HideUnit{unit_id: id});
+        {
+            let ghost = &state.ghosts()[&id];
+            assert_eq!(ghost.pos, pos);
+            assert_eq!(ghost.player_id, PlayerId{id: 1});
+            assert_eq!(ghost.round, state.round);
+        }
+        // the unit is seen again
+        let unit_info = new_unit(&db, id, PlayerId{id: 1}, "soldier", pos);
+        state.apply_event(&CoreEvent::ShowUnit{unit_info: unit_info});
+        assert!(state.ghosts().is_empty());
+    }
+
+    #[test]
+    fn test_ghosts_are_forgotten() {
+        let (db, mut state) = new_state(&options());
+        let id = add_unit(&db, &mut state, 1, "soldier", map_pos(5, 5));
+        state.apply_event(&CoreEvent::HideUnit{unit_id: id});
+        for _ in 0 .. GHOST_LIFETIME - 1 {
+            end_round(&mut state);
+            assert!(state.ghosts().get(&id).is_some());
+        }
+        end_round(&mut state);
+        assert!(state.ghosts().get(&id).is_none());
+    }
+
+    #[test]
+    fn test_seen_empty_tile_has_no_ghost() {
+        let (db, mut state) = new_state(&options());
+        add_unit(&db, &mut state, 0, "soldier", map_pos(1, 5));
+        let near_id = add_unit(&db, &mut state, 1, "soldier", map_pos(2, 5));
+        let far_id = add_unit(&db, &mut state, 1, "soldier", map_pos(10, 5));
+        to_partial(&db, &mut state, 0);
+        state.apply_event(&CoreEvent::HideUnit{unit_id: near_id});
+        state.apply_event(&CoreEvent::HideUnit{unit_id: far_id});
+        assert!(state.ghosts().get(&near_id).is_none());
+        assert!(state.ghosts().get(&far_id).is_some());
+    }
+
+    #[test]
+    fn test_passengers_leave_no_ghosts() {
+        let (db, mut state) = new_state(&options());
+        let truck_id = add_unit(&db, &mut state, 1, "truck", map_pos(5, 5));
+        let soldier_id = add_unit(&db, &mut state, 1, "soldier", map_pos(4, 5));
+        state.apply_event(&CoreEvent::LoadUnit {
+            transporter_id: Some(truck_id),
+            passenger_id: soldier_id,
+            from: state.unit(soldier_id).pos,
+            to: state.unit(truck_id).pos,
+        });
+        state.apply_event(&CoreEvent::HideUnit{unit_id: soldier_id});
+        state.apply_event(&CoreEvent::HideUnit{unit_id: truck_id});
+        assert!(state.ghosts().get(&soldier_id).is_none());
+        assert!(state.ghosts().get(&truck_id).is_some());
+    }
+}
@@ -11,27 +11,31 @@ pub mod pathfinder;
 pub mod misc;
 pub mod types;
 pub mod check;
+pub mod fov;
 
 mod ai;
-mod fov;
 mod fow;
 mod filter;
+mod mapgen;
 
-use std::{cmp, fmt};
+#[cfg(test)]
+mod test_utils;
+
+use std::{cmp, fmt, iter};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::rc::{Rc};
 use rand::{thread_rng, Rng};
 use cgmath::{Vector2};
 use types::{Size2};
 use misc::{clamp};
-use game_state::{State, ObjectsAtIter};
-use map::{Map, Terrain};
-use pathfinder::{tile_cost};
-use unit::{Unit, UnitTypeId};
+use game_state::{State};
+use map::{Map, Terrain, distance, spiral_iter};
+use pathfinder::{Pathfinder, tile_cost};
+use unit::{Unit, UnitType, UnitTypeId, WeaponType};
 use db::{Db};
 use ai::{Ai};
 use fow::{Fow};
-use dir::{Dir};
+use dir::{Dir, dirs};
 use check::{check_command, check_attack};
 
 #[derive(PartialOrd, PartialEq, Eq, Hash, Clone, Copy, Debug)]
@@ -186,9 +190,17 @@ impl Sector {
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ObjectClass {
     Building,
+    Rubble,
     Road,
+    Bridge,
     Smoke,
     ReinforcementSector,
+
+    // Hexside features: `SlotId::TwoTiles` objects placed
+    // on the edge between two tiles
+    River,
+    Wall,
+    Hedge,
 }
 
 #[derive(Debug, PartialOrd, Ord, PartialEq, Eq, Hash, Clone, Copy)]
@@ -196,12 +208,13 @@ pub struct ObjectId {
     pub id: i32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Object {
     pub pos: ExactPos,
     pub class: ObjectClass,
     pub timer: Option<i32>,
     pub owner_id: Option<PlayerId>,
+    pub structural_points: Option<i32>,
 }
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -220,6 +233,7 @@ pub struct Player {
 pub enum FireMode {
     Active,
     Reactive,
+    CloseAssault,
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -234,9 +248,32 @@ pub enum MoveMode {
     Hunt,
 }
 
+/// Last known position of an enemy unit that went out of sight
+#[derive(Clone, PartialEq, Debug)]
+pub struct Ghost {
+    pub type_id: UnitTypeId,
+    pub player_id: PlayerId,
+    pub pos: ExactPos,
+    /// The round when the unit was seen the last time
+    pub round: i32,
+}
+
+/// Ghosts older than this number of rounds are forgotten
+pub const GHOST_LIFETIME: i32 = 3;
+
+/// Long-range movement that is continued automatically
+/// at the start of every owner's turn
+#[derive(Clone, PartialEq, Debug)]
+pub struct MoveOrder {
+    /// The unit goes through the waypoints one by one
+    pub waypoints: Vec<MapPos>,
+    pub mode: MoveMode,
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum Command {
     Move{unit_id: UnitId, path: Vec<ExactPos>, mode: MoveMode},
+    GroupMove{unit_ids: Vec<UnitId>, destination: MapPos, mode: MoveMode},
     EndTurn,
     CreateUnit{pos: ExactPos, type_id: UnitTypeId},
     AttackUnit{attacker_id: UnitId, defender_id: UnitId},
@@ -246,6 +283,52 @@ pub enum Command {
     Detach{transporter_id: UnitId, pos: ExactPos},
     SetReactionFireMode{unit_id: UnitId, mode: ReactionFireMode},
     Smoke{unit_id: UnitId, pos: MapPos},
+    Entrench{unit_id: UnitId},
+    Split{unit_id: UnitId, count: i32, pos: ExactPos},
+    Merge{unit_id: UnitId, into_id: UnitId},
+    CloseAssault{attacker_id: UnitId, defender_id: UnitId},
+    Capture{unit_id: UnitId, target_id: UnitId},
+    Demolish{unit_id: UnitId, object_id: ObjectId},
+    AttackObject{attacker_id: UnitId, object_id: ObjectId},
+    BuildBridge{unit_id: UnitId, pos: MapPos},
+    SetMoveOrder{unit_id: UnitId, order: MoveOrder},
+    CancelMoveOrder{unit_id: UnitId},
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Weather {
+    Clear,
+    Rain,
+    Fog,
+    Snow,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TimeOfDay {
+    Day,
+    Dusk,
+    Night,
+    Dawn,
+}
+
+/// How enemy units restrict movement through the tiles around them.
+/// Moving directly from one zone of control to another is always
+/// forbidden unless the rule is disabled.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ZocRule {
+    Disabled,
+    /// Entering a zone of control costs additional move points
+    Tax,
+    /// Entering a zone of control ends the movement
+    Stop,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VehicleDamage {
+    Immobilized,
+    WeaponDisabled,
+    CrewShaken,
+    Abandoned,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -259,6 +342,8 @@ pub struct AttackInfo {
     pub is_ambush: bool,
     pub is_inderect: bool,
     pub leave_wrecks: bool,
+    pub attacker_suppression: i32,
+    pub damage: Option<VehicleDamage>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -321,20 +406,79 @@ pub enum CoreEvent {
         sector_id: SectorId,
         new_owner_id: Option<PlayerId>,
     },
+    WeatherChanged {
+        weather: Weather,
+        time_of_day: TimeOfDay,
+    },
     VictoryPoint {
         player_id: PlayerId,
         pos: MapPos,
         count: i32,
     },
-    // TODO: CreateObject
-    Smoke {
+    CreateObject {
+        id: ObjectId,
+        object: Object,
+    },
+    RemoveObject {
         id: ObjectId,
-        pos: MapPos,
-        unit_id: Option<UnitId>,
     },
-    // TODO: RemoveObject
-    RemoveSmoke {
+    // `attacker_id` is None when a structure is hit
+    // by the fire aimed at its occupants
+    DamageObject {
         id: ObjectId,
+        damage: i32,
+        attacker_id: Option<UnitId>,
+    },
+    Entrench {
+        unit_id: UnitId,
+    },
+    // Engineers spend the whole turn on demolition or bridge building
+    EngineeringWork {
+        unit_id: UnitId,
+    },
+    // Followed by CreateObject events for every smoke cloud
+    FireSmoke {
+        unit_id: UnitId,
+    },
+    // Passenger or crew escaping from a destroyed vehicle
+    BailOut {
+        unit_info: Unit,
+        transporter_id: UnitId,
+        from: ExactPos,
+        to: ExactPos,
+    },
+    KillPassenger {
+        transporter_id: UnitId,
+        passenger_id: UnitId,
+    },
+    // `unit_info` is the detached squad, it's left out
+    // for enemies that can't see where the squad went
+    Split {
+        unit_id: UnitId,
+        count: i32,
+        unit_info: Option<Unit>,
+    },
+    // `unit_info` is the resulting squad
+    Merge {
+        unit_id: UnitId,
+        from: ExactPos,
+        unit_info: Unit,
+    },
+    // `unit_info` is the crewed vehicle or gun
+    Capture {
+        unit_id: UnitId,
+        crew_count: i32,
+        old_player_id: PlayerId,
+        unit_info: Unit,
+    },
+    // Also used for updating the order when a waypoint is reached
+    SetMoveOrder {
+        unit_id: UnitId,
+        order: MoveOrder,
+    },
+    // The order is completed or interrupted
+    CancelMoveOrder {
+        unit_id: UnitId,
     },
 }
 
@@ -345,6 +489,111 @@ pub fn move_cost_modifier(mode: MoveMode) -> i32 {
     }
 }
 
+/// Chance in percents that the weather changes at the end of a round
+pub const WEATHER_CHANGE_CHANCE: i32 = 20;
+
+fn time_of_day_duration(time_of_day: TimeOfDay) -> i32 {
+    match time_of_day {
+        TimeOfDay::Day => 6,
+        TimeOfDay::Dusk | TimeOfDay::Dawn => 1,
+        TimeOfDay::Night => 3,
+    }
+}
+
+fn next_time_of_day(time_of_day: TimeOfDay) -> TimeOfDay {
+    match time_of_day {
+        TimeOfDay::Day => TimeOfDay::Dusk,
+        TimeOfDay::Dusk => TimeOfDay::Night,
+        TimeOfDay::Night => TimeOfDay::Dawn,
+        TimeOfDay::Dawn => TimeOfDay::Day,
+    }
+}
+
+/// Object timers tick once per full round
+pub fn is_new_round(new_player_id: PlayerId) -> bool {
+    new_player_id.id == 0
+}
+
+pub fn is_hexside(class: ObjectClass) -> bool {
+    match class {
+        ObjectClass::River |
+        ObjectClass::Wall |
+        ObjectClass::Hedge => true,
+        ObjectClass::Building |
+        ObjectClass::Rubble |
+        ObjectClass::Road |
+        ObjectClass::Bridge |
+        ObjectClass::Smoke |
+        ObjectClass::ReinforcementSector => false,
+    }
+}
+
+/// Is the tile next to an enemy known to the `state`?
+/// Air units have no zone of control.
+pub fn is_in_enemy_zoc(
+    db: &Db,
+    state: &State,
+    player_id: PlayerId,
+    pos: MapPos,
+) -> bool {
+    for dir in dirs() {
+        let neighbour_pos = Dir::get_neighbour_pos(pos, dir);
+        if !state.map().is_inboard(neighbour_pos) {
+            continue;
+        }
+        for unit in state.units_at(neighbour_pos) {
+            let unit_type = db.unit_type(unit.type_id);
+            if unit.player_id != player_id
+                && unit.is_alive
+                && !unit_type.is_air
+                && !is_loaded_or_attached(unit)
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Returns a hexside feature on the edge between two neighbour tiles
+pub fn hexside_between(
+    state: &State,
+    pos1: MapPos,
+    pos2: MapPos,
+) -> Option<ObjectClass> {
+    if pos1 == pos2 {
+        return None;
+    }
+    for object in state.objects_at(pos1) {
+        if !is_hexside(object.class) {
+            continue;
+        }
+        if object.pos.map_pos_iter().any(|pos| pos == pos2) {
+            return Some(object.class);
+        }
+    }
+    None
+}
+
+/// Structural points a bridge needs to carry a big unit
+pub const BIG_UNIT_BRIDGE_CAPACITY: i32 = 6;
+
+/// Engineers can only build light bridges that don't carry big units
+pub const FIELD_BRIDGE_STRUCTURAL_POINTS: i32 = 4;
+
+/// Only heavy weapons can damage buildings and bridges
+pub const STRUCTURE_DAMAGE_THRESHOLD: i32 = 5;
+
+pub fn structure_damage(weapon_type: &WeaponType) -> i32 {
+    weapon_type.damage - STRUCTURE_DAMAGE_THRESHOLD
+}
+
+pub fn can_bridge_carry(unit_type: &UnitType, bridge: &Object) -> bool {
+    assert_eq!(bridge.class, ObjectClass::Bridge);
+    !unit_type.is_big
+        || bridge.structural_points.unwrap() >= BIG_UNIT_BRIDGE_CAPACITY
+}
+
 pub fn is_unit_in_object(unit: &Unit, object: &Object) -> bool {
     if unit.pos == object.pos {
         return true;
@@ -387,6 +636,17 @@ pub fn find_prev_player_unit_id(
     unreachable!()
 }
 
+/// Men needed to crew an abandoned vehicle or gun
+pub const CREW_COUNT: i32 = 2;
+
+/// How far from the destination group members look for free slots
+pub const GROUP_MOVE_RADIUS: Distance = Distance{n: 2};
+
+/// Wrecks have nobody left inside, abandoned units can be crewed again
+pub fn is_abandoned(unit: &Unit) -> bool {
+    !unit.is_alive && unit.count > 0
+}
+
 pub fn is_commandable(player_id: PlayerId, unit: &Unit) -> bool {
     unit.is_alive && unit.player_id == player_id
         && !is_loaded_or_attached(unit)
@@ -420,8 +680,9 @@ struct PlayerInfo {
 }
 
 impl PlayerInfo {
-    fn new(db: Rc<Db>, player_id: PlayerId, map_size: Size2) -> PlayerInfo {
-        let fow = Fow::new(db, map_size, player_id);
+    fn new(db: Rc<Db>, player_id: PlayerId, state: &State) -> PlayerInfo {
+        let mut fow = Fow::new(db, state.map(), player_id);
+        fow.remember_objects(state);
         PlayerInfo {
             fow: Some(fow),
             events: VecDeque::new(),
@@ -463,6 +724,10 @@ pub fn print_unit_info(db: &Db, unit: &Unit) {
     println!("  passenger_id: {:?}", unit.passenger_id);
     println!("  attached_unit_id: {:?}", unit.attached_unit_id);
     println!("  is_alive: {:?}", unit.is_alive);
+    println!("  is_entrenched: {:?}", unit.is_entrenched);
+    println!("  is_immobilized: {:?}", unit.is_immobilized);
+    println!("  is_weapon_disabled: {:?}", unit.is_weapon_disabled);
+    println!("  is_crew_shaken: {:?}", unit.is_crew_shaken);
     println!("type:");
     println!("  name: {}", unit_type.name);
     println!("  is_infantry: {}", unit_type.is_infantry);
@@ -519,6 +784,58 @@ pub struct Options {
     pub game_type: GameType,
     pub map_name: String,
     pub players_count: i32, // TODO: must it be defined by map/scenario?
+    pub weather: Weather,
+    pub time_of_day: TimeOfDay,
+    pub zoc_rule: ZocRule,
+    /// Used only when `map_name` is "generated"
+    pub map_gen_params: Option<MapGenParams>,
+}
+
+/// Procedural map generator settings.
+/// The same seed always gives the same map.
+#[derive(Clone, Debug)]
+pub struct MapGenParams {
+    pub size: Size2,
+    pub seed: u32,
+    /// City pairs, one city on each player's half of the map
+    pub cities_count: i32,
+    pub forests_count: i32,
+    pub lakes_count: i32,
+    /// Tiles in one forest or lake cluster
+    pub cluster_size: i32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MapStyle {
+    Mixed,
+    Forest,
+    Lakes,
+    Urban,
+}
+
+impl MapGenParams {
+    pub fn new(seed: u32) -> MapGenParams {
+        MapGenParams::with_style(seed, Size2{w: 12, h: 14}, MapStyle::Mixed)
+    }
+
+    /// Feature counts are given for a 12x14 map and grow with its area
+    pub fn with_style(seed: u32, size: Size2, style: MapStyle) -> MapGenParams {
+        let (cities_count, forests_count, lakes_count, cluster_size) = match style {
+            MapStyle::Mixed => (2, 3, 1, 5),
+            MapStyle::Forest => (1, 6, 0, 6),
+            MapStyle::Lakes => (2, 1, 3, 5),
+            MapStyle::Urban => (3, 1, 0, 4),
+        };
+        let scale = |n| n * size.w * size.h / (12 * 14);
+        MapGenParams {
+            size: size,
+            seed: seed,
+            cities_count: cmp::max(scale(cities_count), 1),
+            forests_count: scale(forests_count),
+            lakes_count: scale(lakes_count),
+            cluster_size: cluster_size,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -531,6 +848,9 @@ pub struct Core {
     players_info: HashMap<PlayerId, PlayerInfo>,
     next_unit_id: UnitId,
     next_object_id: ObjectId,
+    wind: Dir,
+    time_of_day_rounds: i32,
+    pathfinder: Pathfinder,
 }
 
 fn get_players_list(options: &Options) -> Vec<Player> {
@@ -550,17 +870,20 @@ fn get_players_list(options: &Options) -> Vec<Player> {
     )
 }
 
-fn get_player_info_lists(db: &Rc<Db>, map_size: Size2) -> HashMap<PlayerId, PlayerInfo> {
+fn get_player_info_lists(db: &Rc<Db>, state: &State) -> HashMap<PlayerId, PlayerInfo> {
     let mut map = HashMap::new();
     map.insert(PlayerId{id: 0}, PlayerInfo::new(
-        db.clone(), PlayerId{id: 0}, map_size));
+        db.clone(), PlayerId{id: 0}, state));
     map.insert(PlayerId{id: 1}, PlayerInfo::new(
-        db.clone(), PlayerId{id: 1}, map_size));
+        db.clone(), PlayerId{id: 1}, state));
     map
 }
 
-pub fn objects_at(objects: &HashMap<ObjectId, Object>, pos: MapPos) -> ObjectsAtIter {
-    ObjectsAtIter::new(objects, pos)
+// Linear search: there's no `State` with its index while the map is being built
+pub fn objects_at(objects: &HashMap<ObjectId, Object>, pos: MapPos) -> Vec<&Object> {
+    objects.values()
+        .filter(|object| object.pos.map_pos_iter().any(|p| p == pos))
+        .collect()
 }
 
 pub fn get_free_slot_for_building(
@@ -604,6 +927,9 @@ pub fn get_free_slot_id(
     type_id: UnitTypeId,
     pos: MapPos,
 ) -> Option<SlotId> {
+    if !state.map().is_inboard(pos) {
+        return None;
+    }
     let objects_at = state.objects_at(pos);
     let units_at = state.units_at(pos);
     let unit_type = db.unit_type(type_id);
@@ -619,9 +945,16 @@ pub fn get_free_slot_id(
         for object in objects_at {
             match object.class {
                 ObjectClass::Building => return None,
+                ObjectClass::Bridge => if !can_bridge_carry(unit_type, object) {
+                    return None;
+                },
+                ObjectClass::Rubble |
                 ObjectClass::Smoke |
                 ObjectClass::ReinforcementSector |
-                ObjectClass::Road => {},
+                ObjectClass::Road |
+                ObjectClass::River |
+                ObjectClass::Wall |
+                ObjectClass::Hedge => {},
             }
         }
         if units_at.count() == 0 {
@@ -647,9 +980,14 @@ pub fn get_free_slot_id(
                 SlotId::WholeTile => {
                     match object.class {
                         ObjectClass::Building => return None,
+                        ObjectClass::Rubble |
                         ObjectClass::Smoke |
                         ObjectClass::ReinforcementSector |
-                        ObjectClass::Road => {},
+                        ObjectClass::Road |
+                        ObjectClass::Bridge |
+                        ObjectClass::River |
+                        ObjectClass::Wall |
+                        ObjectClass::Hedge => {},
                     }
                 }
                 SlotId::TwoTiles(_) | SlotId::Air => {},
@@ -681,6 +1019,9 @@ pub fn is_exact_pos_free(
     type_id: UnitTypeId,
     pos: ExactPos,
 ) -> bool {
+    if !state.map().is_inboard(pos.map_pos) {
+        return false;
+    }
     let units_at = state.units_at(pos.map_pos);
     let unit_type = db.unit_type(type_id);
     if unit_type.is_big && !unit_type.is_air {
@@ -705,16 +1046,95 @@ pub fn is_exact_pos_free(
 fn cover_bonus(db: &Db, state: &State, defender: &Unit) -> i32 {
     let defender_type = db.unit_type(defender.type_id);
     if defender_type.is_infantry {
-        match *state.map().tile(defender.pos) {
+        let terrain_bonus = match *state.map().tile(defender.pos) {
             Terrain::Plain | Terrain::Water => 0,
             Terrain::Trees => 2,
             Terrain::City => 3,
+        };
+        let entrenchment_bonus = if defender.is_entrenched { 2 } else { 0 };
+        let mut rubble_bonus = 0;
+        for object in state.objects_at(defender.pos.map_pos) {
+            if object.class == ObjectClass::Rubble
+                && is_unit_in_object(defender, object)
+            {
+                rubble_bonus = 1;
+            }
         }
+        terrain_bonus + entrenchment_bonus + rubble_bonus
     } else {
         0
     }
 }
 
+/// Visibility in percents of the clear day visibility
+fn visibility_modifier(state: &State) -> i32 {
+    let weather_modifier = match state.weather() {
+        Weather::Clear => 100,
+        Weather::Rain | Weather::Snow => 75,
+        Weather::Fog => 50,
+    };
+    let time_of_day_modifier = match state.time_of_day() {
+        TimeOfDay::Day => 100,
+        TimeOfDay::Dusk | TimeOfDay::Dawn => 75,
+        TimeOfDay::Night => 50,
+    };
+    weather_modifier * time_of_day_modifier / 100
+}
+
+pub fn los_range(state: &State, unit_type: &UnitType) -> Distance {
+    let n = unit_type.los_range.n * visibility_modifier(state) / 100;
+    Distance{n: cmp::max(n, 1)}
+}
+
+pub fn cover_los_range(state: &State, unit_type: &UnitType) -> Distance {
+    let n = cmp::min(unit_type.cover_los_range.n, los_range(state, unit_type).n);
+    Distance{n: n}
+}
+
+fn visibility_hit_penalty(state: &State) -> i32 {
+    let weather_penalty = match state.weather() {
+        Weather::Clear => 0,
+        Weather::Rain | Weather::Snow => 1,
+        Weather::Fog => 2,
+    };
+    let time_of_day_penalty = match state.time_of_day() {
+        TimeOfDay::Day => 0,
+        TimeOfDay::Dusk | TimeOfDay::Dawn => 1,
+        TimeOfDay::Night => 2,
+    };
+    weather_penalty + time_of_day_penalty
+}
+
+/// Cover given by walls and hedges between the defender and the attacker
+fn hexside_cover_bonus(
+    db: &Db,
+    state: &State,
+    attacker: &Unit,
+    defender: &Unit,
+) -> i32 {
+    let defender_type = db.unit_type(defender.type_id);
+    if !defender_type.is_infantry || attacker.pos.slot_id == SlotId::Air {
+        return 0;
+    }
+    let from = attacker.pos.map_pos;
+    let to = defender.pos.map_pos;
+    let dist = distance(from, to);
+    let mut bonus = 0;
+    for dir in dirs() {
+        let neighbour_pos = Dir::get_neighbour_pos(to, dir);
+        if distance(from, neighbour_pos) >= dist {
+            continue;
+        }
+        let hexside_bonus = match hexside_between(state, to, neighbour_pos) {
+            Some(ObjectClass::Wall) => 2,
+            Some(ObjectClass::Hedge) => 1,
+            _ => 0,
+        };
+        bonus = cmp::max(bonus, hexside_bonus);
+    }
+    bonus
+}
+
 pub fn hit_chance(
     db: &Db,
     state: &State,
@@ -724,9 +1144,38 @@ pub fn hit_chance(
     let attacker_type = db.unit_type(attacker.type_id);
     let defender_type = db.unit_type(defender.type_id);
     let weapon_type = db.weapon_type(attacker_type.weapon_type_id);
-    let cover_bonus = cover_bonus(db, state, defender);
-    let hit_test_v = -7 - cover_bonus + defender_type.size
-        + weapon_type.accuracy + attacker_type.weapon_skill;
+    let cover_bonus = cover_bonus(db, state, defender)
+        + hexside_cover_bonus(db, state, attacker, defender);
+    let hit_test_v = -7 - cover_bonus - visibility_hit_penalty(state)
+        + defender_type.size + weapon_type.accuracy + attacker_type.weapon_skill;
+    let pierce_test_v = 10 + -defender_type.armor + weapon_type.ap;
+    let wound_test_v = 5 -defender_type.toughness + weapon_type.damage;
+    let hit_test_v = clamp(hit_test_v, 0, 10);
+    let pierce_test_v = clamp(pierce_test_v, 0, 10);
+    let wound_test_v = clamp(wound_test_v, 0, 10);
+    let k = (hit_test_v * pierce_test_v * wound_test_v) / 10;
+    HitChance{n: clamp(k, 0, 100)}
+}
+
+pub fn close_assault_hit_chance(
+    db: &Db,
+    state: &State,
+    attacker: &Unit,
+    defender: &Unit,
+) -> HitChance {
+    let attacker_type = db.unit_type(attacker.type_id);
+    let defender_type = db.unit_type(defender.type_id);
+    let weapon_type_id = match attacker_type.close_assault_weapon_type_id {
+        Some(id) => id,
+        None => return HitChance{n: 0},
+    };
+    let weapon_type = db.weapon_type(weapon_type_id);
+    // attackers sneak up using cover and grenades
+    // are thrown right through the windows
+    let attacker_cover_bonus = cover_bonus(db, state, attacker);
+    let defender_cover_bonus = cover_bonus(db, state, defender) / 2;
+    let hit_test_v = -7 - defender_cover_bonus + attacker_cover_bonus
+        + defender_type.size + weapon_type.accuracy + attacker_type.weapon_skill;
     let pierce_test_v = 10 + -defender_type.armor + weapon_type.ap;
     let wound_test_v = 5 -defender_type.toughness + weapon_type.damage;
     let hit_test_v = clamp(hit_test_v, 0, 10);
@@ -740,9 +1189,10 @@ impl Core {
     pub fn new(options: &Options) -> Core {
         let db = Rc::new(Db::new());
         let state = State::new_full(db.clone(), options);
-        let players_info = get_player_info_lists(&db, state.map().size());
+        let players_info = get_player_info_lists(&db, &state);
         let ai = Ai::new(db.clone(), options, PlayerId{id:1});
         let next_object_id = ObjectId{id: state.objects().len() as i32};
+        let pathfinder = Pathfinder::new(db.clone(), state.map());
         Core {
             state: state,
             players: get_players_list(options),
@@ -752,6 +1202,9 @@ impl Core {
             players_info: players_info,
             next_unit_id: UnitId{id: 0},
             next_object_id: next_object_id,
+            wind: Dir::from_int(thread_rng().gen_range(0, 6)),
+            time_of_day_rounds: 0,
+            pathfinder: pathfinder,
         }
     }
 
@@ -769,8 +1222,8 @@ impl Core {
         self.next_object_id
     }
 
-    fn get_killed_count(&self, attacker: &Unit, defender: &Unit) -> i32 {
-        let hit = self.attack_test(attacker, defender);
+    fn get_killed_count(&self, hit_chance: HitChance, defender: &Unit) -> i32 {
+        let hit = self.attack_test(hit_chance);
         if !hit {
             return 0;
         }
@@ -782,10 +1235,38 @@ impl Core {
         }
     }
 
-    fn attack_test(&self, attacker: &Unit, defender: &Unit) -> bool {
-        let k = hit_chance(&self.db, &self.state, attacker, defender).n;
+    /// Non-fatal damage instead of vehicle's destruction
+    fn get_vehicle_damage(&self, defender: &Unit) -> Option<VehicleDamage> {
+        let r = thread_rng().gen_range(0, 100);
+        let damage = if r < 50 {
+            return None;
+        } else if r < 70 {
+            VehicleDamage::Immobilized
+        } else if r < 85 {
+            VehicleDamage::WeaponDisabled
+        } else {
+            VehicleDamage::CrewShaken
+        };
+        // crew of a stuck vehicle bails out instead of waiting for the end
+        if damage == VehicleDamage::CrewShaken && defender.is_immobilized {
+            return Some(VehicleDamage::Abandoned);
+        }
+        // the same damage twice finishes the vehicle off
+        let is_repeated = match damage {
+            VehicleDamage::Immobilized => defender.is_immobilized,
+            VehicleDamage::WeaponDisabled => defender.is_weapon_disabled,
+            VehicleDamage::CrewShaken | VehicleDamage::Abandoned => false,
+        };
+        if is_repeated {
+            None
+        } else {
+            Some(damage)
+        }
+    }
+
+    fn attack_test(&self, hit_chance: HitChance) -> bool {
         let r = thread_rng().gen_range(0, 100);
-        r < k
+        r < hit_chance.n
     }
 
     fn player(&self) -> &Player {
@@ -822,20 +1303,44 @@ impl Core {
         }
         let attacker_type = self.db.unit_type(attacker.type_id);
         let weapon_type = self.db.weapon_type(attacker_type.weapon_type_id);
-        let hit_chance = hit_chance(&self.db, &self.state, attacker, defender);
-        let suppression = hit_chance.n / 2;
-        let killed = cmp::min(
-            defender.count, self.get_killed_count(attacker, defender));
+        let hit_chance = if fire_mode == FireMode::CloseAssault {
+            close_assault_hit_chance(&self.db, &self.state, attacker, defender)
+        } else {
+            hit_chance(&self.db, &self.state, attacker, defender)
+        };
+        let mut suppression = hit_chance.n / 2;
+        let mut killed = cmp::min(
+            defender.count, self.get_killed_count(hit_chance, defender));
+        let defender_type = self.db.unit_type(defender.type_id);
+        let mut damage = None;
+        if killed > 0 && !defender_type.is_infantry && !defender_type.is_air {
+            damage = self.get_vehicle_damage(defender);
+            if damage.is_some() {
+                killed = 0;
+            }
+        }
         let fow = self.players_info[&defender.player_id].fow();
         let is_visible = fow.is_visible(attacker);
         let ambush_chance = 70;
         let is_ambush = !is_visible
             && thread_rng().gen_range(1, 100) <= ambush_chance;
-        let per_death_suppression = 20;
-        let defender_type = self.db.unit_type(defender.type_id);
+        let mut per_death_suppression = 20;
+        if defender.is_entrenched {
+            suppression /= 2;
+            per_death_suppression /= 2;
+        }
         // TODO: destroyed helicopters must kill everyone
         // on the ground in their tile
         let leave_wrecks = !defender_type.is_infantry && !defender_type.is_air;
+        // failed close assault leaves the attackers exposed
+        let attacker_suppression = if fire_mode == FireMode::CloseAssault
+            && killed == 0
+            && damage.is_none()
+        {
+            thread_rng().gen_range(10, 40)
+        } else {
+            0
+        };
         let attack_info = AttackInfo {
             attacker_id: Some(attacker_id),
             defender_id: defender_id,
@@ -846,6 +1351,8 @@ impl Core {
             is_ambush: is_ambush,
             is_inderect: weapon_type.is_inderect,
             leave_wrecks: leave_wrecks,
+            attacker_suppression: attacker_suppression,
+            damage: damage,
         };
         Some(CoreEvent::AttackUnit{attack_info: attack_info})
     }
@@ -881,7 +1388,7 @@ impl Core {
             if is_loaded_or_attached(self.state.unit(enemy_unit_id)) {
                 continue;
             }
-            let event = {
+            let attack_info = {
                 let enemy_unit = self.state.unit(enemy_unit_id);
                 let unit = self.state.unit(unit_id);
                 if enemy_unit.player_id == unit.player_id {
@@ -898,12 +1405,12 @@ impl Core {
                     if hit_chance.n > 15 && !unit_type.is_air && stop_on_attack {
                         attack_info.remove_move_points = true;
                     }
-                    CoreEvent::AttackUnit{attack_info: attack_info}
+                    attack_info
                 } else {
                     continue;
                 }
             };
-            self.do_core_event(&event);
+            self.do_attack_event(&attack_info);
             result = ReactionFireResult::Attacked;
             if self.state.unit_opt(unit_id).is_none() {
                 return ReactionFireResult::Killed;
@@ -916,61 +1423,630 @@ impl Core {
         self.reaction_fire_internal(unit_id, false);
     }
 
-    pub fn next_player_id(&self, id: PlayerId) -> PlayerId {
-        let old_id = id.id;
-        let max_id = self.players.len() as i32;
-        PlayerId{id: if old_id + 1 == max_id {
-            0
+    fn do_attack_event(&mut self, attack_info: &AttackInfo) {
+        let defender = self.state.unit(attack_info.defender_id).clone();
+        let is_killed = defender.count - attack_info.killed <= 0;
+        // nobody gets out of a destroyed aircraft, passengers
+        // must be killed while the transporter still exists
+        if is_killed && !attack_info.leave_wrecks {
+            if let Some(passenger_id) = defender.passenger_id {
+                self.do_core_event(&CoreEvent::KillPassenger {
+                    transporter_id: defender.id,
+                    passenger_id: passenger_id,
+                });
+            }
+        }
+        self.do_core_event(&CoreEvent::AttackUnit {
+            attack_info: attack_info.clone(),
+        });
+        let is_abandoned = attack_info.damage == Some(VehicleDamage::Abandoned);
+        if (is_killed && attack_info.leave_wrecks) || is_abandoned {
+            self.evacuate_wreck(&defender);
+        }
+        self.damage_structures(attack_info, &defender);
+    }
+
+    fn damage_structures(&mut self, attack_info: &AttackInfo, defender: &Unit) {
+        let attacker_id = match attack_info.attacker_id {
+            Some(id) => id,
+            None => return,
+        };
+        if defender.pos.slot_id == SlotId::Air {
+            return;
+        }
+        let damage = {
+            let attacker = self.state.unit(attacker_id);
+            let attacker_type = self.db.unit_type(attacker.type_id);
+            let weapon_type_id = if attack_info.mode == FireMode::CloseAssault {
+                attacker_type.close_assault_weapon_type_id.unwrap()
+            } else {
+                attacker_type.weapon_type_id
+            };
+            structure_damage(self.db.weapon_type(weapon_type_id))
+        };
+        if damage <= 0 {
+            return;
+        }
+        let mut structure_ids = Vec::new();
+        for (&id, object) in self.state.objects() {
+            let is_structure = object.class == ObjectClass::Building
+                || object.class == ObjectClass::Bridge;
+            if is_structure && is_unit_in_object(defender, object) {
+                structure_ids.push(id);
+            }
+        }
+        for id in structure_ids {
+            self.damage_structure(id, damage, None);
+        }
+    }
+
+    fn damage_structure(
+        &mut self,
+        id: ObjectId,
+        damage: i32,
+        attacker_id: Option<UnitId>,
+    ) {
+        self.do_core_event(&CoreEvent::DamageObject {
+            id: id,
+            damage: damage,
+            attacker_id: attacker_id,
+        });
+        let structural_points = self.state.objects()[&id]
+            .structural_points.unwrap();
+        if structural_points <= 0 {
+            self.destroy_structure(id);
+        }
+    }
+
+    // Buildings collapse into rubble, bridges just disappear
+    fn destroy_structure(&mut self, id: ObjectId) {
+        let structure = self.state.objects()[&id].clone();
+        let mut occupants = Vec::new();
+        for unit in self.state.units_at(structure.pos.map_pos) {
+            if unit.is_alive
+                && !unit.is_loaded
+                && !unit.is_attached
+                && unit.pos.slot_id != SlotId::Air
+                && is_unit_in_object(unit, &structure)
+            {
+                occupants.push(unit.clone());
+            }
+        }
+        self.do_core_event(&CoreEvent::RemoveObject {
+            id: id,
+        });
+        if structure.class == ObjectClass::Building {
+            let rubble_id = self.get_new_object_id();
+            self.do_core_event(&CoreEvent::CreateObject {
+                id: rubble_id,
+                object: Object {
+                    class: ObjectClass::Rubble,
+                    structural_points: None,
+                    .. structure
+                },
+            });
+        }
+        let collapse_suppression = 50;
+        for occupant in occupants {
+            // the collapse never wipes out the whole squad
+            let attack_info = AttackInfo {
+                attacker_id: None,
+                defender_id: occupant.id,
+                mode: FireMode::Active,
+                killed: thread_rng().gen_range(0, occupant.count),
+                suppression: collapse_suppression,
+                remove_move_points: false,
+                is_ambush: false,
+                is_inderect: false,
+                leave_wrecks: false,
+                attacker_suppression: 0,
+                damage: None,
+            };
+            self.do_attack_event(&attack_info);
+        }
+    }
+
+    fn update_weather(&mut self) {
+        let old_weather = self.state.weather();
+        let old_time_of_day = self.state.time_of_day();
+        let mut time_of_day = old_time_of_day;
+        self.time_of_day_rounds += 1;
+        if self.time_of_day_rounds >= time_of_day_duration(time_of_day) {
+            self.time_of_day_rounds = 0;
+            time_of_day = next_time_of_day(time_of_day);
+        }
+        let weather = if thread_rng().gen_range(0, 100) < WEATHER_CHANGE_CHANCE {
+            let weathers = [Weather::Clear, Weather::Rain, Weather::Fog, Weather::Snow];
+            weathers[thread_rng().gen_range(0, weathers.len())]
         } else {
-            old_id + 1
-        }}
+            old_weather
+        };
+        if weather != old_weather || time_of_day != old_time_of_day {
+            self.do_core_event(&CoreEvent::WeatherChanged {
+                weather: weather,
+                time_of_day: time_of_day,
+            });
+        }
     }
 
-    fn check_command(&mut self, command: &Command) {
-        let id = self.current_player_id;
-        let mut i = self.players_info.get_mut(&id).unwrap();
-        self.state.to_partial(i.fow.take().unwrap());
-        if let Err(err) = check_command(&self.db, id, &self.state, command) {
-            panic!("Bad command: {:?} ({:?})", err, command);
+    /// Removes expired objects and lets the wind drift smoke clouds
+    fn update_timed_objects(&mut self) {
+        let mut objects = Vec::new();
+        for (&id, object) in self.state.objects() {
+            if object.timer.is_some() {
+                objects.push((id, object.clone()));
+            }
+        }
+        let drift_chance = 50;
+        for (id, object) in objects {
+            let is_drifting = object.class == ObjectClass::Smoke
+                && thread_rng().gen_range(0, 100) < drift_chance;
+            self.update_timed_object(id, object, is_drifting);
         }
-        i.fow = Some(self.state.to_full());
     }
 
-    fn simulation_step(&mut self, command: Command) {
-        match command {
-            Command::EndTurn => {
-                let old_id = self.current_player_id;
-                let new_id = self.next_player_id(old_id);
-                // TODO: extruct func
-                let mut end_turn_events = Vec::new();
-                for sector in self.state.sectors().values() {
-                    if let Some(player_id) = sector.owner_id {
-                        if player_id != new_id {
-                            continue;
-                        }
-                        end_turn_events.push(CoreEvent::VictoryPoint {
-                            player_id: player_id,
-                            pos: sector.center(),
-                            count: 1,
-                        });
-                    }
-                }
-                for (&object_id, object) in self.state.objects() {
-                    if let Some(timer) = object.timer {
-                        if timer <= 0 {
-                            end_turn_events.push(CoreEvent::RemoveSmoke {
-                                id: object_id,
-                            });
-                        }
-                    }
-                }
-                for event in end_turn_events {
-                    self.do_core_event(&event);
-                }
-                self.do_core_event(&CoreEvent::EndTurn {
-                    old_id: old_id,
-                    new_id: new_id,
-                });
+    /// Expires the object or lets the wind carry it to the next tile
+    fn update_timed_object(&mut self, id: ObjectId, object: Object, is_drifting: bool) {
+        let is_expired = object.timer.unwrap() <= 1;
+        if !is_expired && !is_drifting {
+            return;
+        }
+        self.do_core_event(&CoreEvent::RemoveObject {
+            id: id,
+        });
+        if is_expired {
+            return;
+        }
+        let pos = Dir::get_neighbour_pos(object.pos.map_pos, self.wind);
+        if !self.state.map().is_inboard(pos) {
+            // blown off the map
+            return;
+        }
+        let new_id = self.get_new_object_id();
+        self.do_core_event(&CoreEvent::CreateObject {
+            id: new_id,
+            object: Object {
+                pos: ExactPos {
+                    map_pos: pos,
+                    slot_id: object.pos.slot_id,
+                },
+                .. object
+            },
+        });
+    }
+
+    fn create_smoke(&mut self, pos: MapPos, duration: i32) {
+        let id = self.get_new_object_id();
+        self.do_core_event(&CoreEvent::CreateObject {
+            id: id,
+            object: Object {
+                class: ObjectClass::Smoke,
+                pos: ExactPos {
+                    map_pos: pos,
+                    slot_id: SlotId::WholeTile,
+                },
+                timer: Some(duration),
+                owner_id: None,
+                structural_points: None,
+            },
+        });
+    }
+
+    fn get_bail_out_pos(&self, type_id: UnitTypeId, pos: MapPos) -> Option<ExactPos> {
+        if let Some(exact_pos) = get_free_exact_pos(
+            &self.db, &self.state, type_id, pos)
+        {
+            return Some(exact_pos);
+        }
+        for dir in dirs() {
+            let neighbour_pos = Dir::get_neighbour_pos(pos, dir);
+            if !self.state.map().is_inboard(neighbour_pos) {
+                continue;
+            }
+            if let Some(exact_pos) = get_free_exact_pos(
+                &self.db, &self.state, type_id, neighbour_pos)
+            {
+                return Some(exact_pos);
+            }
+        }
+        None
+    }
+
+    fn passenger_bail_out_event(
+        &self,
+        wreck: &Unit,
+        passenger_id: UnitId,
+    ) -> CoreEvent {
+        let passenger = self.state.unit(passenger_id);
+        let survival_chance = 60;
+        let is_survived = thread_rng().gen_range(0, 100) < survival_chance;
+        let killed = thread_rng().gen_range(0, passenger.count);
+        self.passenger_fate_event(wreck, passenger_id, is_survived, killed)
+    }
+
+    /// Deterministic part of the passenger's bail out, rolls are made by the caller
+    fn passenger_fate_event(
+        &self,
+        wreck: &Unit,
+        passenger_id: UnitId,
+        is_survived: bool,
+        killed: i32,
+    ) -> CoreEvent {
+        let passenger = self.state.unit(passenger_id);
+        let bail_out_suppression = 50;
+        let pos = if is_survived {
+            self.get_bail_out_pos(passenger.type_id, wreck.pos.map_pos)
+        } else {
+            None
+        };
+        let pos = match pos {
+            Some(pos) => pos,
+            None => return CoreEvent::KillPassenger {
+                transporter_id: wreck.id,
+                passenger_id: passenger_id,
+            },
+        };
+        CoreEvent::BailOut {
+            unit_info: Unit {
+                pos: pos,
+                count: passenger.count - killed,
+                morale: cmp::max(passenger.morale - bail_out_suppression, 0),
+                move_points: Some(MovePoints{n: 0}),
+                attack_points: Some(AttackPoints{n: 0}),
+                reactive_attack_points: Some(AttackPoints{n: 0}),
+                is_loaded: false,
+                .. passenger.clone()
+            },
+            transporter_id: wreck.id,
+            from: wreck.pos,
+            to: pos,
+        }
+    }
+
+    fn crew_bail_out_event(&mut self, wreck: &Unit) -> Option<CoreEvent> {
+        let wreck_type = self.db.unit_type(wreck.type_id);
+        if wreck_type.is_transporter {
+            return None;
+        }
+        let crew_survival_chance = 50;
+        if thread_rng().gen_range(0, 100) >= crew_survival_chance {
+            return None;
+        }
+        let type_id = self.db.unit_type_id("soldier");
+        let pos = match self.get_bail_out_pos(type_id, wreck.pos.map_pos) {
+            Some(pos) => pos,
+            None => return None,
+        };
+        let crew_count = 2;
+        let crew_morale = 50;
+        let id = self.get_new_unit_id();
+        Some(CoreEvent::BailOut {
+            unit_info: Unit {
+                id: id,
+                player_id: wreck.player_id,
+                pos: pos,
+                type_id: type_id,
+                passenger_id: None,
+                attached_unit_id: None,
+                move_points: Some(MovePoints{n: 0}),
+                attack_points: Some(AttackPoints{n: 0}),
+                reactive_attack_points: Some(AttackPoints{n: 0}),
+                reaction_fire_mode: ReactionFireMode::Normal,
+                count: crew_count,
+                morale: crew_morale,
+                is_alive: true,
+                is_loaded: false,
+                is_attached: false,
+                is_entrenched: false,
+                is_immobilized: false,
+                is_weapon_disabled: false,
+                is_crew_shaken: false,
+            },
+            transporter_id: wreck.id,
+            from: wreck.pos,
+            to: pos,
+        })
+    }
+
+    fn evacuate_wreck(&mut self, wreck: &Unit) {
+        let event = if let Some(passenger_id) = wreck.passenger_id {
+            self.passenger_bail_out_event(wreck, passenger_id)
+        } else {
+            match self.crew_bail_out_event(wreck) {
+                Some(event) => event,
+                None => return,
+            }
+        };
+        self.do_core_event(&event);
+    }
+
+    pub fn next_player_id(&self, id: PlayerId) -> PlayerId {
+        let old_id = id.id;
+        let max_id = self.players.len() as i32;
+        PlayerId{id: if old_id + 1 == max_id {
+            0
+        } else {
+            old_id + 1
+        }}
+    }
+
+    fn check_command(&mut self, command: &Command) {
+        let id = self.current_player_id;
+        let mut i = self.players_info.get_mut(&id).unwrap();
+        self.state.to_partial(i.fow.take().unwrap());
+        if let Err(err) = check_command(&self.db, id, &self.state, command) {
+            panic!("Bad command: {:?} ({:?})", err, command);
+        }
+        i.fow = Some(self.state.to_full());
+    }
+
+    /// Returns true if the movement was interrupted
+    /// by reaction fire, newly spotted enemies or a hidden unit in the way.
+    /// This is shared by single, group and ordered moves: the path was planned
+    /// without knowing about the hidden unit, so none of them can go on.
+    fn move_unit(
+        &mut self,
+        unit_id: UnitId,
+        path: &[ExactPos],
+        mode: MoveMode,
+    ) -> bool {
+        let player_id = self.state.unit(unit_id).player_id;
+        for window in path.windows(2) {
+            let from = window[0];
+            let to = window[1];
+            // passengers and towed units share the position
+            // of their transporter, only the transporter is bumped into
+            let show_event = self.state.units_at(to.map_pos)
+                .find(|unit| unit.pos == to && !is_loaded_or_attached(unit))
+                .map(|unit| CoreEvent::Reveal {
+                    unit_info: unit.clone(),
+                });
+            // bumped into a hidden unit: the move stops in front of it
+            if let Some(event) = show_event {
+                self.do_core_event(&event);
+                return true;
+            }
+            let (move_event, is_too_expensive) = {
+                let cost = self.move_cost(unit_id, from, to, mode);
+                let unit = self.state.unit(unit_id);
+                // the path may have become more expensive
+                // since it was planned (e.g. a bridge was blown up)
+                let is_too_expensive = cost > unit.move_points.unwrap();
+                let move_event = CoreEvent::Move {
+                    unit_id: unit_id,
+                    from: from,
+                    to: to,
+                    mode: mode,
+                    cost: cost,
+                };
+                (move_event, is_too_expensive)
+            };
+            if is_too_expensive {
+                break;
+            }
+            let pre_visible_enemies = self.players_info[&player_id]
+                .visible_enemies.clone();
+            self.do_core_event(&move_event);
+            let reaction_fire_result = self.reaction_fire_internal(
+                unit_id, mode == MoveMode::Fast);
+            if reaction_fire_result != ReactionFireResult::None {
+                return true;
+            }
+            let i = &self.players_info[&player_id];
+            if pre_visible_enemies != i.visible_enemies {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Move cost as the player sees it,
+    /// zones of control of hidden enemies must not affect it
+    fn move_cost(
+        &mut self,
+        unit_id: UnitId,
+        from: ExactPos,
+        to: ExactPos,
+        mode: MoveMode,
+    ) -> MovePoints {
+        let player_id = self.state.unit(unit_id).player_id;
+        let i = self.players_info.get_mut(&player_id).unwrap();
+        self.state.to_partial(i.fow.take().unwrap());
+        let cost = {
+            let unit = self.state.unit(unit_id);
+            tile_cost(&self.db, &self.state, unit, from, to).n
+                * move_cost_modifier(mode)
+        };
+        i.fow = Some(self.state.to_full());
+        MovePoints{n: cost}
+    }
+
+    /// Plans the path using only the player's knowledge,
+    /// hidden enemies must not affect it
+    fn find_move_order_path(
+        &mut self,
+        unit_id: UnitId,
+        destination: MapPos,
+    ) -> Option<Vec<ExactPos>> {
+        let player_id = self.state.unit(unit_id).player_id;
+        let i = self.players_info.get_mut(&player_id).unwrap();
+        self.state.to_partial(i.fow.take().unwrap());
+        let path = {
+            let unit = self.state.unit(unit_id);
+            match get_free_exact_pos(
+                &self.db, &self.state, unit.type_id, destination
+            ) {
+                Some(pos) => self.pathfinder.find_path(&self.state, unit, pos),
+                None => None,
+            }
+        };
+        i.fow = Some(self.state.to_full());
+        path
+    }
+
+    /// Plans a path to the free slot closest to the group's destination
+    /// using only the player's knowledge.
+    /// Returns None if the unit is already there or can't get there.
+    fn find_group_move_path(
+        &mut self,
+        unit_id: UnitId,
+        destination: MapPos,
+    ) -> Option<Vec<ExactPos>> {
+        let player_id = self.state.unit(unit_id).player_id;
+        let i = self.players_info.get_mut(&player_id).unwrap();
+        self.state.to_partial(i.fow.take().unwrap());
+        let mut path = None;
+        {
+            let unit = self.state.unit(unit_id);
+            self.pathfinder.fill_map(&self.state, unit);
+            let positions = iter::once(destination)
+                .chain(spiral_iter(destination, GROUP_MOVE_RADIUS));
+            for pos in positions {
+                if pos == unit.pos.map_pos {
+                    break;
+                }
+                let exact_pos = match get_free_exact_pos(
+                    &self.db, &self.state, unit.type_id, pos
+                ) {
+                    Some(pos) => pos,
+                    None => continue,
+                };
+                if let Some(p) = self.pathfinder.get_path(exact_pos) {
+                    path = Some(p);
+                    break;
+                }
+            }
+        }
+        i.fow = Some(self.state.to_full());
+        path
+    }
+
+    /// Units are moved one by one so every path is planned
+    /// around the group members that have already arrived
+    fn group_move(
+        &mut self,
+        unit_ids: &[UnitId],
+        destination: MapPos,
+        mode: MoveMode,
+    ) {
+        let mut unit_ids = unit_ids.to_vec();
+        // the closest units take the central slots first
+        // so the rest of the group doesn't have to go around them
+        unit_ids.sort_by_key(|&id| {
+            distance(self.state.unit(id).pos.map_pos, destination).n
+        });
+        for unit_id in unit_ids {
+            // may be killed by reaction fire during the previous moves
+            let is_alive = match self.state.unit_opt(unit_id) {
+                Some(unit) => unit.is_alive,
+                None => false,
+            };
+            if !is_alive {
+                continue;
+            }
+            if let Some(path) = self.find_group_move_path(unit_id, destination) {
+                self.move_unit(unit_id, &path, mode);
+            }
+        }
+    }
+
+    /// Moves the unit towards its waypoints as far as its move points allow
+    fn execute_move_order(&mut self, unit_id: UnitId) {
+        loop {
+            let mut order = match self.state.move_orders().get(&unit_id) {
+                Some(order) => order.clone(),
+                None => return,
+            };
+            let (pos, can_move) = {
+                let unit = self.state.unit(unit_id);
+                let can_move = unit.is_alive
+                    && !unit.is_immobilized
+                    && !is_loaded_or_attached(unit);
+                (unit.pos.map_pos, can_move)
+            };
+            let destination = order.waypoints[0];
+            let is_completed = pos == destination && order.waypoints.len() == 1;
+            if !can_move || is_completed {
+                self.do_core_event(&CoreEvent::CancelMoveOrder {
+                    unit_id: unit_id,
+                });
+                return;
+            }
+            if pos == destination {
+                order.waypoints.remove(0);
+                self.do_core_event(&CoreEvent::SetMoveOrder {
+                    unit_id: unit_id,
+                    order: order,
+                });
+                continue;
+            }
+            let path = match self.find_move_order_path(unit_id, destination) {
+                Some(path) => path,
+                None => {
+                    // the waypoint is unreachable now
+                    self.do_core_event(&CoreEvent::CancelMoveOrder {
+                        unit_id: unit_id,
+                    });
+                    return;
+                },
+            };
+            let is_interrupted = self.move_unit(unit_id, &path, order.mode);
+            // the order of a killed unit is removed together with the unit
+            if is_interrupted && self.state.unit_opt(unit_id).is_some() {
+                self.do_core_event(&CoreEvent::CancelMoveOrder {
+                    unit_id: unit_id,
+                });
+            }
+            if is_interrupted
+                || self.state.unit(unit_id).pos.map_pos != destination
+            {
+                // no more move points, the order continues next turn
+                return;
+            }
+        }
+    }
+
+    fn execute_move_orders(&mut self, player_id: PlayerId) {
+        let mut unit_ids: Vec<_> = self.state.move_orders().keys()
+            .cloned()
+            .filter(|&id| self.state.unit(id).player_id == player_id)
+            .collect();
+        unit_ids.sort();
+        for unit_id in unit_ids {
+            self.execute_move_order(unit_id);
+        }
+    }
+
+    fn simulation_step(&mut self, command: Command) {
+        match command {
+            Command::EndTurn => {
+                let old_id = self.current_player_id;
+                let new_id = self.next_player_id(old_id);
+                // TODO: extruct func
+                let mut end_turn_events = Vec::new();
+                for sector in self.state.sectors().values() {
+                    if let Some(player_id) = sector.owner_id {
+                        if player_id != new_id {
+                            continue;
+                        }
+                        end_turn_events.push(CoreEvent::VictoryPoint {
+                            player_id: player_id,
+                            pos: sector.center(),
+                            count: 1,
+                        });
+                    }
+                }
+                for event in end_turn_events {
+                    self.do_core_event(&event);
+                }
+                if is_new_round(new_id) {
+                    self.update_timed_objects();
+                    self.update_weather();
+                }
+                self.do_core_event(&CoreEvent::EndTurn {
+                    old_id: old_id,
+                    new_id: new_id,
+                });
             },
             Command::CreateUnit{pos, type_id} => {
                 let event = {
@@ -993,61 +2069,68 @@ impl Core {
                             is_alive: true,
                             is_loaded: false,
                             is_attached: false,
+                            is_entrenched: false,
+                            is_immobilized: false,
+                            is_weapon_disabled: false,
+                            is_crew_shaken: false,
                         },
                     }
                 };
                 self.do_core_event(&event);
             },
             Command::Move{unit_id, path, mode} => {
-                let player_id = self.state.unit(unit_id).player_id;
-                for window in path.windows(2) {
-                    let from = window[0];
-                    let to = window[1];
-                    let show_event = self.state.unit_at_opt(to).and_then(|unit| {
-                        Some(CoreEvent::Reveal {
-                            unit_info: unit.clone(),
-                        })
-                    });
-                    if let Some(event) = show_event {
-                        self.do_core_event(&event);
-                        continue;
-                    }
-                    let move_event = {
-                        let unit = self.state.unit(unit_id);
-                        let cost = MovePoints {
-                            n: tile_cost(&self.db, &self.state, unit, from, to).n
-                                * move_cost_modifier(mode)
-                        };
-                        CoreEvent::Move {
-                            unit_id: unit_id,
-                            from: from,
-                            to: to,
-                            mode: mode,
-                            cost: cost,
-                        }
-                    };
-                    let pre_visible_enemies = self.players_info[&player_id]
-                        .visible_enemies.clone();
-                    self.do_core_event(&move_event);
-                    let reaction_fire_result = self.reaction_fire_internal(
-                        unit_id, mode == MoveMode::Fast);
-                    if reaction_fire_result != ReactionFireResult::None {
-                        break;
-                    }
-                    let i = &self.players_info[&player_id];
-                    if pre_visible_enemies != i.visible_enemies {
-                        break;
-                    }
-                }
+                self.move_unit(unit_id, &path, mode);
+            },
+            Command::GroupMove{unit_ids, destination, mode} => {
+                self.group_move(&unit_ids, destination, mode);
             },
             Command::AttackUnit{attacker_id, defender_id} => {
-                if let Some(ref event) = self.command_attack_unit_to_event(
-                    attacker_id, defender_id, FireMode::Active)
+                if let Some(CoreEvent::AttackUnit{ref attack_info})
+                    = self.command_attack_unit_to_event(
+                        attacker_id, defender_id, FireMode::Active)
                 {
-                    self.do_core_event(event);
+                    self.do_attack_event(attack_info);
                     self.reaction_fire(attacker_id);
                 }
             },
+            Command::CloseAssault{attacker_id, defender_id} => {
+                // defenders open fire at the approaching assault group
+                let result = self.reaction_fire_internal(attacker_id, false);
+                if result != ReactionFireResult::Killed {
+                    if let Some(CoreEvent::AttackUnit{ref attack_info})
+                        = self.command_attack_unit_to_event(
+                            attacker_id, defender_id, FireMode::CloseAssault)
+                    {
+                        self.do_attack_event(attack_info);
+                        self.reaction_fire(attacker_id);
+                    }
+                }
+            },
+            Command::Capture{unit_id, target_id} => {
+                let event = {
+                    let unit = self.state.unit(unit_id);
+                    let target = self.state.unit(target_id);
+                    CoreEvent::Capture {
+                        unit_id: unit_id,
+                        crew_count: CREW_COUNT,
+                        old_player_id: target.player_id,
+                        unit_info: Unit {
+                            player_id: unit.player_id,
+                            morale: unit.morale,
+                            move_points: Some(MovePoints{n: 0}),
+                            attack_points: Some(AttackPoints{n: 0}),
+                            reactive_attack_points: Some(AttackPoints{n: 0}),
+                            is_alive: true,
+                            is_crew_shaken: false,
+                            // the new crew gets the vehicle going again,
+                            // but a disabled weapon can't be fixed in the field
+                            is_immobilized: false,
+                            .. target.clone()
+                        },
+                    }
+                };
+                self.do_core_event(&event);
+            },
             Command::LoadUnit{transporter_id, passenger_id} => {
                 let from = self.state.unit(passenger_id).pos;
                 let to = self.state.unit(transporter_id).pos;
@@ -1102,35 +2185,158 @@ impl Core {
                 });
             },
             Command::Smoke{unit_id, pos} => {
-                let id = self.get_new_object_id();
-                self.do_core_event(&CoreEvent::Smoke {
-                    id: id,
-                    unit_id: Some(unit_id),
-                    pos: pos,
-                });
-                let mut dir = Dir::from_int(thread_rng().gen_range(0, 5));
-                let additional_smoke_count = {
+                let smoke_info = {
                     let unit = self.state.unit(unit_id);
                     let unit_type = self.db.unit_type(unit.type_id);
                     let weapon_type = self.db.weapon_type(unit_type.weapon_type_id);
-                    weapon_type.smoke.unwrap()
+                    weapon_type.smoke.clone().unwrap()
                 };
-                assert!(additional_smoke_count <= 3);
-                for _ in 0..additional_smoke_count {
-                    let mut dir_index = dir.to_int() + thread_rng().gen_range(1, 3);
-                    if dir_index > 5 {
-                        dir_index -= 6;
+                self.do_core_event(&CoreEvent::FireSmoke {
+                    unit_id: unit_id,
+                });
+                self.create_smoke(pos, smoke_info.duration);
+                let rotation = thread_rng().gen_range(0, 6);
+                for dir in &smoke_info.pattern {
+                    let dir = Dir::from_int((dir.to_int() + rotation) % 6);
+                    let neighbour_pos = Dir::get_neighbour_pos(pos, dir);
+                    if self.state.map().is_inboard(neighbour_pos) {
+                        self.create_smoke(neighbour_pos, smoke_info.duration);
                     }
-                    dir = Dir::from_int(dir_index);
-                    let id = self.get_new_object_id();
-                    self.do_core_event(&CoreEvent::Smoke {
-                        id: id,
-                        unit_id: Some(unit_id),
-                        pos: Dir::get_neighbour_pos(pos, dir),
-                    });
                 }
                 self.reaction_fire(unit_id);
             },
+            Command::Entrench{unit_id} => {
+                self.do_core_event(&CoreEvent::Entrench {
+                    unit_id: unit_id,
+                });
+            },
+            Command::Demolish{unit_id, object_id} => {
+                self.do_core_event(&CoreEvent::EngineeringWork {
+                    unit_id: unit_id,
+                });
+                self.destroy_structure(object_id);
+            },
+            Command::AttackObject{attacker_id, object_id} => {
+                let damage = {
+                    let attacker = self.state.unit(attacker_id);
+                    let attacker_type = self.db.unit_type(attacker.type_id);
+                    structure_damage(self.db.weapon_type(attacker_type.weapon_type_id))
+                };
+                self.damage_structure(object_id, damage, Some(attacker_id));
+                self.reaction_fire(attacker_id);
+            },
+            Command::BuildBridge{unit_id, pos} => {
+                self.do_core_event(&CoreEvent::EngineeringWork {
+                    unit_id: unit_id,
+                });
+                let id = self.get_new_object_id();
+                self.do_core_event(&CoreEvent::CreateObject {
+                    id: id,
+                    object: Object {
+                        class: ObjectClass::Bridge,
+                        pos: ExactPos {
+                            map_pos: pos,
+                            slot_id: SlotId::WholeTile,
+                        },
+                        timer: None,
+                        owner_id: None,
+                        structural_points: Some(FIELD_BRIDGE_STRUCTURAL_POINTS),
+                    },
+                });
+            },
+            Command::SetMoveOrder{unit_id, order} => {
+                self.do_core_event(&CoreEvent::SetMoveOrder {
+                    unit_id: unit_id,
+                    order: order,
+                });
+                self.execute_move_order(unit_id);
+            },
+            Command::CancelMoveOrder{unit_id} => {
+                self.do_core_event(&CoreEvent::CancelMoveOrder {
+                    unit_id: unit_id,
+                });
+            },
+            Command::Split{unit_id, count, pos} => {
+                let type_id = self.state.unit(unit_id).type_id;
+                let player_id = self.state.unit(unit_id).player_id;
+                if !is_exact_pos_free(&self.db, &self.state, type_id, pos) {
+                    // the destination is occupied by some hidden enemy
+                    let event = {
+                        let enemy = self.state.units_at(pos.map_pos)
+                            .find(|unit| {
+                                unit.player_id != player_id
+                                    && !is_loaded_or_attached(unit)
+                            })
+                            .expect("Can't find hidden enemy");
+                        CoreEvent::Reveal {
+                            unit_info: enemy.clone(),
+                        }
+                    };
+                    self.do_core_event(&event);
+                } else {
+                    let new_unit_id = self.get_new_unit_id();
+                    let event = {
+                        let unit = self.state.unit(unit_id);
+                        let mut move_points = unit.move_points.unwrap();
+                        let is_same_tile = unit.pos.map_pos == pos.map_pos;
+                        if !is_same_tile {
+                            move_points.n -= tile_cost(
+                                &self.db, &self.state, unit, unit.pos, pos).n;
+                        }
+                        CoreEvent::Split {
+                            unit_id: unit_id,
+                            count: count,
+                            unit_info: Some(Unit {
+                                id: new_unit_id,
+                                pos: pos,
+                                count: count,
+                                move_points: Some(move_points),
+                                is_entrenched: unit.is_entrenched && is_same_tile,
+                                .. unit.clone()
+                            }),
+                        }
+                    };
+                    self.do_core_event(&event);
+                    self.reaction_fire(new_unit_id);
+                }
+            },
+            Command::Merge{unit_id, into_id} => {
+                let event = {
+                    let unit = self.state.unit(unit_id);
+                    let into = self.state.unit(into_id);
+                    let mut move_points = unit.move_points.unwrap();
+                    if unit.pos.map_pos != into.pos.map_pos {
+                        move_points.n -= tile_cost(
+                            &self.db, &self.state, unit, unit.pos, into.pos).n;
+                    }
+                    // the merged squad is as tired and shaken
+                    // as the worst of its parts
+                    let min_points = |a: Option<AttackPoints>, b: Option<AttackPoints>| {
+                        Some(AttackPoints {
+                            n: cmp::min(a.unwrap().n, b.unwrap().n),
+                        })
+                    };
+                    CoreEvent::Merge {
+                        unit_id: unit_id,
+                        from: unit.pos,
+                        unit_info: Unit {
+                            count: into.count + unit.count,
+                            morale: cmp::min(into.morale, unit.morale),
+                            move_points: Some(MovePoints {
+                                n: cmp::min(into.move_points.unwrap().n, move_points.n),
+                            }),
+                            attack_points: min_points(
+                                into.attack_points, unit.attack_points),
+                            reactive_attack_points: min_points(
+                                into.reactive_attack_points,
+                                unit.reactive_attack_points,
+                            ),
+                            .. into.clone()
+                        },
+                    }
+                };
+                self.do_core_event(&event);
+            },
         };
         let sector_events = check_sectors(&self.db, &self.state);
         for event in sector_events {
@@ -1165,6 +2371,7 @@ impl Core {
                 break;
             }
         }
+        self.execute_move_orders(new_id);
         if self.player().class == PlayerClass::Ai
             && new_id == self.player_id()
         {
@@ -1186,6 +2393,10 @@ impl Core {
                 state, &active_unit_ids, &i.visible_enemies, &new_enemies);
             i.events.extend(show_hide_events);
             i.visible_enemies = new_enemies;
+            for object_event in filter::show_or_hide_objects(state, i.fow()) {
+                i.fow_mut().apply_event(state, &object_event);
+                i.events.push_back(object_event);
+            }
         }
     }
 
@@ -1205,7 +2416,75 @@ impl Core {
 #[cfg(test)]
 mod tests {
     use cgmath::{Vector2};
-    use ::{Sector, MapPos};
+    use dir::{Dir};
+    use map::{distance};
+    use pathfinder::{tile_cost};
+    use test_utils::{
+        options,
+        new_state,
+        add_unit,
+        add_core_unit,
+        add_core_object,
+        add_object,
+        big_building,
+        hexside,
+        smoke,
+        map_pos,
+        map01_options,
+        bad_weather_options,
+        load_unit,
+        killing_attack,
+    };
+    use ::{
+        Sector,
+        MapPos,
+        Core,
+        Command,
+        CoreEvent,
+        AttackInfo,
+        VehicleDamage,
+        MovePoints,
+        AttackPoints,
+        ExactPos,
+        SlotId,
+        ObjectClass,
+        Options,
+        Weather,
+        TimeOfDay,
+        FireMode,
+        PlayerId,
+        UnitId,
+        MoveMode,
+        MoveOrder,
+        ReactionFireMode,
+        ZocRule,
+        CREW_COUNT,
+        GROUP_MOVE_RADIUS,
+        hit_chance,
+        close_assault_hit_chance,
+        los_range,
+        hexside_between,
+        can_bridge_carry,
+        FIELD_BRIDGE_STRUCTURAL_POINTS,
+    };
+
+    fn damage_unit(core: &mut Core, defender_id: UnitId, damage: VehicleDamage) {
+        core.do_core_event(&CoreEvent::AttackUnit {
+            attack_info: AttackInfo {
+                attacker_id: None,
+                defender_id: defender_id,
+                mode: FireMode::Active,
+                killed: 0,
+                suppression: 0,
+                remove_move_points: false,
+                is_ambush: false,
+                is_inderect: false,
+                leave_wrecks: true,
+                attacker_suppression: 0,
+                damage: Some(damage),
+            },
+        });
+    }
 
     #[test]
     fn test_center_1() {
@@ -1238,4 +2517,483 @@ mod tests {
         let expected = MapPos{v: Vector2{x: 6, y: 1}};
         assert_eq!(expected, real);
     }
+
+    #[test]
+    fn test_entrenched_infantry_is_harder_to_hit() {
+        let (db, mut state) = new_state(&options());
+        let attacker_id = add_unit(&db, &mut state, 0, "soldier", map_pos(4, 5));
+        let defender_id = add_unit(&db, &mut state, 1, "soldier", map_pos(6, 5));
+        let old_chance = hit_chance(
+            &db, &state, state.unit(attacker_id), state.unit(defender_id));
+        state.apply_event(&CoreEvent::Entrench{unit_id: defender_id});
+        let new_chance = hit_chance(
+            &db, &state, state.unit(attacker_id), state.unit(defender_id));
+        assert!(new_chance < old_chance);
+    }
+
+    #[test]
+    fn test_passengers_bail_out_of_wreck() {
+        let mut core = Core::new(&options());
+        let truck_id = add_core_unit(&mut core, 0, "truck", map_pos(5, 5));
+        let soldier_id = add_core_unit(&mut core, 0, "soldier", map_pos(6, 5));
+        let tank_id = add_core_unit(&mut core, 1, "medium_tank", map_pos(5, 3));
+        // already shaken passengers can't lose more morale than they have
+        core.do_core_event(&CoreEvent::AttackUnit {
+            attack_info: AttackInfo {
+                suppression: 80,
+                .. killing_attack(tank_id, soldier_id, true)
+            },
+        });
+        let count = core.state.unit(soldier_id).count;
+        load_unit(&mut core, truck_id, soldier_id);
+        core.do_core_event(&CoreEvent::AttackUnit {
+            attack_info: killing_attack(tank_id, truck_id, true),
+        });
+        let wreck = core.state.unit(truck_id).clone();
+        let event = core.passenger_fate_event(&wreck, soldier_id, true, 1);
+        core.do_core_event(&event);
+        assert_eq!(core.state.unit(truck_id).passenger_id, None);
+        let soldier = core.state.unit(soldier_id);
+        assert!(!soldier.is_loaded);
+        assert_eq!(soldier.count, count - 1);
+        assert_eq!(soldier.morale, 0);
+        assert_eq!(soldier.move_points, Some(MovePoints{n: 0}));
+        assert!(distance(soldier.pos.map_pos, wreck.pos.map_pos).n <= 1);
+    }
+
+    #[test]
+    fn test_passengers_die_in_wreck() {
+        let mut core = Core::new(&options());
+        let truck_id = add_core_unit(&mut core, 0, "truck", map_pos(5, 5));
+        let soldier_id = add_core_unit(&mut core, 0, "soldier", map_pos(6, 5));
+        let tank_id = add_core_unit(&mut core, 1, "medium_tank", map_pos(5, 3));
+        load_unit(&mut core, truck_id, soldier_id);
+        core.do_core_event(&CoreEvent::AttackUnit {
+            attack_info: killing_attack(tank_id, truck_id, true),
+        });
+        let wreck = core.state.unit(truck_id).clone();
+        let event = core.passenger_fate_event(&wreck, soldier_id, false, 0);
+        assert_eq!(event, CoreEvent::KillPassenger {
+            transporter_id: truck_id,
+            passenger_id: soldier_id,
+        });
+        core.do_core_event(&event);
+        assert_eq!(core.state.unit(truck_id).passenger_id, None);
+        assert!(core.state.unit_opt(soldier_id).is_none());
+    }
+
+    #[test]
+    fn test_passengers_die_without_wreck() {
+        let mut core = Core::new(&options());
+        let truck_id = add_core_unit(&mut core, 0, "truck", map_pos(5, 5));
+        let soldier_id = add_core_unit(&mut core, 0, "soldier", map_pos(6, 5));
+        let tank_id = add_core_unit(&mut core, 1, "medium_tank", map_pos(5, 3));
+        load_unit(&mut core, truck_id, soldier_id);
+        // like an aircraft, leaves nothing behind
+        core.do_attack_event(&killing_attack(tank_id, truck_id, false));
+        assert!(core.state.unit_opt(truck_id).is_none());
+        assert!(core.state.unit_opt(soldier_id).is_none());
+        let events = &core.players_info[&PlayerId{id: 0}].events;
+        assert!(events.contains(&CoreEvent::KillPassenger {
+            transporter_id: truck_id,
+            passenger_id: soldier_id,
+        }));
+    }
+
+    #[test]
+    fn test_split_and_merge() {
+        let mut core = Core::new(&options());
+        let unit_id = add_core_unit(&mut core, 0, "soldier", map_pos(5, 5));
+        let pos = ExactPos{map_pos: map_pos(6, 5), slot_id: SlotId::Id(0)};
+        core.do_command(Command::Split{unit_id: unit_id, count: 1, pos: pos});
+        let new_unit_id = UnitId{id: unit_id.id + 1};
+        {
+            let unit = core.state.unit(unit_id);
+            let new_unit = core.state.unit(new_unit_id);
+            assert_eq!(unit.count, 3);
+            assert_eq!(new_unit.count, 1);
+            assert_eq!(new_unit.pos, pos);
+            assert!(new_unit.move_points < unit.move_points);
+        }
+        core.do_command(Command::Merge{unit_id: new_unit_id, into_id: unit_id});
+        assert!(core.state.unit_opt(new_unit_id).is_none());
+        let unit = core.state.unit(unit_id);
+        assert_eq!(unit.count, 4);
+        // as tired as the split off part
+        assert!(unit.move_points < Some(core.db.unit_type(unit.type_id).move_points));
+    }
+
+    #[test]
+    fn test_grenades_work_against_tanks() {
+        let (db, mut state) = new_state(&options());
+        let soldier_id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 5));
+        let tank_id = add_unit(&db, &mut state, 1, "medium_tank", map_pos(6, 5));
+        let soldier = state.unit(soldier_id);
+        let tank = state.unit(tank_id);
+        assert_eq!(hit_chance(&db, &state, soldier, tank).n, 0);
+        assert!(close_assault_hit_chance(&db, &state, soldier, tank).n > 0);
+    }
+
+    #[test]
+    fn test_capture_abandoned_vehicle() {
+        let mut core = Core::new(&options());
+        let soldier_id = add_core_unit(&mut core, 0, "soldier", map_pos(5, 5));
+        let tank_id = add_core_unit(&mut core, 1, "medium_tank", map_pos(6, 5));
+        damage_unit(&mut core, tank_id, VehicleDamage::Immobilized);
+        damage_unit(&mut core, tank_id, VehicleDamage::Abandoned);
+        core.do_command(Command::Capture{unit_id: soldier_id, target_id: tank_id});
+        {
+            let tank = core.state.unit(tank_id);
+            assert_eq!(tank.player_id, PlayerId{id: 0});
+            assert!(tank.is_alive);
+            assert!(!tank.is_immobilized);
+            assert_eq!(tank.move_points, Some(MovePoints{n: 0}));
+        }
+        let soldier = core.state.unit(soldier_id);
+        assert_eq!(soldier.count, 4 - CREW_COUNT);
+        assert_eq!(soldier.move_points, Some(MovePoints{n: 0}));
+        assert_eq!(soldier.attack_points, Some(AttackPoints{n: 0}));
+    }
+
+    #[test]
+    fn test_shelled_building_collapses() {
+        let mut core = Core::new(&options());
+        let tank_id = add_core_unit(&mut core, 0, "mammoth_tank", map_pos(3, 5));
+        let pos = map_pos(6, 5);
+        let building_id = add_core_object(&mut core, big_building(pos));
+        // 12 structural points, 6 damage per shot
+        for _ in 0 .. 2 {
+            core.do_core_event(&CoreEvent::EndTurn {
+                old_id: PlayerId{id: 1},
+                new_id: PlayerId{id: 0},
+            });
+            core.do_command(Command::AttackObject {
+                attacker_id: tank_id,
+                object_id: building_id,
+            });
+            let tank = core.state.unit(tank_id);
+            assert_eq!(tank.attack_points, Some(AttackPoints{n: 0}));
+        }
+        assert!(core.state.objects().get(&building_id).is_none());
+        let classes: Vec<_> = core.state.objects_at(pos)
+            .map(|object| object.class)
+            .collect();
+        assert_eq!(classes, vec![ObjectClass::Rubble]);
+    }
+
+    fn end_round(core: &mut Core) {
+        core.do_core_event(&CoreEvent::EndTurn {
+            old_id: PlayerId{id: 1},
+            new_id: PlayerId{id: 0},
+        });
+    }
+
+    fn smoke_positions(core: &Core) -> Vec<MapPos> {
+        core.state.objects().values()
+            .filter(|object| object.class == ObjectClass::Smoke)
+            .map(|object| object.pos.map_pos)
+            .collect()
+    }
+
+    #[test]
+    fn test_smoke_pattern() {
+        let mut core = Core::new(&options());
+        let mortar_id = add_core_unit(&mut core, 0, "mortar", map_pos(3, 5));
+        let pos = map_pos(6, 6);
+        core.do_command(Command::Smoke{unit_id: mortar_id, pos: pos});
+        let positions = smoke_positions(&core);
+        // the target tile and three neighbours
+        assert_eq!(positions.len(), 4);
+        assert!(positions.contains(&pos));
+        for &smoke_pos in &positions {
+            assert!(distance(pos, smoke_pos).n <= 1);
+        }
+        for object in core.state.objects().values() {
+            if object.class == ObjectClass::Smoke {
+                assert_eq!(object.timer, Some(3));
+            }
+        }
+        let mortar = core.state.unit(mortar_id);
+        assert_eq!(mortar.attack_points, Some(AttackPoints{n: 0}));
+    }
+
+    #[test]
+    fn test_smoke_drifts_with_wind() {
+        let mut core = Core::new(&options());
+        core.wind = Dir::East;
+        let id = add_core_object(&mut core, smoke(map_pos(5, 5), 3));
+        let object = core.state.objects()[&id].clone();
+        core.update_timed_object(id, object, true);
+        assert_eq!(smoke_positions(&core), vec![map_pos(6, 5)]);
+        // drifting doesn't renew the smoke
+        end_round(&mut core);
+        let timers: Vec<_> = core.state.objects().values()
+            .filter(|object| object.class == ObjectClass::Smoke)
+            .map(|object| object.timer)
+            .collect();
+        assert_eq!(timers, vec![Some(2)]);
+    }
+
+    #[test]
+    fn test_smoke_stays_without_drift() {
+        let mut core = Core::new(&options());
+        core.wind = Dir::East;
+        let id = add_core_object(&mut core, smoke(map_pos(5, 5), 3));
+        let object = core.state.objects()[&id].clone();
+        core.update_timed_object(id, object, false);
+        assert_eq!(smoke_positions(&core), vec![map_pos(5, 5)]);
+    }
+
+    #[test]
+    fn test_smoke_expires() {
+        let mut core = Core::new(&options());
+        add_core_object(&mut core, smoke(map_pos(5, 5), 1));
+        core.update_timed_objects();
+        assert!(smoke_positions(&core).is_empty());
+    }
+
+    #[test]
+    fn test_bad_weather_limits_visibility() {
+        let (db, state) = new_state(&options());
+        let (_, bad_state) = new_state(&bad_weather_options());
+        let unit_type = db.unit_type(db.unit_type_id("soldier"));
+        let range = los_range(&state, unit_type);
+        let bad_range = los_range(&bad_state, unit_type);
+        assert!(bad_range.n < range.n);
+        assert!(bad_range.n >= 1);
+    }
+
+    #[test]
+    fn test_bad_weather_reduces_hit_chance() {
+        let (db, mut state) = new_state(&options());
+        let (_, mut bad_state) = new_state(&bad_weather_options());
+        let mut chances = Vec::new();
+        for state in &mut [&mut state, &mut bad_state] {
+            let attacker_id = add_unit(&db, state, 0, "soldier", map_pos(4, 5));
+            let defender_id = add_unit(&db, state, 1, "soldier", map_pos(6, 5));
+            let attacker = state.unit(attacker_id);
+            let defender = state.unit(defender_id);
+            chances.push(hit_chance(&db, state, attacker, defender).n);
+        }
+        assert!(chances[1] < chances[0]);
+    }
+
+    #[test]
+    fn test_day_turns_into_dusk() {
+        let mut core = Core::new(&options());
+        for _ in 0 .. 5 {
+            core.update_weather();
+            assert_eq!(core.state.time_of_day(), TimeOfDay::Day);
+        }
+        core.update_weather();
+        assert_eq!(core.state.time_of_day(), TimeOfDay::Dusk);
+    }
+
+    #[test]
+    fn test_hexside_between() {
+        let (_, mut state) = new_state(&options());
+        let pos = map_pos(5, 5);
+        let neighbour_pos = Dir::get_neighbour_pos(pos, Dir::East);
+        add_object(&mut state, hexside(ObjectClass::Hedge, pos, Dir::East));
+        let hedge = Some(ObjectClass::Hedge);
+        assert_eq!(hexside_between(&state, pos, neighbour_pos), hedge);
+        assert_eq!(hexside_between(&state, neighbour_pos, pos), hedge);
+        let other_pos = Dir::get_neighbour_pos(pos, Dir::West);
+        assert_eq!(hexside_between(&state, pos, other_pos), None);
+        assert_eq!(hexside_between(&state, pos, pos), None);
+    }
+
+    #[test]
+    fn test_wall_gives_cover() {
+        let (db, mut state) = new_state(&options());
+        let attacker_id = add_unit(&db, &mut state, 0, "soldier", map_pos(3, 5));
+        let defender_id = add_unit(&db, &mut state, 1, "soldier", map_pos(6, 5));
+        let old_chance = hit_chance(
+            &db, &state, state.unit(attacker_id), state.unit(defender_id));
+        let wall = hexside(ObjectClass::Wall, map_pos(5, 5), Dir::East);
+        add_object(&mut state, wall);
+        let new_chance = hit_chance(
+            &db, &state, state.unit(attacker_id), state.unit(defender_id));
+        assert!(new_chance < old_chance);
+    }
+
+    fn move_cost_to(core: &Core, unit_id: UnitId, pos: MapPos) -> i32 {
+        let unit = core.state.unit(unit_id);
+        let to = ExactPos{map_pos: pos, slot_id: SlotId::WholeTile};
+        tile_cost(&core.db, &core.state, unit, unit.pos, to).n
+    }
+
+    #[test]
+    fn test_build_bridge() {
+        let mut core = Core::new(&map01_options());
+        let engineer_id = add_core_unit(&mut core, 0, "engineer", map_pos(5, 7));
+        let pos = map_pos(6, 7);
+        assert!(move_cost_to(&core, engineer_id, pos) >= 99);
+        core.do_command(Command::BuildBridge{unit_id: engineer_id, pos: pos});
+        {
+            let engineer = core.state.unit(engineer_id);
+            assert_eq!(engineer.move_points, Some(MovePoints{n: 0}));
+            assert_eq!(engineer.attack_points, Some(AttackPoints{n: 0}));
+        }
+        let bridge = core.state.objects_at(pos)
+            .find(|object| object.class == ObjectClass::Bridge)
+            .unwrap();
+        let points = Some(FIELD_BRIDGE_STRUCTURAL_POINTS);
+        assert_eq!(bridge.structural_points, points);
+        assert!(move_cost_to(&core, engineer_id, pos) < 99);
+        // field bridges are too weak for tanks
+        let soldier_type = core.db.unit_type(core.db.unit_type_id("soldier"));
+        let tank_type = core.db.unit_type(core.db.unit_type_id("mammoth_tank"));
+        assert!(can_bridge_carry(soldier_type, bridge));
+        assert!(!can_bridge_carry(tank_type, bridge));
+    }
+
+    #[test]
+    fn test_demolish_bridge() {
+        let mut core = Core::new(&map01_options());
+        let engineer_id = add_core_unit(&mut core, 0, "engineer", map_pos(5, 7));
+        let pos = map_pos(5, 8);
+        let bridge_id = *core.state.objects().iter()
+            .find(|&(_, object)| object.class == ObjectClass::Bridge)
+            .unwrap().0;
+        assert!(move_cost_to(&core, engineer_id, pos) < 99);
+        core.do_command(Command::Demolish {
+            unit_id: engineer_id,
+            object_id: bridge_id,
+        });
+        assert!(core.state.objects().get(&bridge_id).is_none());
+        // blown up bridges leave no rubble
+        assert!(core.state.objects_at(pos)
+            .all(|object| object.class != ObjectClass::Rubble));
+        assert!(move_cost_to(&core, engineer_id, pos) >= 99);
+    }
+
+    #[test]
+    fn test_move_order_takes_several_turns() {
+        let mut core = Core::new(&options());
+        let unit_id = add_core_unit(&mut core, 0, "soldier", map_pos(1, 5));
+        let destination = map_pos(9, 5);
+        core.do_command(Command::SetMoveOrder {
+            unit_id: unit_id,
+            order: MoveOrder {
+                waypoints: vec![map_pos(1, 8), destination],
+                mode: MoveMode::Fast,
+            },
+        });
+        let mut rounds = 0;
+        while core.state.move_orders().get(&unit_id).is_some() {
+            assert!(rounds < 10);
+            core.do_command(Command::EndTurn);
+            core.do_command(Command::EndTurn);
+            rounds += 1;
+        }
+        assert!(rounds > 1);
+        assert_eq!(core.state.unit(unit_id).pos.map_pos, destination);
+    }
+
+    #[test]
+    fn test_move_stops_at_unit_in_the_way() {
+        let mut core = Core::new(&options());
+        let unit_id = add_core_unit(&mut core, 0, "soldier", map_pos(1, 5));
+        let enemy_id = add_core_unit(&mut core, 1, "soldier", map_pos(2, 5));
+        let start_pos = core.state.unit(unit_id).pos;
+        let path = vec![
+            start_pos,
+            core.state.unit(enemy_id).pos,
+            ExactPos{map_pos: map_pos(3, 5), slot_id: SlotId::Id(0)},
+        ];
+        assert!(core.move_unit(unit_id, &path, MoveMode::Fast));
+        let unit = core.state.unit(unit_id);
+        assert_eq!(unit.pos, start_pos);
+        assert_eq!(unit.move_points, Some(MovePoints{n: 9}));
+    }
+
+    #[test]
+    fn test_move_command_stops_in_front_of_hidden_unit() {
+        let mut core = Core::new(&options());
+        let unit_id = add_core_unit(&mut core, 0, "jeep", map_pos(3, 5));
+        // infantry in smoke can't be seen by a jeep even from the next tile
+        add_core_object(&mut core, smoke(map_pos(5, 5), 3));
+        let enemy_id = add_core_unit(&mut core, 1, "soldier", map_pos(5, 5));
+        // reaction fire would interrupt the move before the bump
+        core.do_core_event(&CoreEvent::SetReactionFireMode {
+            unit_id: enemy_id,
+            mode: ReactionFireMode::HoldFire,
+        });
+        let player_id = PlayerId{id: 0};
+        core.do_core_event(&CoreEvent::EndTurn {
+            old_id: PlayerId{id: 1},
+            new_id: player_id,
+        });
+        assert!(!core.players_info[&player_id].visible_enemies.contains(&enemy_id));
+        let path = vec![
+            core.state.unit(unit_id).pos,
+            ExactPos{map_pos: map_pos(4, 5), slot_id: SlotId::Id(0)},
+            core.state.unit(enemy_id).pos,
+            ExactPos{map_pos: map_pos(6, 5), slot_id: SlotId::Id(0)},
+        ];
+        core.do_command(Command::Move {
+            unit_id: unit_id,
+            path: path.clone(),
+            mode: MoveMode::Fast,
+        });
+        // the rest of the path is dropped, the jeep doesn't jump over the enemy
+        assert_eq!(core.state.unit(unit_id).pos, path[1]);
+        assert_eq!(core.state.unit(unit_id).move_points, Some(MovePoints{n: 8}));
+        // the player learns who is standing in the way
+        let is_shown = core.players_info[&player_id].events.iter().any(|event| {
+            match *event {
+                CoreEvent::ShowUnit{ref unit_info} => unit_info.id == enemy_id,
+                _ => false,
+            }
+        });
+        assert!(is_shown);
+    }
+
+    #[test]
+    fn test_hidden_enemies_have_no_zoc() {
+        let options = Options {
+            weather: Weather::Fog,
+            time_of_day: TimeOfDay::Night,
+            zoc_rule: ZocRule::Tax,
+            .. options()
+        };
+        let mut core = Core::new(&options);
+        let unit_id = add_core_unit(&mut core, 0, "soldier", map_pos(3, 5));
+        let enemy_id = add_core_unit(&mut core, 1, "soldier", map_pos(5, 5));
+        let player_id = PlayerId{id: 0};
+        // fixtures add units with ShowUnit, they count as seen until the turn ends
+        core.do_core_event(&CoreEvent::EndTurn {
+            old_id: PlayerId{id: 1},
+            new_id: player_id,
+        });
+        assert!(!core.players_info[&player_id].visible_enemies.contains(&enemy_id));
+        let from = core.state.unit(unit_id).pos;
+        let to = ExactPos{map_pos: map_pos(4, 5), slot_id: SlotId::WholeTile};
+        let real_cost = tile_cost(
+            &core.db, &core.state, core.state.unit(unit_id), from, to);
+        let cost = core.move_cost(unit_id, from, to, MoveMode::Fast);
+        assert!(cost.n < real_cost.n);
+    }
+
+    #[test]
+    fn test_group_move() {
+        let mut core = Core::new(&options());
+        let unit_ids: Vec<_> = [(2, 5), (2, 6), (2, 7)].iter()
+            .map(|&(x, y)| add_core_unit(&mut core, 0, "soldier", map_pos(x, y)))
+            .collect();
+        let destination = map_pos(4, 6);
+        core.do_command(Command::GroupMove {
+            unit_ids: unit_ids.clone(),
+            destination: destination,
+            mode: MoveMode::Fast,
+        });
+        let positions: Vec<_> = unit_ids.iter()
+            .map(|&id| core.state.unit(id).pos)
+            .collect();
+        assert!(positions.iter().any(|pos| pos.map_pos == destination));
+        for pos in &positions {
+            assert!(distance(pos.map_pos, destination) <= GROUP_MOVE_RADIUS);
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use std::default::{Default};
 use std::iter::{repeat};
+use std::rc::{Rc};
 use cgmath::{Vector2, Array};
 use types::{Size2};
 use dir::{Dir, DirIter, dirs};
@@ -20,6 +21,11 @@ impl Default for Terrain {
 #[derive(Clone, Debug)]
 pub struct Map<T> {
     tiles: Vec<T>,
+
+    /// Tiles that don't exist: holes and irregular board edges.
+    /// Shared with the iterators.
+    void_tiles: Rc<Vec<bool>>,
+
     size: Size2,
 }
 
@@ -29,59 +35,88 @@ impl<T: Clone + Default> Map<T> {
         let tiles = repeat(Default::default()).take(tiles_count).collect();
         Map {
             tiles: tiles,
+            void_tiles: Rc::new(vec![false; tiles_count]),
             size: size,
         }
     }
 
+    /// Empty map with the same size and void tiles as `map`
+    pub fn new_like<U>(map: &Map<U>) -> Map<T> {
+        let tiles_count = (map.size.w * map.size.h) as usize;
+        let tiles = repeat(Default::default()).take(tiles_count).collect();
+        Map {
+            tiles: tiles,
+            void_tiles: map.void_tiles.clone(),
+            size: map.size,
+        }
+    }
+
     pub fn size(&self) -> Size2 {
         self.size
     }
 
+    fn index(&self, pos: MapPos) -> usize {
+        assert!(is_in_bounds(self.size, pos));
+        (self.size.w * pos.v.y + pos.v.x) as usize
+    }
+
+    // Void tiles still have storage, so they can be safely cleaned
     pub fn tile_mut<P: Into<MapPos>>(&mut self, pos: P) -> &mut T {
-        let pos = pos.into();
-        assert!(self.is_inboard(pos));
-        let index = self.size.w * pos.v.y + pos.v.x;
-        &mut self.tiles[index as usize]
+        let index = self.index(pos.into());
+        &mut self.tiles[index]
     }
 
     pub fn tile<P: Into<MapPos>>(&self, pos: P) -> &T {
+        let index = self.index(pos.into());
+        debug_assert!(!self.void_tiles[index], "Void tile can't be read");
+        &self.tiles[index]
+    }
+
+    pub fn set_void<P: Into<MapPos>>(&mut self, pos: P) {
+        let index = self.index(pos.into());
+        Rc::make_mut(&mut self.void_tiles)[index] = true;
+    }
+
+    /// Is the tile inside the map's bounds but doesn't exist?
+    pub fn is_void<P: Into<MapPos>>(&self, pos: P) -> bool {
         let pos = pos.into();
-        assert!(self.is_inboard(pos));
-        let index = self.size.w * pos.v.y + pos.v.x;
-        &self.tiles[index as usize]
+        is_in_bounds(self.size, pos) && self.void_tiles[self.index(pos)]
     }
 
     pub fn is_inboard<P: Into<MapPos>>(&self, pos: P) -> bool {
         let pos = pos.into();
-        let x = pos.v.x;
-        let y = pos.v.y;
-        x >= 0 && y >= 0 && x < self.size.w && y < self.size.h
+        is_in_bounds(self.size, pos) && !self.void_tiles[self.index(pos)]
     }
 
     pub fn get_iter(&self) -> MapPosIter {
-        MapPosIter::new(self.size())
+        MapPosIter::new(self.size(), self.void_tiles.clone())
     }
 }
 
+fn is_in_bounds(size: Size2, pos: MapPos) -> bool {
+    let x = pos.v.x;
+    let y = pos.v.y;
+    x >= 0 && y >= 0 && x < size.w && y < size.h
+}
+
+/// Iterates over all tiles except the void ones
 #[derive(Clone, Debug)]
 pub struct MapPosIter {
     cursor: MapPos,
     map_size: Size2,
+    void_tiles: Rc<Vec<bool>>,
 }
 
 impl MapPosIter {
-    fn new(map_size: Size2) -> MapPosIter {
+    fn new(map_size: Size2, void_tiles: Rc<Vec<bool>>) -> MapPosIter {
         MapPosIter {
             cursor: MapPos{v: Vector2::from_value(0)},
             map_size: map_size,
+            void_tiles: void_tiles,
         }
     }
-}
 
-impl Iterator for MapPosIter {
-    type Item = MapPos;
-
-    fn next(&mut self) -> Option<MapPos> {
+    fn step(&mut self) -> Option<MapPos> {
         let current_pos = if self.cursor.v.y >= self.map_size.h {
             None
         } else {
@@ -96,6 +131,20 @@ impl Iterator for MapPosIter {
     }
 }
 
+impl Iterator for MapPosIter {
+    type Item = MapPos;
+
+    fn next(&mut self) -> Option<MapPos> {
+        while let Some(pos) = self.step() {
+            let index = self.map_size.w * pos.v.y + pos.v.x;
+            if !self.void_tiles[index as usize] {
+                return Some(pos);
+            }
+        }
+        None
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct RingIter {
     cursor: MapPos,
@@ -205,7 +254,8 @@ pub fn distance(from: MapPos, to: MapPos) -> Distance {
 #[cfg(test)]
 mod tests {
     use cgmath::{Vector2};
-    use super::{ring_iter, spiral_iter};
+    use types::{Size2};
+    use super::{Map, Terrain, ring_iter, spiral_iter};
     use ::{MapPos, Distance};
 
     #[test]
@@ -284,4 +334,16 @@ mod tests {
         }
         assert!(expected.next().is_none());
     }
+
+    #[test]
+    fn test_void_tiles() {
+        let mut map: Map<Terrain> = Map::new(Size2{w: 3, h: 2});
+        let void_pos = MapPos{v: Vector2{x: 1, y: 0}};
+        map.set_void(void_pos);
+        assert!(!map.is_inboard(void_pos));
+        assert!(map.is_inboard(MapPos{v: Vector2{x: 2, y: 0}}));
+        assert!(!map.is_inboard(MapPos{v: Vector2{x: 3, y: 0}}));
+        let positions: Vec<_> = map.get_iter().map(|p| (p.v.x, p.v.y)).collect();
+        assert_eq!(positions, vec![(0, 0), (2, 0), (0, 1), (1, 1), (2, 1)]);
+    }
 }
@@ -0,0 +1,493 @@
+use std::collections::{HashMap, HashSet};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use cgmath::{Vector2};
+use types::{Size2};
+use map::{Map, Terrain, distance};
+use dir::{Dir, dirs};
+use game_state::{
+    MapInfo,
+    add_road,
+    add_bridge,
+    add_buildings,
+    add_big_building,
+    add_reinforcement_sector,
+};
+use ::{
+    MapGenParams,
+    MapPos,
+    ObjectId,
+    Object,
+    ObjectClass,
+    PlayerId,
+    Sector,
+    SectorId,
+    objects_at,
+};
+
+// Columns near the map's left and right edges are kept free
+// for reinforcement sectors
+const EDGE_MARGIN: i32 = 2;
+
+// Layouts that have no room for a city or a road are thrown away
+const MAX_LAYOUT_ATTEMPTS: i32 = 20;
+
+fn new_rng(seed: u32) -> XorShiftRng {
+    // XorShiftRng can't be seeded with zeros only
+    XorShiftRng::from_seed([seed, 0x193a_6754, 0xa8a7_d469, 0x9783_0e05])
+}
+
+// With an even map height this is an exact point symmetry
+// of the hex grid, so both players get the same battlefield
+fn mirror(size: Size2, pos: MapPos) -> MapPos {
+    MapPos{v: Vector2 {
+        x: size.w - 1 - pos.v.x,
+        y: size.h - 1 - pos.v.y,
+    }}
+}
+
+fn is_in_generation_zone(size: Size2, pos: MapPos) -> bool {
+    pos.v.x >= EDGE_MARGIN
+        && pos.v.x < size.w - EDGE_MARGIN
+        && pos.v.y >= 0
+        && pos.v.y < size.h
+}
+
+fn random_pos<R: Rng>(rng: &mut R, size: Size2) -> MapPos {
+    MapPos{v: Vector2 {
+        x: rng.gen_range(EDGE_MARGIN, size.w - EDGE_MARGIN),
+        y: rng.gen_range(0, size.h),
+    }}
+}
+
+fn set_plain_tile(map: &mut Map<Terrain>, pos: MapPos, terrain: Terrain) {
+    if *map.tile(pos) == Terrain::Plain {
+        *map.tile_mut(pos) = terrain;
+    }
+}
+
+fn add_cluster<R: Rng>(
+    rng: &mut R,
+    map: &mut Map<Terrain>,
+    terrain: Terrain,
+    cluster_size: i32,
+) {
+    let size = map.size();
+    let mut pos = random_pos(rng, size);
+    for _ in 0 .. cluster_size {
+        set_plain_tile(map, pos, terrain);
+        set_plain_tile(map, mirror(size, pos), terrain);
+        let dir = Dir::from_int(rng.gen_range(0, 6));
+        let next_pos = Dir::get_neighbour_pos(pos, dir);
+        if is_in_generation_zone(size, next_pos) {
+            pos = next_pos;
+        }
+    }
+}
+
+fn add_city<R: Rng>(
+    rng: &mut R,
+    map: &mut Map<Terrain>,
+    objects: &mut HashMap<ObjectId, Object>,
+    center: MapPos,
+) {
+    let size = map.size();
+    let is_big_building = rng.gen_range(0, 3) == 0;
+    let count = rng.gen_range(1, 4);
+    for &pos in &[center, mirror(size, center)] {
+        if is_big_building {
+            add_big_building(map, objects, pos);
+        } else {
+            add_buildings(map, objects, pos, count);
+        }
+    }
+    for dir in dirs() {
+        let pos = Dir::get_neighbour_pos(center, dir);
+        let count = rng.gen_range(1, 3);
+        if rng.gen_range(0, 2) == 0 || *map.tile(pos) != Terrain::Plain {
+            continue;
+        }
+        for &pos in &[pos, mirror(size, pos)] {
+            if *map.tile(pos) == Terrain::Plain {
+                add_buildings(map, objects, pos, count);
+            }
+        }
+    }
+}
+
+// City centers are placed on the first player's half,
+// their mirrored copies go to the other half
+fn get_city_centers<R: Rng>(
+    rng: &mut R,
+    map: &Map<Terrain>,
+    cities_count: i32,
+) -> Vec<MapPos> {
+    let size = map.size();
+    let max_attempts = 100;
+    let min_distance = 3;
+    let mut centers: Vec<MapPos> = Vec::new();
+    for _ in 0 .. max_attempts {
+        if centers.len() as i32 == cities_count {
+            break;
+        }
+        // whole city sector must fit into the board
+        let pos = MapPos{v: Vector2 {
+            x: rng.gen_range(EDGE_MARGIN, size.w / 2),
+            y: rng.gen_range(1, size.h - 1),
+        }};
+        if *map.tile(pos) != Terrain::Plain {
+            continue;
+        }
+        let is_too_close = centers.iter().any(|&center| {
+            distance(center, pos).n < min_distance
+                || distance(mirror(size, center), pos).n < min_distance
+        }) || distance(mirror(size, pos), pos).n < min_distance;
+        if !is_too_close {
+            centers.push(pos);
+        }
+    }
+    centers
+}
+
+fn get_road_path<R: Rng>(
+    rng: &mut R,
+    map: &Map<Terrain>,
+    from: MapPos,
+    to: MapPos,
+) -> Option<Vec<MapPos>> {
+    let mut path = vec![from];
+    let mut pos = from;
+    while pos != to {
+        let current_distance = distance(pos, to);
+        let next_positions: Vec<MapPos> = dirs()
+            .map(|dir| Dir::get_neighbour_pos(pos, dir))
+            .filter(|&next_pos| map.is_inboard(next_pos)
+                && distance(next_pos, to) < current_distance)
+            .collect();
+        pos = match rng.choose(&next_positions) {
+            Some(&pos) => pos,
+            None => return None,
+        };
+        path.push(pos);
+    }
+    Some(path)
+}
+
+fn add_roads(
+    map: &Map<Terrain>,
+    objects: &mut HashMap<ObjectId, Object>,
+    road_edges: &mut HashSet<(MapPos, MapPos)>,
+    bridges: &mut HashSet<MapPos>,
+    path: &[MapPos],
+) {
+    for window in path.windows(2) {
+        let from = window[0];
+        let to = window[1];
+        if road_edges.contains(&(from, to)) || road_edges.contains(&(to, from)) {
+            continue;
+        }
+        road_edges.insert((from, to));
+        add_road(objects, window);
+    }
+    for &pos in path {
+        if *map.tile(pos) == Terrain::Water && !bridges.contains(&pos) {
+            bridges.insert(pos);
+            add_bridge(objects, pos);
+        }
+    }
+}
+
+fn city_sector(center: MapPos) -> Sector {
+    let mut positions = vec![center];
+    for dir in dirs() {
+        positions.push(Dir::get_neighbour_pos(center, dir));
+    }
+    Sector {
+        positions: positions,
+        owner_id: None,
+    }
+}
+
+// Infantry can go anywhere except water without a bridge
+fn is_passable(
+    map: &Map<Terrain>,
+    objects: &HashMap<ObjectId, Object>,
+    pos: MapPos,
+) -> bool {
+    *map.tile(pos) != Terrain::Water || objects_at(objects, pos)
+        .iter().any(|object| object.class == ObjectClass::Bridge)
+}
+
+/// Can infantry get from `from` into every sector and every reinforcement sector?
+fn are_sectors_reachable(
+    map: &Map<Terrain>,
+    objects: &HashMap<ObjectId, Object>,
+    sectors: &HashMap<SectorId, Sector>,
+    from: MapPos,
+) -> bool {
+    let mut reached = HashSet::new();
+    let mut queue = vec![from];
+    reached.insert(from);
+    while let Some(pos) = queue.pop() {
+        for dir in dirs() {
+            let next_pos = Dir::get_neighbour_pos(pos, dir);
+            if map.is_inboard(next_pos)
+                && !reached.contains(&next_pos)
+                && is_passable(map, objects, next_pos)
+            {
+                reached.insert(next_pos);
+                queue.push(next_pos);
+            }
+        }
+    }
+    let are_sectors_reached = sectors.values().all(|sector| {
+        sector.positions.iter().any(|pos| reached.contains(pos))
+    });
+    let are_reinforcement_sectors_reached = objects.values()
+        .filter(|object| object.class == ObjectClass::ReinforcementSector)
+        .all(|object| reached.contains(&object.pos.map_pos));
+    are_sectors_reached && are_reinforcement_sectors_reached
+}
+
+pub fn generate_map(params: &MapGenParams) -> MapInfo {
+    let size = params.size;
+    assert!(size.h % 2 == 0, "Map height must be even");
+    assert!(size.w > EDGE_MARGIN * 2 + 1);
+    // retries use the same rng, so a seed still always gives the same map
+    let mut rng = new_rng(params.seed);
+    for _ in 0 .. MAX_LAYOUT_ATTEMPTS {
+        if let Some(map_info) = try_generate_map(&mut rng, params) {
+            return map_info;
+        }
+    }
+    // nothing gets in the way of cities and roads on an open field
+    let open_field_params = MapGenParams {
+        forests_count: 0,
+        lakes_count: 0,
+        .. params.clone()
+    };
+    try_generate_map(&mut rng, &open_field_params)
+        .expect("Can't place a city on an open field")
+}
+
+fn try_generate_map<R: Rng>(rng: &mut R, params: &MapGenParams) -> Option<MapInfo> {
+    let size = params.size;
+    let mut objects = HashMap::new();
+    let mut map = Map::new(size);
+    let mut sectors = HashMap::new();
+    for _ in 0 .. params.lakes_count {
+        add_cluster(rng, &mut map, Terrain::Water, params.cluster_size);
+    }
+    for _ in 0 .. params.forests_count {
+        add_cluster(rng, &mut map, Terrain::Trees, params.cluster_size);
+    }
+    let mut city_centers = get_city_centers(rng, &map, params.cities_count);
+    // cities are victory sectors, a map can't lose all of them
+    if params.cities_count > 0 && city_centers.is_empty() {
+        return None;
+    }
+    // buildings must be added before the roads, they need free slots
+    for &center in &city_centers {
+        add_city(rng, &mut map, &mut objects, center);
+    }
+    city_centers.sort_by_key(|pos| pos.v.x);
+    let reinforcement_sector_pos = MapPos{v: Vector2 {
+        x: 0,
+        y: rng.gen_range(1, size.h - 1),
+    }};
+    let mut road_edges = HashSet::new();
+    let mut bridges = HashSet::new();
+    let mut waypoints = vec![reinforcement_sector_pos];
+    waypoints.extend_from_slice(&city_centers);
+    // connect the two halves of the map
+    if let Some(&last_center) = city_centers.last() {
+        waypoints.push(mirror(size, last_center));
+    }
+    for window in waypoints.windows(2) {
+        let path = match get_road_path(rng, &map, window[0], window[1]) {
+            Some(path) => path,
+            None => return None,
+        };
+        let mirrored_path: Vec<MapPos> = path.iter()
+            .map(|&pos| mirror(size, pos))
+            .collect();
+        for path in &[path, mirrored_path] {
+            add_roads(&map, &mut objects, &mut road_edges, &mut bridges, path);
+        }
+    }
+    let second_reinforcement_sector_pos = MapPos{v: Vector2 {
+        x: 0,
+        y: size.h - 1 - reinforcement_sector_pos.v.y,
+    }};
+    for &pos in &[reinforcement_sector_pos, second_reinforcement_sector_pos] {
+        add_reinforcement_sector(&mut objects, pos, Some(PlayerId{id: 0}));
+        let pos = mirror(size, pos);
+        add_reinforcement_sector(&mut objects, pos, Some(PlayerId{id: 1}));
+    }
+    let mut sector_id = 0;
+    for &center in &city_centers {
+        for &pos in &[center, mirror(size, center)] {
+            sectors.insert(SectorId{id: sector_id}, city_sector(pos));
+            sector_id += 1;
+        }
+    }
+    // water may cut a city off the road network
+    if !are_sectors_reachable(&map, &objects, &sectors, reinforcement_sector_pos) {
+        return None;
+    }
+    Some((map, objects, sectors))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use types::{Size2};
+    use dir::{Dir};
+    use ::{MapGenParams, MapStyle, MapPos, ObjectId, Object, ObjectClass, PlayerId, SlotId};
+    use super::{generate_map, mirror, are_sectors_reachable};
+
+    const STYLES: [MapStyle; 4] = [
+        MapStyle::Mixed,
+        MapStyle::Forest,
+        MapStyle::Lakes,
+        MapStyle::Urban,
+    ];
+
+    fn all_params() -> Vec<MapGenParams> {
+        let sizes = [
+            Size2{w: 12, h: 14},
+            Size2{w: 16, h: 18},
+            Size2{w: 9, h: 10},
+        ];
+        let mut params = Vec::new();
+        for &size in &sizes {
+            for &style in &STYLES {
+                for seed in 0 .. 25 {
+                    params.push(MapGenParams::with_style(seed, size, style));
+                }
+            }
+        }
+        params
+    }
+
+    fn reinforcement_sectors(
+        objects: &HashMap<ObjectId, Object>,
+        player_id: PlayerId,
+    ) -> Vec<MapPos> {
+        objects.values()
+            .filter(|object| object.class == ObjectClass::ReinforcementSector
+                && object.owner_id == Some(player_id))
+            .map(|object| object.pos.map_pos)
+            .collect()
+    }
+
+    /// Tiles that can be reached from `from` by roads only
+    fn road_network(
+        objects: &HashMap<ObjectId, Object>,
+        from: MapPos,
+    ) -> HashSet<MapPos> {
+        let mut edges: HashMap<MapPos, Vec<MapPos>> = HashMap::new();
+        for object in objects.values() {
+            if object.class != ObjectClass::Road {
+                continue;
+            }
+            let dir = match object.pos.slot_id {
+                SlotId::TwoTiles(dir) => dir,
+                _ => panic!("Road must be a hexside object"),
+            };
+            let pos1 = object.pos.map_pos;
+            let pos2 = Dir::get_neighbour_pos(pos1, dir);
+            edges.entry(pos1).or_insert_with(Vec::new).push(pos2);
+            edges.entry(pos2).or_insert_with(Vec::new).push(pos1);
+        }
+        let mut reached = HashSet::new();
+        let mut queue = vec![from];
+        reached.insert(from);
+        while let Some(pos) = queue.pop() {
+            for &next_pos in edges.get(&pos).map(|v| v.as_slice()).unwrap_or(&[]) {
+                if reached.insert(next_pos) {
+                    queue.push(next_pos);
+                }
+            }
+        }
+        reached
+    }
+
+    #[test]
+    fn test_same_seed_same_map() {
+        let params = MapGenParams::new(42);
+        let (map1, objects1, sectors1) = generate_map(&params);
+        let (map2, objects2, sectors2) = generate_map(&params);
+        for pos in map1.get_iter() {
+            assert_eq!(map1.tile(pos), map2.tile(pos));
+        }
+        assert_eq!(objects1, objects2);
+        assert_eq!(sectors1.len(), sectors2.len());
+    }
+
+    #[test]
+    fn test_terrain_is_symmetric() {
+        for seed in 0 .. 20 {
+            let params = MapGenParams::new(seed);
+            let (map, _, sectors) = generate_map(&params);
+            let size = map.size();
+            for pos in map.get_iter() {
+                assert_eq!(map.tile(pos), map.tile(mirror(size, pos)));
+            }
+            for sector in sectors.values() {
+                sector.center();
+            }
+        }
+    }
+
+    #[test]
+    fn test_roads_connect_all_cities() {
+        for params in all_params() {
+            let (_, objects, sectors) = generate_map(&params);
+            let is_connected = reinforcement_sectors(&objects, PlayerId{id: 0})
+                .into_iter()
+                .any(|pos| {
+                    let roads = road_network(&objects, pos);
+                    sectors.values().all(|sector| {
+                        sector.positions.iter().any(|pos| roads.contains(pos))
+                    })
+                });
+            assert!(is_connected, "{:?}", params);
+        }
+    }
+
+    #[test]
+    fn test_reinforcement_sectors_are_balanced() {
+        for params in all_params() {
+            let (map, objects, sectors) = generate_map(&params);
+            let size = map.size();
+            let sectors0 = reinforcement_sectors(&objects, PlayerId{id: 0});
+            let sectors1 = reinforcement_sectors(&objects, PlayerId{id: 1});
+            assert!(!sectors0.is_empty(), "{:?}", params);
+            assert_eq!(sectors0.len(), sectors1.len(), "{:?}", params);
+            for &pos in &sectors0 {
+                assert!(sectors1.contains(&mirror(size, pos)), "{:?}", params);
+            }
+            for &pos in sectors0.iter().chain(&sectors1) {
+                assert!(are_sectors_reachable(&map, &objects, &sectors, pos),
+                    "{:?}", params);
+            }
+        }
+    }
+
+    #[test]
+    fn test_victory_sectors_exist() {
+        for params in all_params() {
+            let (map, _, sectors) = generate_map(&params);
+            let size = map.size();
+            assert!(!sectors.is_empty(), "{:?}", params);
+            assert!(sectors.len() as i32 <= params.cities_count * 2);
+            let centers: Vec<MapPos> = sectors.values()
+                .map(|sector| sector.positions[0])
+                .collect();
+            for &center in &centers {
+                assert!(map.is_inboard(center));
+                assert!(centers.contains(&mirror(size, center)), "{:?}", params);
+            }
+        }
+    }
+}
@@ -1,16 +1,44 @@
 use std::default::{Default};
 use std::rc::{Rc};
-use types::{Size2};
+use std::cmp::{Ordering};
+use std::collections::{BinaryHeap};
 use db::{Db};
-use unit::{Unit};
-use map::{Map, Terrain};
+use unit::{Unit, UnitType};
+use map::{Map, Terrain, distance};
 use game_state::{State};
 use dir::{Dir, dirs};
-use ::{MovePoints, ExactPos, SlotId, ObjectClass, get_free_exact_pos};
+use fov::{fov, simple_fov};
+use ::{
+    MovePoints,
+    MapPos,
+    ExactPos,
+    SlotId,
+    ObjectClass,
+    Weather,
+    ZocRule,
+    get_free_exact_pos,
+    is_in_enemy_zoc,
+    hexside_between,
+    is_hexside,
+    is_loaded_or_attached,
+    can_bridge_carry,
+    hit_chance,
+    los_range,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PathMode {
+    /// Only move points matter
+    Fastest,
+    /// Tiles covered by known enemy weapons are avoided
+    Safe,
+}
 
 #[derive(Clone, Debug)]
 pub struct Tile {
     cost: MovePoints,
+    /// Cost with the danger penalty, this is what the search minimizes
+    weight: i32,
     parent: Option<Dir>,
     slot_id: SlotId,
 }
@@ -25,6 +53,7 @@ impl Default for Tile {
     fn default() -> Tile {
         Tile {
             cost: MovePoints{n: 0},
+            weight: 0,
             parent: None,
             slot_id: SlotId::WholeTile,
         }
@@ -69,7 +98,88 @@ pub fn max_cost() -> MovePoints {
     MovePoints{n: i32::max_value()}
 }
 
-// TODO: increase cost for attached units
+/// Can the unit drive along a road from `from` to the neighbouring `pos`?
+fn is_road_between(
+    state: &State,
+    unit_type: &UnitType,
+    from: MapPos,
+    pos: MapPos,
+) -> bool {
+    if unit_type.is_big {
+        return false;
+    }
+    // roads don't cross water without a bridge
+    let objects_at = state.objects_at(pos);
+    let has_bridge = objects_at.clone().any(|object| {
+        object.class == ObjectClass::Bridge
+            && can_bridge_carry(unit_type, object)
+    });
+    if *state.map().tile(pos) == Terrain::Water && !has_bridge {
+        return false;
+    }
+    for object in objects_at {
+        if object.class != ObjectClass::Road {
+            continue;
+        }
+        let mut i = object.pos.map_pos_iter();
+        let road_from = i.next().unwrap();
+        let road_to = i.next().unwrap();
+        assert!(road_from != road_to);
+        if (road_from == from && road_to == pos)
+            || (road_to == from && road_from == pos)
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Extra move points that a transporter pays for every tile
+/// it drags the towed unit through
+fn towing_cost(db: &Db, towed_type: &UnitType) -> i32 {
+    let weapon_type = db.weapon_type(towed_type.weapon_type_id);
+    if !towed_type.is_infantry {
+        // a vehicle on a rope: the heavier it is the slower it goes
+        towed_type.size / 2
+    } else if weapon_type.is_inderect {
+        // howitzers and heavy mortars have long heavy trails
+        3
+    } else {
+        // anti-tank guns are built low and light
+        2
+    }
+}
+
+/// Towed guns get stuck between trees and houses,
+/// so a transporter with an attached unit can get there only by road.
+/// Shared by the pathfinder and `check_command`.
+pub fn is_towing_blocked(
+    db: &Db,
+    state: &State,
+    unit: &Unit,
+    from: ExactPos,
+    pos: ExactPos,
+) -> bool {
+    if unit.attached_unit_id.is_none() {
+        return false;
+    }
+    let map_pos = pos.map_pos;
+    let has_building = state.objects_at(map_pos)
+        .any(|object| object.class == ObjectClass::Building);
+    let is_rough = *state.map().tile(map_pos) == Terrain::Trees || has_building;
+    let unit_type = db.unit_type(unit.type_id);
+    is_rough && !is_road_between(state, unit_type, from.map_pos, map_pos)
+}
+
+/// Lower bound of `tile_cost`, keeps the A* heuristic admissible
+fn min_tile_cost(unit_type: &UnitType) -> i32 {
+    if unit_type.is_air || !unit_type.is_infantry {
+        2 // flying or driving along a road
+    } else {
+        4
+    }
+}
+
 pub fn tile_cost(db: &Db, state: &State, unit: &Unit, from: ExactPos, pos: ExactPos)
     -> MovePoints
 {
@@ -84,6 +194,9 @@ pub fn tile_cost(db: &Db, state: &State, unit: &Unit, from: ExactPos, pos: Exact
     }
     'unit_loop: for unit in units_at {
         for object in objects_at.clone() {
+            if is_hexside(object.class) || object.class == ObjectClass::Bridge {
+                continue;
+            }
             match object.pos.slot_id {
                 SlotId::Id(_) => if unit.pos == object.pos {
                     assert!(db.unit_type(unit.type_id).is_infantry);
@@ -98,71 +211,199 @@ pub fn tile_cost(db: &Db, state: &State, unit: &Unit, from: ExactPos, pos: Exact
         unit_cost += 1;
     }
     let tile = state.map().tile(pos);
+    let has_bridge = objects_at.clone().any(|object| {
+        object.class == ObjectClass::Bridge
+            && can_bridge_carry(unit_type, object)
+    });
+    let water_cost = if has_bridge { 4 } else { 99 };
     let mut terrain_cost = if unit_type.is_infantry {
         match *tile {
             Terrain::Plain | Terrain::City => 4,
             Terrain::Trees => 5,
-            Terrain::Water => 99,
+            Terrain::Water => water_cost,
         }
     } else {
         match *tile {
             Terrain::Plain | Terrain::City => 4,
             Terrain::Trees => 8,
-            Terrain::Water => 99,
+            Terrain::Water => water_cost,
         }
     };
-    for object in objects_at.clone() {
-        if object.class != ObjectClass::Road {
-            continue;
-        }
-        let mut i = object.pos.map_pos_iter();
-        let road_from = i.next().unwrap();
-        let road_to = i.next().unwrap();
-        assert!(road_from != road_to);
-        let is_road_pos_ok = road_from == from.map_pos && road_to == pos.map_pos;
-        let is_road_pos_rev_ok = road_to == from.map_pos && road_from == pos.map_pos;
-        if (is_road_pos_ok || is_road_pos_rev_ok) && !unit_type.is_big {
-            // TODO: ultrahardcoded value :(
-            terrain_cost = if unit_type.is_infantry { 4 } else { 2 };
-        }
+    if is_road_between(state, unit_type, from.map_pos, map_pos) {
+        // TODO: ultrahardcoded value :(
+        terrain_cost = if unit_type.is_infantry { 4 } else { 2 };
     }
+    // `is_towing_blocked` tiles can't be entered at all
+    let towing_cost = match unit.attached_unit_id.and_then(|id| state.unit_opt(id)) {
+        Some(attached_unit) => towing_cost(db, db.unit_type(attached_unit.type_id)),
+        None => 0,
+    };
     for object in objects_at {
         let cost = if unit_type.is_infantry {
             match object.class {
                 ObjectClass::Building => 1,
+                ObjectClass::Rubble => 2,
                 ObjectClass::ReinforcementSector |
                 ObjectClass::Road |
-                ObjectClass::Smoke => 0,
+                ObjectClass::Bridge |
+                ObjectClass::Smoke |
+                ObjectClass::River |
+                ObjectClass::Wall |
+                ObjectClass::Hedge => 0,
             }
         } else {
             match object.class {
                 ObjectClass::Building => 2,
+                ObjectClass::Rubble => 4,
                 ObjectClass::ReinforcementSector |
                 ObjectClass::Road |
-                ObjectClass::Smoke => 0,
+                ObjectClass::Bridge |
+                ObjectClass::Smoke |
+                ObjectClass::River |
+                ObjectClass::Wall |
+                ObjectClass::Hedge => 0,
             }
         };
         object_cost += cost;
     }
-    MovePoints{n: terrain_cost + object_cost + unit_cost}
+    let hexside_cost = match hexside_between(state, from.map_pos, pos.map_pos) {
+        Some(ObjectClass::River) => if unit_type.is_infantry { 4 } else { 8 },
+        Some(ObjectClass::Wall) => if unit_type.is_infantry { 2 } else { 6 },
+        Some(ObjectClass::Hedge) => if unit_type.is_infantry { 1 } else { 4 },
+        _ => 0,
+    };
+    let weather_cost = match state.weather() {
+        Weather::Clear | Weather::Fog => 0,
+        // mud
+        Weather::Rain => if unit_type.is_infantry { 0 } else { 1 },
+        Weather::Snow => 1,
+    };
+    let is_taxed = state.zoc_rule() == ZocRule::Tax
+        && is_in_enemy_zoc(db, state, unit.player_id, map_pos);
+    let zoc_cost = if is_taxed { 4 } else { 0 };
+    MovePoints{n: terrain_cost + object_cost + unit_cost + hexside_cost
+        + weather_cost + zoc_cost + towing_cost}
+}
+
+#[derive(Clone, Copy, Debug)]
+struct QueueItem {
+    /// Weight of the path so far plus the heuristic estimate (if any)
+    priority: i32,
+    weight: i32,
+    pos: ExactPos,
+}
+
+// BinaryHeap is a max-heap, so the ordering is reversed
+impl Ord for QueueItem {
+    fn cmp(&self, other: &QueueItem) -> Ordering {
+        other.priority.cmp(&self.priority)
+    }
+}
+
+impl PartialOrd for QueueItem {
+    fn partial_cmp(&self, other: &QueueItem) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueueItem {
+    fn eq(&self, other: &QueueItem) -> bool {
+        self.priority == other.priority
+    }
+}
+
+impl Eq for QueueItem {}
+
+// Sum of enemies' hit chances (in percents) that costs one move point
+const DANGER_PER_MOVE_POINT: i32 = 10;
+
+/// Danger penalty for every tile: how likely the unit is to be shot there
+/// by the enemies that the `state` knows about
+fn fill_danger_map(db: &Db, state: &State, unit: &Unit, danger: &mut Map<i32>) {
+    for pos in danger.get_iter() {
+        *danger.tile_mut(pos) = 0;
+    }
+    let unit_type = db.unit_type(unit.type_id);
+    for (_, enemy) in state.units() {
+        if enemy.player_id == unit.player_id
+            || !enemy.is_alive
+            || is_loaded_or_attached(enemy)
+        {
+            continue;
+        }
+        let enemy_type = db.unit_type(enemy.type_id);
+        let weapon_type = db.weapon_type(enemy_type.weapon_type_id);
+        let max_distance = if unit_type.is_air {
+            match weapon_type.max_air_distance {
+                Some(max_air_distance) => max_air_distance,
+                None => continue,
+            }
+        } else {
+            weapon_type.max_distance
+        };
+        let mut positions = Vec::new();
+        let range = los_range(state, enemy_type);
+        let f = if unit_type.is_air || enemy_type.is_air {
+            simple_fov
+        } else {
+            fov
+        };
+        f(state, enemy.pos.map_pos, range, &mut |pos| positions.push(pos));
+        for pos in positions {
+            let dist = distance(enemy.pos.map_pos, pos);
+            if dist > max_distance
+                || (!unit_type.is_air && dist < weapon_type.min_distance)
+            {
+                continue;
+            }
+            // the moving unit at the tile
+            let target = Unit {
+                pos: ExactPos {
+                    map_pos: pos,
+                    slot_id: unit.pos.slot_id,
+                },
+                is_entrenched: false,
+                .. unit.clone()
+            };
+            let chance = hit_chance(db, state, enemy, &target);
+            *danger.tile_mut(pos) += chance.n;
+        }
+    }
+    for pos in danger.get_iter() {
+        *danger.tile_mut(pos) /= DANGER_PER_MOVE_POINT;
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Pathfinder {
-    queue: Vec<ExactPos>,
+    queue: BinaryHeap<QueueItem>,
     map: Map<Tile>,
+    danger: Map<i32>,
+    zoc: Map<bool>,
+    mode: PathMode,
     db: Rc<Db>,
 }
 
 impl Pathfinder {
-    pub fn new(db: Rc<Db>, map_size: Size2) -> Pathfinder {
+    pub fn new(db: Rc<Db>, map: &Map<Terrain>) -> Pathfinder {
         Pathfinder {
-            queue: Vec::new(),
-            map: Map::new(map_size),
+            queue: BinaryHeap::new(),
+            map: Map::new_like(map),
+            danger: Map::new_like(map),
+            zoc: Map::new_like(map),
+            mode: PathMode::Fastest,
             db: db,
         }
     }
 
+    pub fn set_mode(&mut self, mode: PathMode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> PathMode {
+        self.mode
+    }
+
     pub fn get_map(&self) -> &Map<Tile> {
         &self.map
     }
@@ -172,25 +413,46 @@ impl Pathfinder {
         state: &State,
         unit: &Unit,
         original_pos: ExactPos,
-        neighbour_pos: ExactPos
+        neighbour_pos: ExactPos,
+        destination: Option<ExactPos>,
     ) {
         let old_cost = self.map.tile(original_pos).cost;
+        let old_weight = self.map.tile(original_pos).weight;
         let tile_cost = tile_cost(&self.db, state, unit, original_pos, neighbour_pos);
-        let tile = self.map.tile_mut(neighbour_pos);
         let new_cost = MovePoints{n: old_cost.n + tile_cost.n};
-        if tile.cost.n > new_cost.n {
+        let new_weight = old_weight + tile_cost.n
+            + *self.danger.tile(neighbour_pos);
+        {
+            let tile = self.map.tile_mut(neighbour_pos);
+            if tile.weight <= new_weight {
+                return;
+            }
             tile.cost = new_cost;
+            tile.weight = new_weight;
             tile.parent = Some(Dir::get_dir_from_to(
                 neighbour_pos.map_pos, original_pos.map_pos));
             tile.slot_id = neighbour_pos.slot_id;
-            self.queue.push(neighbour_pos);
         }
+        let heuristic = match destination {
+            Some(destination) => {
+                let unit_type = self.db.unit_type(unit.type_id);
+                distance(neighbour_pos.map_pos, destination.map_pos).n
+                    * min_tile_cost(unit_type)
+            },
+            None => 0,
+        };
+        self.queue.push(QueueItem {
+            priority: new_weight + heuristic,
+            weight: new_weight,
+            pos: neighbour_pos,
+        });
     }
 
     fn clean_map(&mut self) {
         for pos in self.map.get_iter() {
             let tile = self.map.tile_mut(pos);
             tile.cost = max_cost();
+            tile.weight = max_cost().n;
             tile.parent = None;
             tile.slot_id = SlotId::WholeTile;
         }
@@ -201,19 +463,35 @@ impl Pathfinder {
         state: &State,
         unit: &Unit,
         pos: ExactPos,
+        destination: Option<ExactPos>,
     ) {
         assert!(self.map.is_inboard(pos));
+        let is_in_zoc = *self.zoc.tile(pos);
+        if is_in_zoc
+            && pos != unit.pos
+            && state.zoc_rule() == ZocRule::Stop
+        {
+            return;
+        }
         for dir in dirs() {
             let neighbour_pos = Dir::get_neighbour_pos(pos.map_pos, dir);
-            if self.map.is_inboard(neighbour_pos) {
+            // void tiles are never entered
+            if state.map().is_inboard(neighbour_pos) {
+                // can't slip from one zone of control to another
+                if is_in_zoc && *self.zoc.tile(neighbour_pos) {
+                    continue;
+                }
                 let exact_neighbour_pos = match get_free_exact_pos(
                     &self.db, state, unit.type_id, neighbour_pos
                 ) {
                     Some(pos) => pos,
                     None => continue,
                 };
+                if is_towing_blocked(&self.db, state, unit, pos, exact_neighbour_pos) {
+                    continue;
+                }
                 self.process_neighbour_pos(
-                    state, unit, pos, exact_neighbour_pos);
+                    state, unit, pos, exact_neighbour_pos, destination);
             }
         }
     }
@@ -221,21 +499,81 @@ impl Pathfinder {
     fn push_start_pos_to_queue(&mut self, start_pos: ExactPos) {
         let start_tile = self.map.tile_mut(start_pos);
         start_tile.cost = MovePoints{n: 0};
+        start_tile.weight = 0;
         start_tile.parent = None;
         start_tile.slot_id = start_pos.slot_id;
-        self.queue.push(start_pos);
+        self.queue.push(QueueItem {
+            priority: 0,
+            weight: 0,
+            pos: start_pos,
+        });
     }
 
-    pub fn fill_map(&mut self, state: &State, unit: &Unit) {
-        assert!(self.queue.len() == 0);
+    /// Dijkstra (or A* if `destination` is known) search from the unit's
+    /// position. Stops early when the destination is reached.
+    fn search(
+        &mut self,
+        state: &State,
+        unit: &Unit,
+        destination: Option<ExactPos>,
+    ) {
+        assert!(self.queue.is_empty());
+        assert!(state.map().is_inboard(unit.pos.map_pos));
         self.clean_map();
+        match self.mode {
+            PathMode::Fastest => {
+                for pos in self.danger.get_iter() {
+                    *self.danger.tile_mut(pos) = 0;
+                }
+            },
+            PathMode::Safe => {
+                fill_danger_map(&self.db, state, unit, &mut self.danger);
+            },
+        }
+        let is_zoc_enabled = state.zoc_rule() != ZocRule::Disabled
+            && !self.db.unit_type(unit.type_id).is_air;
+        for pos in self.zoc.get_iter() {
+            *self.zoc.tile_mut(pos) = is_zoc_enabled
+                && is_in_enemy_zoc(&self.db, state, unit.player_id, pos);
+        }
         self.push_start_pos_to_queue(unit.pos);
-        while !self.queue.is_empty() {
-            let pos = self.queue.remove(0);
-            self.try_to_push_neighbours(state, unit, pos);
+        if unit.is_immobilized {
+            self.queue.clear();
+            return;
+        }
+        while let Some(item) = self.queue.pop() {
+            // a cheaper path to this tile was found after the item was pushed
+            if item.weight > self.map.tile(item.pos).weight {
+                continue;
+            }
+            if let Some(destination) = destination {
+                if item.pos.map_pos == destination.map_pos {
+                    self.queue.clear();
+                    return;
+                }
+            }
+            self.try_to_push_neighbours(state, unit, item.pos, destination);
         }
     }
 
+    /// Finds the cheapest paths to all tiles, see `get_path`
+    pub fn fill_map(&mut self, state: &State, unit: &Unit) {
+        self.search(state, unit, None);
+    }
+
+    /// Finds the cheapest path to one tile.
+    /// Faster than `fill_map` + `get_path` but leaves the map
+    /// only partially filled.
+    pub fn find_path(
+        &mut self,
+        state: &State,
+        unit: &Unit,
+        destination: ExactPos,
+    ) -> Option<Vec<ExactPos>> {
+        self.search(state, unit, Some(destination));
+        self.get_path(destination)
+    }
+
     /*
     pub fn is_reachable(&self, pos: ExactPos) -> bool {
         self.map.tile(pos).cost.n != max_cost().n
@@ -245,6 +583,9 @@ impl Pathfinder {
     pub fn get_path(&self, destination: ExactPos) -> Option<Vec<ExactPos>> {
         let mut path = vec![destination];
         let mut pos = destination;
+        if !self.map.is_inboard(pos) {
+            return None;
+        }
         if self.map.tile(pos).cost.n == max_cost().n {
             return None;
         }
@@ -269,3 +610,252 @@ impl Pathfinder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use map::{Map, distance};
+    use dir::{Dir, dirs};
+    use test_utils::{
+        options,
+        new_state,
+        add_unit,
+        map_pos,
+        map01_options,
+        map06_options,
+        bad_weather_options,
+        add_object,
+        hexside,
+    };
+    use ::{
+        ExactPos,
+        SlotId,
+        VehicleDamage,
+        AttackInfo,
+        FireMode,
+        CoreEvent,
+        Options,
+        ZocRule,
+        ObjectClass,
+    };
+    use super::{Pathfinder, PathMode, path_cost, tile_cost, fill_danger_map};
+
+    #[test]
+    fn test_find_path_agrees_with_fill_map() {
+        let (db, mut state) = new_state(&map01_options());
+        let id = add_unit(&db, &mut state, 0, "soldier", map_pos(0, 1));
+        let unit = state.unit(id);
+        let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+        pathfinder.fill_map(&state, unit);
+        let expected: Vec<_> = state.map().get_iter().map(|pos| {
+            let tile = pathfinder.get_map().tile(pos);
+            let destination = ExactPos{map_pos: pos, slot_id: tile.slot_id()};
+            (destination, tile.cost(), pathfinder.get_path(destination))
+        }).collect();
+        for (destination, expected_cost, expected_path) in expected {
+            let path = pathfinder.find_path(&state, unit, destination);
+            assert_eq!(path.is_some(), expected_path.is_some());
+            if let Some(path) = path {
+                assert_eq!(path[0], unit.pos);
+                assert_eq!(*path.last().unwrap(), destination);
+                let cost = path_cost(&db, &state, unit, &path);
+                assert_eq!(cost, expected_cost);
+            }
+        }
+    }
+
+    #[test]
+    fn test_immobilized_unit_has_no_paths() {
+        let (db, mut state) = new_state(&map01_options());
+        let id = add_unit(&db, &mut state, 0, "medium_tank", map_pos(0, 1));
+        state.apply_event(&CoreEvent::AttackUnit {
+            attack_info: AttackInfo {
+                attacker_id: None,
+                defender_id: id,
+                mode: FireMode::Active,
+                killed: 0,
+                suppression: 0,
+                remove_move_points: false,
+                is_ambush: false,
+                is_inderect: false,
+                leave_wrecks: true,
+                attacker_suppression: 0,
+                damage: Some(VehicleDamage::Immobilized),
+            },
+        });
+        let unit = state.unit(id);
+        let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+        let destination = ExactPos{map_pos: map_pos(3, 1), .. unit.pos};
+        assert_eq!(pathfinder.find_path(&state, unit, destination), None);
+    }
+
+    #[test]
+    fn test_safe_path_avoids_enemy_fire() {
+        let (db, mut state) = new_state(&options());
+        let id = add_unit(&db, &mut state, 0, "soldier", map_pos(1, 4));
+        add_unit(&db, &mut state, 1, "medium_tank", map_pos(6, 7));
+        let unit = state.unit(id);
+        let destination = ExactPos{map_pos: map_pos(10, 4), .. unit.pos};
+        let mut danger = Map::new(state.map().size());
+        fill_danger_map(&db, &state, unit, &mut danger);
+        let path_danger = |path: &[ExactPos]| -> i32 {
+            path.iter().map(|pos| *danger.tile(*pos)).sum()
+        };
+        let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+        let fast_path = pathfinder.find_path(&state, unit, destination).unwrap();
+        pathfinder.set_mode(PathMode::Safe);
+        let safe_path = pathfinder.find_path(&state, unit, destination).unwrap();
+        assert!(path_danger(&safe_path) < path_danger(&fast_path));
+        let fast_cost = path_cost(&db, &state, unit, &fast_path);
+        let safe_cost = path_cost(&db, &state, unit, &safe_path);
+        assert!(safe_cost.n >= fast_cost.n);
+    }
+
+    #[test]
+    fn test_no_danger_without_enemies() {
+        let (db, mut state) = new_state(&options());
+        let id = add_unit(&db, &mut state, 0, "soldier", map_pos(1, 4));
+        add_unit(&db, &mut state, 0, "medium_tank", map_pos(6, 7));
+        let mut danger = Map::new(state.map().size());
+        fill_danger_map(&db, &state, state.unit(id), &mut danger);
+        assert!(danger.get_iter().all(|pos| *danger.tile(pos) == 0));
+    }
+
+    #[test]
+    fn test_zoc_map() {
+        let options = Options{zoc_rule: ZocRule::Stop, .. options()};
+        let (db, mut state) = new_state(&options);
+        let id = add_unit(&db, &mut state, 0, "soldier", map_pos(2, 5));
+        let enemy_pos = map_pos(6, 5);
+        add_unit(&db, &mut state, 1, "soldier", enemy_pos);
+        let unit = state.unit(id);
+        let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+        let destination = ExactPos{map_pos: map_pos(9, 5), .. unit.pos};
+        let path = pathfinder.find_path(&state, unit, destination).unwrap();
+        for pos in state.map().get_iter() {
+            let is_zoc = distance(pos, enemy_pos).n == 1;
+            assert_eq!(*pathfinder.zoc.tile(pos), is_zoc);
+        }
+        // units stop when they enter an enemy zone of control
+        for pos in &path[.. path.len() - 1] {
+            assert!(!*pathfinder.zoc.tile(*pos));
+        }
+    }
+
+    #[test]
+    fn test_zoc_tax() {
+        let mut costs = Vec::new();
+        for &rule in &[ZocRule::Disabled, ZocRule::Tax] {
+            let options = Options{zoc_rule: rule, .. options()};
+            let (db, mut state) = new_state(&options);
+            let id = add_unit(&db, &mut state, 0, "soldier", map_pos(2, 5));
+            add_unit(&db, &mut state, 1, "soldier", map_pos(6, 5));
+            let unit = state.unit(id);
+            let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+            let destination = ExactPos{map_pos: map_pos(5, 5), .. unit.pos};
+            let path = pathfinder.find_path(&state, unit, destination).unwrap();
+            costs.push(path_cost(&db, &state, unit, &path).n);
+        }
+        assert!(costs[1] > costs[0]);
+    }
+
+    #[test]
+    fn test_path_goes_around_hole() {
+        let hole = map_pos(7, 3);
+        let mut paths_count = 0;
+        for dir in dirs() {
+            let (db, mut state) = new_state(&map06_options());
+            let opposite_dir = Dir::from_int((dir.to_int() + 3) % 6);
+            let from = Dir::get_neighbour_pos(hole, opposite_dir);
+            let to = Dir::get_neighbour_pos(hole, dir);
+            if !state.map().is_inboard(from) || !state.map().is_inboard(to) {
+                continue;
+            }
+            let id = add_unit(&db, &mut state, 0, "soldier", from);
+            let unit = state.unit(id);
+            let mut pathfinder = Pathfinder::new(db.clone(), state.map());
+            let destination = ExactPos{map_pos: to, .. unit.pos};
+            let path = pathfinder.find_path(&state, unit, destination).unwrap();
+            assert!(path.iter().all(|pos| pos.map_pos != hole), "{:?}", path);
+            // the straight way is two tiles long
+            assert!(path.len() > 3);
+            assert_eq!(pathfinder.get_path(ExactPos{map_pos: hole, .. unit.pos}), None);
+            paths_count += 1;
+        }
+        assert!(paths_count > 0);
+    }
+
+    #[test]
+    fn test_towing_cost_depends_on_towed_type() {
+        let mut costs = Vec::new();
+        for &towed_type_name in &["field_gun", "light_tank"] {
+            let (db, mut state) = new_state(&options());
+            let truck_id = add_unit(&db, &mut state, 0, "truck", map_pos(4, 6));
+            let towed_id = add_unit(&db, &mut state, 0, towed_type_name, map_pos(5, 6));
+            state.apply_event(&CoreEvent::Attach {
+                transporter_id: truck_id,
+                attached_unit_id: towed_id,
+                from: state.unit(truck_id).pos,
+                to: state.unit(towed_id).pos,
+            });
+            let truck = state.unit(truck_id);
+            let to = ExactPos{map_pos: map_pos(6, 6), slot_id: SlotId::WholeTile};
+            costs.push(tile_cost(&db, &state, truck, truck.pos, to).n);
+        }
+        // a gun is much lighter than a tank on a rope
+        assert!(costs[0] < costs[1]);
+    }
+
+    #[test]
+    fn test_snow_slows_movement() {
+        let (db, mut state) = new_state(&options());
+        let (_, mut bad_state) = new_state(&bad_weather_options());
+        let mut costs = Vec::new();
+        for state in &mut [&mut state, &mut bad_state] {
+            let id = add_unit(&db, state, 0, "soldier", map_pos(5, 5));
+            let unit = state.unit(id);
+            let to = ExactPos{map_pos: map_pos(6, 5), slot_id: SlotId::WholeTile};
+            costs.push(tile_cost(&db, state, unit, unit.pos, to).n);
+        }
+        assert!(costs[1] > costs[0]);
+    }
+
+    #[test]
+    fn test_hexsides_cost_move_points() {
+        let (db, mut state) = new_state(&options());
+        let id = add_unit(&db, &mut state, 0, "soldier", map_pos(5, 5));
+        let from = state.unit(id).pos;
+        let to = ExactPos{map_pos: map_pos(6, 5), slot_id: SlotId::WholeTile};
+        let plain_cost = tile_cost(&db, &state, state.unit(id), from, to).n;
+        let hedge = hexside(ObjectClass::Hedge, map_pos(5, 5), Dir::East);
+        let hedge_id = add_object(&mut state, hedge);
+        let hedge_cost = tile_cost(&db, &state, state.unit(id), from, to).n;
+        state.apply_event(&CoreEvent::RemoveObject{id: hedge_id});
+        let river = hexside(ObjectClass::River, map_pos(5, 5), Dir::East);
+        add_object(&mut state, river);
+        let river_cost = tile_cost(&db, &state, state.unit(id), from, to).n;
+        assert!(plain_cost < hedge_cost);
+        assert!(hedge_cost < river_cost);
+    }
+
+    #[test]
+    fn test_towing_slows_down() {
+        let (db, mut state) = new_state(&options());
+        let truck_id = add_unit(&db, &mut state, 0, "truck", map_pos(5, 2));
+        let towing_truck_id = add_unit(&db, &mut state, 0, "truck", map_pos(4, 6));
+        let gun_id = add_unit(&db, &mut state, 0, "field_gun", map_pos(5, 6));
+        state.apply_event(&CoreEvent::Attach {
+            transporter_id: towing_truck_id,
+            attached_unit_id: gun_id,
+            from: state.unit(towing_truck_id).pos,
+            to: state.unit(gun_id).pos,
+        });
+        let mut costs = Vec::new();
+        for &id in &[truck_id, towing_truck_id] {
+            let unit = state.unit(id);
+            let pos = Dir::get_neighbour_pos(unit.pos.map_pos, Dir::East);
+            let to = ExactPos{map_pos: pos, slot_id: SlotId::WholeTile};
+            costs.push(tile_cost(&db, &state, unit, unit.pos, to).n);
+        }
+        assert!(costs[1] > costs[0]);
+    }
+}
@@ -0,0 +1,274 @@
+// Fixtures shared by unit tests of different modules
+
+use std::rc::{Rc};
+use cgmath::{Vector2};
+use db::{Db};
+use unit::{Unit};
+use game_state::{State};
+use fow::{Fow};
+use filter;
+use dir::{Dir};
+use ::{
+    AttackInfo,
+    Core,
+    CoreEvent,
+    ExactPos,
+    FireMode,
+    GameType,
+    MapGenParams,
+    MapPos,
+    Object,
+    ObjectClass,
+    ObjectId,
+    Options,
+    PlayerId,
+    ReactionFireMode,
+    SlotId,
+    TimeOfDay,
+    UnitId,
+    Weather,
+    ZocRule,
+    get_free_exact_pos,
+};
+
+/// A plain 12x14 map without cities, forests and lakes.
+/// Reinforcement sectors are at the left and right edges.
+pub fn options() -> Options {
+    let mut params = MapGenParams::new(0);
+    params.cities_count = 0;
+    params.forests_count = 0;
+    params.lakes_count = 0;
+    Options {
+        game_type: GameType::Hotseat,
+        map_name: "generated".into(),
+        players_count: 2,
+        weather: Weather::Clear,
+        time_of_day: TimeOfDay::Day,
+        zoc_rule: ZocRule::Disabled,
+        map_gen_params: Some(params),
+    }
+}
+
+/// The first hand-made map: a river runs through (6, 7), (5, 8) and (5, 9),
+/// the only bridge is at (5, 8)
+pub fn map01_options() -> Options {
+    Options {
+        map_name: "map01".into(),
+        map_gen_params: None,
+        .. options()
+    }
+}
+
+/// Hex-shaped board with a radius of 5 around (5, 5)
+/// and holes at (3, 7) and (7, 3)
+pub fn map06_options() -> Options {
+    Options {
+        map_name: "map06".into(),
+        map_gen_params: None,
+        .. options()
+    }
+}
+
+/// The plain map at a snowy night
+pub fn bad_weather_options() -> Options {
+    Options {
+        weather: Weather::Snow,
+        time_of_day: TimeOfDay::Night,
+        .. options()
+    }
+}
+
+pub fn new_state(options: &Options) -> (Rc<Db>, State) {
+    let db = Rc::new(Db::new());
+    let state = State::new_full(db.clone(), options);
+    (db, state)
+}
+
+pub fn map_pos(x: i32, y: i32) -> MapPos {
+    MapPos{v: Vector2{x: x, y: y}}
+}
+
+/// Unit with full move and attack points
+pub fn new_unit(
+    db: &Db,
+    id: UnitId,
+    player_id: PlayerId,
+    type_name: &str,
+    pos: ExactPos,
+) -> Unit {
+    let type_id = db.unit_type_id(type_name);
+    let unit_type = db.unit_type(type_id);
+    Unit {
+        id: id,
+        pos: pos,
+        player_id: player_id,
+        type_id: type_id,
+        move_points: Some(unit_type.move_points),
+        attack_points: Some(unit_type.attack_points),
+        reactive_attack_points: Some(unit_type.reactive_attack_points),
+        reaction_fire_mode: ReactionFireMode::Normal,
+        count: unit_type.count,
+        morale: 100,
+        passenger_id: None,
+        attached_unit_id: None,
+        is_alive: true,
+        is_loaded: false,
+        is_attached: false,
+        is_entrenched: false,
+        is_immobilized: false,
+        is_weapon_disabled: false,
+        is_crew_shaken: false,
+    }
+}
+
+pub fn big_building(pos: MapPos) -> Object {
+    Object {
+        class: ObjectClass::Building,
+        pos: ExactPos{map_pos: pos, slot_id: SlotId::WholeTile},
+        timer: None,
+        owner_id: None,
+        structural_points: Some(12),
+    }
+}
+
+pub fn smoke(pos: MapPos, timer: i32) -> Object {
+    Object {
+        class: ObjectClass::Smoke,
+        pos: ExactPos{map_pos: pos, slot_id: SlotId::WholeTile},
+        timer: Some(timer),
+        owner_id: None,
+        structural_points: None,
+    }
+}
+
+/// Wall, hedge or river on the edge between `pos` and its neighbour
+pub fn hexside(class: ObjectClass, pos: MapPos, dir: Dir) -> Object {
+    Object {
+        class: class,
+        pos: ExactPos{map_pos: pos, slot_id: SlotId::TwoTiles(dir)},
+        timer: None,
+        owner_id: None,
+        structural_points: None,
+    }
+}
+
+pub fn add_object(state: &mut State, object: Object) -> ObjectId {
+    let id = ObjectId{id: state.objects().keys().map(|id| id.id).max().unwrap_or(0) + 1};
+    state.apply_event(&CoreEvent::CreateObject {
+        id: id,
+        object: object,
+    });
+    id
+}
+
+fn free_pos(db: &Db, state: &State, type_name: &str, pos: MapPos) -> ExactPos {
+    let type_id = db.unit_type_id(type_name);
+    get_free_exact_pos(db, state, type_id, pos).expect("No free slot")
+}
+
+/// Puts a new unit into a full state bypassing reinforcement rules
+pub fn add_unit(
+    db: &Db,
+    state: &mut State,
+    player_id: i32,
+    type_name: &str,
+    pos: MapPos,
+) -> UnitId {
+    let id = UnitId{id: state.units().map(|(id, _)| id.id).max().unwrap_or(0) + 1};
+    let exact_pos = free_pos(db, state, type_name, pos);
+    let unit = new_unit(
+        db, id, PlayerId{id: player_id}, type_name, exact_pos);
+    state.apply_event(&CoreEvent::ShowUnit{unit_info: unit});
+    id
+}
+
+/// What the given player sees in a full state
+pub fn new_fow(db: &Rc<Db>, state: &State, player_id: i32) -> Fow {
+    let player_id = PlayerId{id: player_id};
+    let mut fow = Fow::new(db.clone(), state.map(), player_id);
+    fow.remember_objects(state);
+    // EndTurn makes Fow forget everything and look again
+    fow.apply_event(state, &CoreEvent::EndTurn {
+        old_id: player_id,
+        new_id: player_id,
+    });
+    fow
+}
+
+/// Switches a full state to the given player's point of view
+pub fn to_partial(db: &Rc<Db>, state: &mut State, player_id: i32) {
+    let fow = new_fow(db, state, player_id);
+    state.to_partial(fow);
+}
+/// Puts a new unit into the core and updates what every player sees
+pub fn add_core_unit(
+    core: &mut Core,
+    player_id: i32,
+    type_name: &str,
+    pos: MapPos,
+) -> UnitId {
+    let id = core.get_new_unit_id();
+    let exact_pos = free_pos(&core.db, &core.state, type_name, pos);
+    let unit = new_unit(
+        &core.db, id, PlayerId{id: player_id}, type_name, exact_pos);
+    core.state.apply_event(&CoreEvent::ShowUnit{unit_info: unit});
+    update_fows(core);
+    id
+}
+
+/// Recalculates fog of war of all players from scratch
+pub fn update_fows(core: &mut Core) {
+    let state = &core.state;
+    for (&player_id, i) in &mut core.players_info {
+        // EndTurn makes Fow forget everything and look again
+        let end_turn = CoreEvent::EndTurn {
+            old_id: player_id,
+            new_id: player_id,
+        };
+        i.fow_mut().apply_event(state, &end_turn);
+        i.visible_enemies = filter::get_visible_enemies(
+            state, i.fow(), player_id);
+    }
+}
+
+pub fn add_core_object(core: &mut Core, object: Object) -> ObjectId {
+    let id = core.get_new_object_id();
+    core.do_core_event(&CoreEvent::CreateObject {
+        id: id,
+        object: object,
+    });
+    id
+}
+
+/// Puts the passenger into the transporter
+pub fn load_unit(core: &mut Core, transporter_id: UnitId, passenger_id: UnitId) {
+    let from = core.state.unit(passenger_id).pos;
+    let to = core.state.unit(transporter_id).pos;
+    core.do_core_event(&CoreEvent::LoadUnit {
+        transporter_id: Some(transporter_id),
+        passenger_id: passenger_id,
+        from: from,
+        to: to,
+    });
+}
+
+/// Attack that kills one soldier or the whole vehicle
+pub fn killing_attack(
+    attacker_id: UnitId,
+    defender_id: UnitId,
+    leave_wrecks: bool,
+) -> AttackInfo {
+    AttackInfo {
+        attacker_id: Some(attacker_id),
+        defender_id: defender_id,
+        mode: FireMode::Active,
+        killed: 1,
+        suppression: 0,
+        remove_move_points: false,
+        is_ambush: false,
+        is_inderect: false,
+        leave_wrecks: leave_wrecks,
+        attacker_suppression: 0,
+        damage: None,
+    }
+}
@@ -1,3 +1,4 @@
+use dir::{Dir};
 use ::{
     ReactionFireMode,
     MovePoints,
@@ -29,6 +30,20 @@ pub struct Unit {
     pub is_alive: bool,
     pub is_loaded: bool,
     pub is_attached: bool,
+    pub is_entrenched: bool,
+    pub is_immobilized: bool,
+    pub is_weapon_disabled: bool,
+    pub is_crew_shaken: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct SmokeInfo {
+    /// Number of full rounds the smoke lasts
+    pub duration: i32,
+
+    /// Neighbour tiles covered in addition to the target tile,
+    /// the whole pattern gets a random rotation
+    pub pattern: Vec<Dir>,
 }
 
 #[derive(Clone, Debug)]
@@ -42,7 +57,7 @@ pub struct WeaponType {
     pub max_air_distance: Option<Distance>,
     pub is_inderect: bool,
     pub reaction_fire: bool,
-    pub smoke: Option<i32>,
+    pub smoke: Option<SmokeInfo>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -57,6 +72,7 @@ pub struct UnitType {
     pub toughness: i32,
     pub weapon_skill: i32,
     pub weapon_type_id: WeaponTypeId,
+    pub close_assault_weapon_type_id: Option<WeaponTypeId>,
     pub move_points: MovePoints,
     pub attack_points: AttackPoints,
     pub reactive_attack_points: AttackPoints,
@@ -67,5 +83,6 @@ pub struct UnitType {
     pub is_air: bool,
     pub is_infantry: bool,
     pub can_be_towed: bool,
+    pub is_engineer: bool,
     pub cost: ReinforcementPoints,
 }
@@ -3,10 +3,11 @@ use std::collections::{HashMap};
 use cgmath::{Vector2};
 use glutin::{self, Event, MouseButton, VirtualKeyCode};
 use glutin::ElementState::{Released};
-use core::{self, ObjectClass, UnitId, MapPos, ExactPos, HitChance};
+use core::{self, ObjectClass, ObjectId, UnitId, MapPos, ExactPos, HitChance};
 use core::game_state::{State};
 use core::db::{Db};
 use core::check::{check_command};
+use core::pathfinder::{Pathfinder, PathMode};
 use types::{Time, ScreenPos};
 use screen::{Screen, ScreenCommand, EventStatus};
 use context::{Context};
@@ -43,6 +44,29 @@ fn can_unload_unit(
     }
 }
 
+fn can_split_unit(
+    db: &Db,
+    state: &State,
+    unit_id: UnitId,
+    pos: MapPos,
+) -> Option<ExactPos> {
+    let unit = state.unit(unit_id);
+    let exact_pos = match core::get_free_exact_pos(db, state, unit.type_id, pos) {
+        Some(pos) => pos,
+        None => return None,
+    };
+    let command = core::Command::Split {
+        unit_id: unit_id,
+        count: unit.count / 2,
+        pos: exact_pos,
+    };
+    if check_command(db, unit.player_id, state, &command).is_ok() {
+        Some(exact_pos)
+    } else {
+        None
+    }
+}
+
 fn can_detach_unit(
     db: &Db,
     state: &State,
@@ -69,10 +93,27 @@ fn can_detach_unit(
     }
 }
 
+/// Passengers of enemy transporters are never shown,
+/// so we only know if somebody was seen boarding
+fn enemy_unit_name(db: &Db, state: &State, unit_id: UnitId) -> String {
+    let unit = state.unit(unit_id);
+    let unit_type = db.unit_type(unit.type_id);
+    if !unit_type.is_transporter {
+        return unit_type.name.clone();
+    }
+    let status = if state.is_transporter_occupied(unit) {
+        "occupied"
+    } else {
+        "unknown"
+    };
+    format!("{}, {}", unit_type.name, status)
+}
+
 pub fn get_options(
     core: &core::Core,
     player_info: &PlayerInfo,
     selected_unit_id: Option<UnitId>,
+    group_unit_ids: &[UnitId],
     pos: MapPos,
 ) -> Options {
     let state = &player_info.game_state;
@@ -114,8 +155,21 @@ pub fn get_options(
     for unit_id in unit_ids {
         let unit = state.unit(unit_id);
         let unit_type = db.unit_type(unit.type_id);
+        let capture_command = core::Command::Capture {
+            unit_id: selected_unit_id,
+            target_id: unit_id,
+        };
+        if check_command(db, player_id, state, &capture_command).is_ok() {
+            options.captures.push(unit_id);
+        }
         if unit.player_id == player_id {
             if unit_id == selected_unit_id {
+                let entrench_command = core::Command::Entrench {
+                    unit_id: selected_unit_id,
+                };
+                if check_command(db, player_id, state, &entrench_command).is_ok() {
+                    options.entrench = Some(selected_unit_id);
+                }
                 if unit_type.attack_points.n != 0
                     || unit_type.reactive_attack_points.n != 0
                 {
@@ -128,6 +182,11 @@ pub fn get_options(
             } else {
                 if unit.is_alive {
                     options.selects.push(unit_id);
+                    if !group_unit_ids.contains(&unit_id)
+                        && !core::is_loaded_or_attached(unit)
+                    {
+                        options.group_adds.push(unit_id);
+                    }
                     let load_command = core::Command::LoadUnit {
                         transporter_id: selected_unit_id,
                         passenger_id: unit_id,
@@ -136,6 +195,13 @@ pub fn get_options(
                         options.loads.push(unit_id);
                     }
                 }
+                let merge_command = core::Command::Merge {
+                    unit_id: selected_unit_id,
+                    into_id: unit_id,
+                };
+                if check_command(db, player_id, state, &merge_command).is_ok() {
+                    options.merges.push(unit_id);
+                }
                 let attach_command = core::Command::Attach {
                     transporter_id: selected_unit_id,
                     attached_unit_id: unit_id,
@@ -155,6 +221,15 @@ pub fn get_options(
             if check_command(db, player_id, state, &attack_command).is_ok() {
                 options.attacks.push((unit_id, hit_chance));
             }
+            let close_assault_command = core::Command::CloseAssault {
+                attacker_id: attacker.id,
+                defender_id: defender.id,
+            };
+            if check_command(db, player_id, state, &close_assault_command).is_ok() {
+                let hit_chance = core::close_assault_hit_chance(
+                    db, state, attacker, defender);
+                options.close_assaults.push((unit_id, hit_chance));
+            }
         }
     }
     if check_command(db, player_id, state, &core::Command::Smoke {
@@ -163,12 +238,43 @@ pub fn get_options(
     }).is_ok() {
         options.smoke_pos = Some(pos);
     }
+    for (&object_id, object) in state.objects() {
+        if object.pos.map_pos != pos || object.class != ObjectClass::Bridge {
+            continue;
+        }
+        if check_command(db, player_id, state, &core::Command::Demolish {
+            unit_id: selected_unit_id,
+            object_id: object_id,
+        }).is_ok() {
+            options.demolish_object_id = Some(object_id);
+        }
+    }
+    for (&object_id, object) in state.objects() {
+        if object.pos.map_pos != pos {
+            continue;
+        }
+        if check_command(db, player_id, state, &core::Command::AttackObject {
+            attacker_id: selected_unit_id,
+            object_id: object_id,
+        }).is_ok() {
+            options.shell_object_id = Some(object_id);
+        }
+    }
+    if check_command(db, player_id, state, &core::Command::BuildBridge {
+        unit_id: selected_unit_id,
+        pos: pos,
+    }).is_ok() {
+        options.build_bridge_pos = Some(pos);
+    }
     if let Some(pos) = can_unload_unit(db, state, selected_unit_id, pos) {
         options.unload_pos = Some(pos);
     }
     if let Some(pos) = can_detach_unit(db, state, selected_unit_id, pos) {
         options.detach_pos = Some(pos);
     }
+    if let Some(pos) = can_split_unit(db, state, selected_unit_id, pos) {
+        options.split_pos = Some(pos);
+    }
     let selected_unit = state.unit(selected_unit_id);
     let selected_unit_type = db.unit_type(selected_unit.type_id);
     if let Some(destination) = core::get_free_exact_pos(
@@ -192,6 +298,64 @@ pub fn get_options(
             {
                 options.hunt_pos = Some(destination);
             }
+            let mut safe_pathfinder = Pathfinder::new(
+                db.clone(), state.map());
+            safe_pathfinder.set_mode(PathMode::Safe);
+            let safe_path = safe_pathfinder.find_path(
+                state, selected_unit, destination);
+            if let Some(safe_path) = safe_path {
+                let safe_move_command = core::Command::Move {
+                    unit_id: selected_unit_id,
+                    path: safe_path.clone(),
+                    mode: core::MoveMode::Fast,
+                };
+                if safe_path != path && check_command(
+                    db, player_id, state, &safe_move_command).is_ok()
+                {
+                    options.safe_move_path = Some(safe_path);
+                }
+            }
+        }
+    }
+    let is_reachable = core::get_free_exact_pos(
+        db, state, selected_unit.type_id, pos,
+    ).and_then(|destination| pathfinder.get_path(destination)).is_some();
+    if let Some(order) = state.move_orders().get(&selected_unit_id) {
+        let mut order = order.clone();
+        order.waypoints.push(pos);
+        let command = core::Command::SetMoveOrder {
+            unit_id: selected_unit_id,
+            order: order,
+        };
+        if is_reachable
+            && check_command(db, player_id, state, &command).is_ok()
+        {
+            options.add_waypoint_pos = Some(pos);
+        }
+        options.cancel_move_order = Some(selected_unit_id);
+    }
+    if !group_unit_ids.is_empty() && selected_unit.pos.map_pos != pos {
+        let mut unit_ids = vec![selected_unit_id];
+        unit_ids.extend_from_slice(group_unit_ids);
+        let command = core::Command::GroupMove {
+            unit_ids: unit_ids,
+            destination: pos,
+            mode: core::MoveMode::Fast,
+        };
+        if check_command(db, player_id, state, &command).is_ok() {
+            options.group_move_pos = Some(pos);
+        }
+    }
+    if selected_unit.pos.map_pos != pos && is_reachable {
+        let order = core::MoveOrder {
+            waypoints: vec![pos],
+            mode: core::MoveMode::Fast,
+        };
+        if check_command(db, player_id, state, &core::Command::SetMoveOrder {
+            unit_id: selected_unit_id,
+            order: order,
+        }).is_ok() {
+            options.move_order_pos = Some(pos);
         }
     }
     options
@@ -200,9 +364,13 @@ pub fn get_options(
 #[derive(Clone, Debug)]
 pub enum Command {
     Select{id: UnitId},
+    AddToGroup{id: UnitId},
     Move{pos: ExactPos},
     Hunt{pos: ExactPos},
+    SafeMove{path: Vec<ExactPos>},
+    GroupMove{pos: MapPos},
     Attack{id: UnitId},
+    CloseAssault{id: UnitId},
     LoadUnit{passenger_id: UnitId},
     Attach{attached_unit_id: UnitId},
     UnloadUnit{pos: ExactPos},
@@ -210,22 +378,46 @@ pub enum Command {
     EnableReactionFire{id: UnitId},
     DisableReactionFire{id: UnitId},
     Smoke{pos: MapPos},
+    Entrench{id: UnitId},
+    Split{pos: ExactPos},
+    Merge{into_id: UnitId},
+    Capture{target_id: UnitId},
+    Demolish{object_id: ObjectId},
+    Shell{object_id: ObjectId},
+    BuildBridge{pos: MapPos},
+    SetMoveOrder{pos: MapPos},
+    AddWaypoint{pos: MapPos},
+    CancelMoveOrder{id: UnitId},
     CallReiforcements{pos: MapPos},
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Options {
     selects: Vec<UnitId>,
+    group_adds: Vec<UnitId>,
     attacks: Vec<(UnitId, HitChance)>,
+    close_assaults: Vec<(UnitId, HitChance)>,
     loads: Vec<UnitId>,
     attaches: Vec<UnitId>,
+    merges: Vec<UnitId>,
+    captures: Vec<UnitId>,
     move_pos: Option<ExactPos>,
     hunt_pos: Option<ExactPos>,
+    safe_move_path: Option<Vec<ExactPos>>,
+    group_move_pos: Option<MapPos>,
     unload_pos: Option<ExactPos>,
     detach_pos: Option<ExactPos>,
     smoke_pos: Option<MapPos>,
     enable_reaction_fire: Option<UnitId>,
     disable_reaction_fire: Option<UnitId>,
+    entrench: Option<UnitId>,
+    split_pos: Option<ExactPos>,
+    demolish_object_id: Option<ObjectId>,
+    shell_object_id: Option<ObjectId>,
+    build_bridge_pos: Option<MapPos>,
+    move_order_pos: Option<MapPos>,
+    add_waypoint_pos: Option<MapPos>,
+    cancel_move_order: Option<UnitId>,
     reinforcements_pos: Option<MapPos>,
 }
 
@@ -233,16 +425,30 @@ impl Options {
     pub fn new() -> Options {
         Options {
             selects: Vec::new(),
+            group_adds: Vec::new(),
             attacks: Vec::new(),
+            close_assaults: Vec::new(),
             loads: Vec::new(),
             attaches: Vec::new(),
+            merges: Vec::new(),
+            captures: Vec::new(),
             move_pos: None,
             hunt_pos: None,
+            safe_move_path: None,
+            group_move_pos: None,
             unload_pos: None,
             detach_pos: None,
             smoke_pos: None,
             enable_reaction_fire: None,
             disable_reaction_fire: None,
+            entrench: None,
+            split_pos: None,
+            demolish_object_id: None,
+            shell_object_id: None,
+            build_bridge_pos: None,
+            move_order_pos: None,
+            add_waypoint_pos: None,
+            cancel_move_order: None,
             reinforcements_pos: None,
         }
     }
@@ -260,16 +466,30 @@ pub struct ContextMenuPopup {
     button_manager: ButtonManager,
     options: Options,
     select_button_ids: HashMap<ButtonId, UnitId>,
+    group_add_button_ids: HashMap<ButtonId, UnitId>,
     attack_button_ids: HashMap<ButtonId, UnitId>,
+    close_assault_button_ids: HashMap<ButtonId, UnitId>,
     load_button_ids: HashMap<ButtonId, UnitId>,
     attach_button_ids: HashMap<ButtonId, UnitId>,
+    merge_button_ids: HashMap<ButtonId, UnitId>,
+    capture_button_ids: HashMap<ButtonId, UnitId>,
     move_button_id: Option<ButtonId>,
     hunt_button_id: Option<ButtonId>,
+    safe_move_button_id: Option<ButtonId>,
+    group_move_button_id: Option<ButtonId>,
     unload_unit_button_id: Option<ButtonId>,
     detach_button_id: Option<ButtonId>,
     smoke_button_id: Option<ButtonId>,
     enable_reaction_fire_button_id: Option<ButtonId>,
     disable_reaction_fire_button_id: Option<ButtonId>,
+    entrench_button_id: Option<ButtonId>,
+    split_button_id: Option<ButtonId>,
+    demolish_button_id: Option<ButtonId>,
+    shell_button_id: Option<ButtonId>,
+    build_bridge_button_id: Option<ButtonId>,
+    move_order_button_id: Option<ButtonId>,
+    add_waypoint_button_id: Option<ButtonId>,
+    cancel_move_order_button_id: Option<ButtonId>,
     call_reinforcements_button_id: Option<ButtonId>,
 }
 
@@ -284,16 +504,30 @@ impl ContextMenuPopup {
     ) -> ContextMenuPopup {
         let mut button_manager = ButtonManager::new();
         let mut select_button_ids = HashMap::new();
+        let mut group_add_button_ids = HashMap::new();
         let mut attack_button_ids = HashMap::new();
+        let mut close_assault_button_ids = HashMap::new();
         let mut load_button_ids = HashMap::new();
         let mut attach_button_ids = HashMap::new();
+        let mut merge_button_ids = HashMap::new();
+        let mut capture_button_ids = HashMap::new();
         let mut move_button_id = None;
         let mut hunt_button_id = None;
+        let mut safe_move_button_id = None;
+        let mut group_move_button_id = None;
         let mut unload_unit_button_id = None;
         let mut detach_button_id = None;
         let mut smoke_button_id = None;
         let mut enable_reaction_fire_button_id = None;
         let mut disable_reaction_fire_button_id = None;
+        let mut entrench_button_id = None;
+        let mut split_button_id = None;
+        let mut demolish_button_id = None;
+        let mut shell_button_id = None;
+        let mut build_bridge_button_id = None;
+        let mut move_order_button_id = None;
+        let mut add_waypoint_button_id = None;
+        let mut cancel_move_order_button_id = None;
         let mut call_reinforcements_button_id = None;
         let mut pos = pos;
         let text_size = basic_text_size(context);
@@ -307,14 +541,29 @@ impl ContextMenuPopup {
             select_button_ids.insert(button_id, unit_id);
             pos.v.y -= vstep;
         }
-        for &(unit_id, hit_chance) in &options.attacks {
+        for &unit_id in &options.group_adds {
             let unit_type = db.unit_type(state.unit(unit_id).type_id);
-            let text = format!("attack <{}> ({}%)", unit_type.name, hit_chance.n);
+            let button_id = button_manager.add_button(
+                Button::new(context, &format!("add to group <{}>", unit_type.name), pos));
+            group_add_button_ids.insert(button_id, unit_id);
+            pos.v.y -= vstep;
+        }
+        for &(unit_id, hit_chance) in &options.attacks {
+            let name = enemy_unit_name(db, state, unit_id);
+            let text = format!("attack <{}> ({}%)", name, hit_chance.n);
             let button_id = button_manager.add_button(
                 Button::new(context, &text, pos));
             attack_button_ids.insert(button_id, unit_id);
             pos.v.y -= vstep;
         }
+        for &(unit_id, hit_chance) in &options.close_assaults {
+            let name = enemy_unit_name(db, state, unit_id);
+            let text = format!("assault <{}> ({}%)", name, hit_chance.n);
+            let button_id = button_manager.add_button(
+                Button::new(context, &text, pos));
+            close_assault_button_ids.insert(button_id, unit_id);
+            pos.v.y -= vstep;
+        }
         for &unit_id in &options.loads {
             let unit_type = db.unit_type(state.unit(unit_id).type_id);
             let button_id = button_manager.add_button(
@@ -329,6 +578,20 @@ impl ContextMenuPopup {
             attach_button_ids.insert(button_id, unit_id);
             pos.v.y -= vstep;
         }
+        for &unit_id in &options.merges {
+            let unit_type = db.unit_type(state.unit(unit_id).type_id);
+            let button_id = button_manager.add_button(
+                Button::new(context, &format!("merge into <{}>", unit_type.name), pos));
+            merge_button_ids.insert(button_id, unit_id);
+            pos.v.y -= vstep;
+        }
+        for &unit_id in &options.captures {
+            let unit_type = db.unit_type(state.unit(unit_id).type_id);
+            let button_id = button_manager.add_button(
+                Button::new(context, &format!("crew <{}>", unit_type.name), pos));
+            capture_button_ids.insert(button_id, unit_id);
+            pos.v.y -= vstep;
+        }
         if options.move_pos.is_some() {
             move_button_id = Some(button_manager.add_button(
                 Button::new(context, "move", pos)));
@@ -339,6 +602,31 @@ impl ContextMenuPopup {
                 Button::new(context, "hunt", pos)));
             pos.v.y -= vstep;
         }
+        if options.safe_move_path.is_some() {
+            safe_move_button_id = Some(button_manager.add_button(
+                Button::new(context, "move (safe route)", pos)));
+            pos.v.y -= vstep;
+        }
+        if options.group_move_pos.is_some() {
+            group_move_button_id = Some(button_manager.add_button(
+                Button::new(context, "group move", pos)));
+            pos.v.y -= vstep;
+        }
+        if options.move_order_pos.is_some() {
+            move_order_button_id = Some(button_manager.add_button(
+                Button::new(context, "move order", pos)));
+            pos.v.y -= vstep;
+        }
+        if options.add_waypoint_pos.is_some() {
+            add_waypoint_button_id = Some(button_manager.add_button(
+                Button::new(context, "add waypoint", pos)));
+            pos.v.y -= vstep;
+        }
+        if options.cancel_move_order.is_some() {
+            cancel_move_order_button_id = Some(button_manager.add_button(
+                Button::new(context, "cancel move order", pos)));
+            pos.v.y -= vstep;
+        }
         if options.enable_reaction_fire.is_some() {
             enable_reaction_fire_button_id = Some(button_manager.add_button(
                 Button::new(context, "enable reaction fire", pos)));
@@ -349,6 +637,31 @@ impl ContextMenuPopup {
                 Button::new(context, "disable reaction fire", pos)));
             pos.v.y -= vstep;
         }
+        if options.entrench.is_some() {
+            entrench_button_id = Some(button_manager.add_button(
+                Button::new(context, "entrench", pos)));
+            pos.v.y -= vstep;
+        }
+        if options.split_pos.is_some() {
+            split_button_id = Some(button_manager.add_button(
+                Button::new(context, "split", pos)));
+            pos.v.y -= vstep;
+        }
+        if options.demolish_object_id.is_some() {
+            demolish_button_id = Some(button_manager.add_button(
+                Button::new(context, "demolish bridge", pos)));
+            pos.v.y -= vstep;
+        }
+        if options.shell_object_id.is_some() {
+            shell_button_id = Some(button_manager.add_button(
+                Button::new(context, "shell", pos)));
+            pos.v.y -= vstep;
+        }
+        if options.build_bridge_pos.is_some() {
+            build_bridge_button_id = Some(button_manager.add_button(
+                Button::new(context, "build bridge", pos)));
+            pos.v.y -= vstep;
+        }
         if options.reinforcements_pos.is_some() {
             call_reinforcements_button_id = Some(button_manager.add_button(
                 Button::new(context, "reinforcements", pos)));
@@ -385,16 +698,30 @@ impl ContextMenuPopup {
             game_screen_tx: tx,
             button_manager: button_manager,
             select_button_ids: select_button_ids,
+            group_add_button_ids: group_add_button_ids,
             attack_button_ids: attack_button_ids,
+            close_assault_button_ids: close_assault_button_ids,
             load_button_ids: load_button_ids,
             attach_button_ids: attach_button_ids,
+            merge_button_ids: merge_button_ids,
+            capture_button_ids: capture_button_ids,
             move_button_id: move_button_id,
             hunt_button_id: hunt_button_id,
+            safe_move_button_id: safe_move_button_id,
+            group_move_button_id: group_move_button_id,
             unload_unit_button_id: unload_unit_button_id,
             detach_button_id: detach_button_id,
             smoke_button_id: smoke_button_id,
             enable_reaction_fire_button_id: enable_reaction_fire_button_id,
             disable_reaction_fire_button_id: disable_reaction_fire_button_id,
+            entrench_button_id: entrench_button_id,
+            split_button_id: split_button_id,
+            demolish_button_id: demolish_button_id,
+            shell_button_id: shell_button_id,
+            build_bridge_button_id: build_bridge_button_id,
+            move_order_button_id: move_order_button_id,
+            add_waypoint_button_id: add_waypoint_button_id,
+            cancel_move_order_button_id: cancel_move_order_button_id,
             call_reinforcements_button_id: call_reinforcements_button_id,
             options: options,
         }
@@ -427,12 +754,24 @@ impl ContextMenuPopup {
             });
             return;
         }
+        if let Some(&unit_id) = self.group_add_button_ids.get(&button_id) {
+            self.return_command(context, Command::AddToGroup {
+                id: unit_id,
+            });
+            return;
+        }
         if let Some(&unit_id) = self.attack_button_ids.get(&button_id) {
             self.return_command(context, Command::Attack {
                 id: unit_id,
             });
             return;
         }
+        if let Some(&unit_id) = self.close_assault_button_ids.get(&button_id) {
+            self.return_command(context, Command::CloseAssault {
+                id: unit_id,
+            });
+            return;
+        }
         if let Some(&unit_id) = self.load_button_ids.get(&button_id) {
             self.return_command(context, Command::LoadUnit {
                 passenger_id: unit_id,
@@ -445,6 +784,18 @@ impl ContextMenuPopup {
             });
             return;
         }
+        if let Some(&unit_id) = self.capture_button_ids.get(&button_id) {
+            self.return_command(context, Command::Capture {
+                target_id: unit_id,
+            });
+            return;
+        }
+        if let Some(&unit_id) = self.merge_button_ids.get(&button_id) {
+            self.return_command(context, Command::Merge {
+                into_id: unit_id,
+            });
+            return;
+        }
         let id = Some(button_id);
         if id == self.move_button_id {
             self.return_command(context, Command::Move {
@@ -454,6 +805,14 @@ impl ContextMenuPopup {
             self.return_command(context, Command::Hunt {
                 pos: self.options.move_pos.unwrap(),
             });
+        } else if id == self.safe_move_button_id {
+            self.return_command(context, Command::SafeMove {
+                path: self.options.safe_move_path.clone().unwrap(),
+            });
+        } else if id == self.group_move_button_id {
+            self.return_command(context, Command::GroupMove {
+                pos: self.options.group_move_pos.unwrap(),
+            });
         } else if id == self.unload_unit_button_id {
             self.return_command(context, Command::UnloadUnit {
                 pos: self.options.unload_pos.unwrap(),
@@ -474,6 +833,38 @@ impl ContextMenuPopup {
             self.return_command(context, Command::DisableReactionFire {
                 id: self.options.disable_reaction_fire.unwrap(),
             });
+        } else if id == self.entrench_button_id {
+            self.return_command(context, Command::Entrench {
+                id: self.options.entrench.unwrap(),
+            });
+        } else if id == self.split_button_id {
+            self.return_command(context, Command::Split {
+                pos: self.options.split_pos.unwrap(),
+            });
+        } else if id == self.demolish_button_id {
+            self.return_command(context, Command::Demolish {
+                object_id: self.options.demolish_object_id.unwrap(),
+            });
+        } else if id == self.shell_button_id {
+            self.return_command(context, Command::Shell {
+                object_id: self.options.shell_object_id.unwrap(),
+            });
+        } else if id == self.build_bridge_button_id {
+            self.return_command(context, Command::BuildBridge {
+                pos: self.options.build_bridge_pos.unwrap(),
+            });
+        } else if id == self.move_order_button_id {
+            self.return_command(context, Command::SetMoveOrder {
+                pos: self.options.move_order_pos.unwrap(),
+            });
+        } else if id == self.add_waypoint_button_id {
+            self.return_command(context, Command::AddWaypoint {
+                pos: self.options.add_waypoint_pos.unwrap(),
+            });
+        } else if id == self.cancel_move_order_button_id {
+            self.return_command(context, Command::CancelMoveOrder {
+                id: self.options.cancel_move_order.unwrap(),
+            });
         } else if id == self.call_reinforcements_button_id {
             self.return_command(context, Command::CallReiforcements {
                 pos: self.options.reinforcements_pos.unwrap(),
@@ -12,7 +12,9 @@ use core::{
     PlayerId,
     SectorId,
     MapPos,
-    ObjectId
+    Object,
+    ObjectId,
+    ObjectClass
 };
 use core::db::{Db};
 use types::{WorldPos, Time, Speed};
@@ -163,7 +165,13 @@ fn get_unit_scene_nodes(
     } else {
         WRECKS_COLOR
     };
-    let count = db.unit_type(unit_info.type_id).count;
+    // squads can be split or merged so alive units
+    // are drawn with their real size
+    let count = if unit_info.is_alive {
+        unit_info.count
+    } else {
+        db.unit_type(unit_info.type_id).count
+    };
     let mut vec = Vec::new();
     if count == 1 {
         vec![SceneNode {
@@ -229,6 +237,7 @@ impl EventVisualizer for EventCreateUnitVisualizer {
 pub struct EventAttackUnitVisualizer {
     defender_node_id: NodeId,
     is_target_destroyed: bool,
+    is_target_abandoned: bool,
     move_helper: MoveHelper,
     shell_move: Option<MoveHelper>,
     shell_node_id: Option<NodeId>,
@@ -260,8 +269,14 @@ impl EventAttackUnitVisualizer {
             let attacker_node_id = scene.unit_id_to_node_id(attacker_id);
             let attacker_pos = scene.node(attacker_node_id).pos;
             let attacker_map_pos = state.unit(attacker_id).pos.map_pos;
-            if attack_info.mode == core::FireMode::Reactive {
-                map_text.add_text(attacker_map_pos, "reaction fire");
+            match attack_info.mode {
+                core::FireMode::Reactive => {
+                    map_text.add_text(attacker_map_pos, "reaction fire");
+                },
+                core::FireMode::CloseAssault => {
+                    map_text.add_text(attacker_map_pos, "close assault");
+                },
+                core::FireMode::Active => {},
             }
             shell_node_id = Some(scene.add_node(SceneNode {
                 pos: from,
@@ -283,21 +298,35 @@ impl EventAttackUnitVisualizer {
                 defender.pos.map_pos,
                 &format!("-{}", attack_info.killed),
             );
+        } else if let Some(damage) = attack_info.damage {
+            let text = match damage {
+                core::VehicleDamage::Immobilized => "immobilized",
+                core::VehicleDamage::WeaponDisabled => "weapon disabled",
+                core::VehicleDamage::CrewShaken => "crew shaken",
+                core::VehicleDamage::Abandoned => "abandoned",
+            };
+            map_text.add_text(defender.pos.map_pos, text);
         } else {
             map_text.add_text(defender.pos.map_pos, "miss");
         }
         let is_target_suppressed = defender.morale < 50
             && defender.morale + attack_info.suppression >= 50;
-        if is_target_destroyed {
+        let is_target_abandoned
+            = attack_info.damage == Some(core::VehicleDamage::Abandoned);
+        if is_target_destroyed || is_target_abandoned {
             if let Some(attached_unit_id) = defender.attached_unit_id {
                 let attached_unit = state.unit(attached_unit_id);
                 let attached_unit_mesh_id = unit_type_visual_info
                     .get(attached_unit.type_id).mesh_id;
+                // towed unit is left behind without a crew
                 show_unit_at(
                     db,
                     state,
                     scene,
-                    attached_unit,
+                    &Unit {
+                        is_alive: false,
+                        .. attached_unit.clone()
+                    },
                     attached_unit_mesh_id,
                     mesh_ids.marker_mesh_id,
                 );
@@ -316,6 +345,7 @@ impl EventAttackUnitVisualizer {
             defender_node_id: defender_node_id,
             attack_info: attack_info,
             is_target_destroyed: is_target_destroyed,
+            is_target_abandoned: is_target_abandoned,
             move_helper: move_helper,
             shell_move: shell_move,
             shell_node_id: shell_node_id,
@@ -382,13 +412,18 @@ impl EventVisualizer for EventAttackUnitVisualizer {
                 }
             }
         }
-        if self.is_target_destroyed {
+        if self.is_target_destroyed || self.is_target_abandoned {
             if self.attached_unit_id.is_some() {
                 scene.node_mut(self.defender_node_id).children.pop().unwrap();
             }
             // delete unit's marker
             scene.node_mut(self.defender_node_id).children.pop().unwrap();
-            if !self.attack_info.leave_wrecks {
+            if self.is_target_abandoned {
+                for child in &mut scene.node_mut(self.defender_node_id).children {
+                    child.color = WRECKS_COLOR;
+                }
+            }
+            if self.is_target_destroyed && !self.attack_info.leave_wrecks {
                 assert_eq!(scene.node(self.defender_node_id).children.len(), 0);
                 scene.remove_node(self.defender_node_id);
             }
@@ -516,6 +551,266 @@ impl EventVisualizer for EventUnloadUnitVisualizer {
     fn end(&mut self, _: &mut Scene, _: &State) {}
 }
 
+#[derive(Clone, Debug)]
+pub struct EventBailOutVisualizer {
+    node_id: NodeId,
+    move_helper: MoveHelper,
+}
+
+impl EventBailOutVisualizer {
+    pub fn new(
+        db: &Db,
+        state: &State,
+        scene: &mut Scene,
+        unit_info: &Unit,
+        mesh_id: MeshId,
+        marker_mesh_id: MeshId,
+        transporter_pos: ExactPos,
+        unit_type_visual_info: &UnitTypeVisualInfo,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        map_text.add_text(unit_info.pos.map_pos, "bailed out");
+        let to = geom::exact_pos_to_world_pos(state, unit_info.pos);
+        let from = geom::exact_pos_to_world_pos(state, transporter_pos);
+        show_unit_at(db, state, scene, unit_info, mesh_id, marker_mesh_id);
+        let node_id = scene.unit_id_to_node_id(unit_info.id);
+        let unit_node = scene.node_mut(node_id);
+        unit_node.pos = from;
+        unit_node.rot = geom::get_rot_angle(from, to);
+        let move_speed = unit_type_visual_info.move_speed;
+        Box::new(EventBailOutVisualizer {
+            node_id: node_id,
+            move_helper: MoveHelper::new(from, to, move_speed),
+        })
+    }
+}
+
+impl EventVisualizer for EventBailOutVisualizer {
+    fn is_finished(&self) -> bool {
+        self.move_helper.is_finished()
+    }
+
+    fn draw(&mut self, scene: &mut Scene, dtime: Time) {
+        let node = scene.node_mut(self.node_id);
+        node.pos = self.move_helper.step(dtime);
+    }
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+#[derive(Clone, Debug)]
+pub struct EventKillPassengerVisualizer;
+
+impl EventKillPassengerVisualizer {
+    pub fn new(
+        state: &State,
+        transporter_id: UnitId,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        if let Some(transporter) = state.unit_opt(transporter_id) {
+            map_text.add_text(transporter.pos.map_pos, "passenger killed");
+        }
+        Box::new(EventKillPassengerVisualizer)
+    }
+}
+
+impl EventVisualizer for EventKillPassengerVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+#[derive(Clone, Debug)]
+pub struct EventSplitVisualizer {
+    node_id: NodeId,
+    move_helper: MoveHelper,
+}
+
+impl EventSplitVisualizer {
+    pub fn new(
+        db: &Db,
+        state: &State,
+        scene: &mut Scene,
+        unit_id: UnitId,
+        unit_info: &Unit,
+        mesh_id: MeshId,
+        marker_mesh_id: MeshId,
+        unit_type_visual_info: &UnitTypeVisualInfo,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        let unit_pos = state.unit(unit_id).pos;
+        map_text.add_text(unit_pos.map_pos, "split");
+        let parent_node_id = scene.unit_id_to_node_id(unit_id);
+        for _ in 0 .. unit_info.count {
+            let _ = scene.node_mut(parent_node_id).children.remove(0);
+        }
+        let from = geom::exact_pos_to_world_pos(state, unit_pos);
+        let to = geom::exact_pos_to_world_pos(state, unit_info.pos);
+        show_unit_at(db, state, scene, unit_info, mesh_id, marker_mesh_id);
+        let node_id = scene.unit_id_to_node_id(unit_info.id);
+        let unit_node = scene.node_mut(node_id);
+        unit_node.pos = from;
+        unit_node.rot = geom::get_rot_angle(from, to);
+        let move_speed = unit_type_visual_info.move_speed;
+        Box::new(EventSplitVisualizer {
+            node_id: node_id,
+            move_helper: MoveHelper::new(from, to, move_speed),
+        })
+    }
+}
+
+impl EventVisualizer for EventSplitVisualizer {
+    fn is_finished(&self) -> bool {
+        self.move_helper.is_finished()
+    }
+
+    fn draw(&mut self, scene: &mut Scene, dtime: Time) {
+        let node = scene.node_mut(self.node_id);
+        node.pos = self.move_helper.step(dtime);
+    }
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+// the detached squad went somewhere into the fog
+#[derive(Clone, Debug)]
+pub struct EventHiddenSplitVisualizer;
+
+impl EventHiddenSplitVisualizer {
+    pub fn new(
+        state: &State,
+        scene: &mut Scene,
+        unit_id: UnitId,
+        count: i32,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        map_text.add_text(state.unit(unit_id).pos.map_pos, "split");
+        let node_id = scene.unit_id_to_node_id(unit_id);
+        for _ in 0 .. count {
+            let _ = scene.node_mut(node_id).children.remove(0);
+        }
+        Box::new(EventHiddenSplitVisualizer)
+    }
+}
+
+impl EventVisualizer for EventHiddenSplitVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+#[derive(Clone, Debug)]
+pub struct EventMergeVisualizer {
+    unit_id: UnitId,
+    move_helper: Option<MoveHelper>,
+}
+
+impl EventMergeVisualizer {
+    pub fn new(
+        db: &Db,
+        state: &State,
+        scene: &mut Scene,
+        unit_id: UnitId,
+        unit_info: &Unit,
+        mesh_id: MeshId,
+        marker_mesh_id: MeshId,
+        unit_type_visual_info: &UnitTypeVisualInfo,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        map_text.add_text(unit_info.pos.map_pos, "merged");
+        let to = geom::exact_pos_to_world_pos(state, unit_info.pos);
+        // merging squad may be hidden in a fogged tile
+        let move_helper = scene.unit_id_to_node_id_opt(unit_id).map(|node_id| {
+            let node = scene.node_mut(node_id);
+            let from = node.pos;
+            node.rot = geom::get_rot_angle(from, to);
+            MoveHelper::new(from, to, unit_type_visual_info.move_speed)
+        });
+        // redraw the receiving squad with its new size
+        scene.remove_unit(unit_info.id);
+        show_unit_at(db, state, scene, unit_info, mesh_id, marker_mesh_id);
+        Box::new(EventMergeVisualizer {
+            unit_id: unit_id,
+            move_helper: move_helper,
+        })
+    }
+}
+
+impl EventVisualizer for EventMergeVisualizer {
+    fn is_finished(&self) -> bool {
+        match self.move_helper {
+            Some(ref move_helper) => move_helper.is_finished(),
+            None => true,
+        }
+    }
+
+    fn draw(&mut self, scene: &mut Scene, dtime: Time) {
+        if let Some(ref mut move_helper) = self.move_helper {
+            let node_id = scene.unit_id_to_node_id(self.unit_id);
+            scene.node_mut(node_id).pos = move_helper.step(dtime);
+        }
+    }
+
+    fn end(&mut self, scene: &mut Scene, _: &State) {
+        if self.move_helper.is_some() {
+            scene.remove_unit(self.unit_id);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EventCaptureVisualizer;
+
+impl EventCaptureVisualizer {
+    pub fn new(
+        db: &Db,
+        state: &State,
+        scene: &mut Scene,
+        unit_id: UnitId,
+        crew_count: i32,
+        unit_info: &Unit,
+        mesh_id: MeshId,
+        marker_mesh_id: MeshId,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        map_text.add_text(unit_info.pos.map_pos, "captured");
+        // capturing squad may be hidden in a fogged tile
+        if let Some(unit) = state.unit_opt(unit_id) {
+            if unit.count <= crew_count {
+                scene.remove_unit(unit_id);
+            } else {
+                let node_id = scene.unit_id_to_node_id(unit_id);
+                for _ in 0 .. crew_count {
+                    let _ = scene.node_mut(node_id).children.remove(0);
+                }
+            }
+        }
+        if scene.unit_id_to_node_id_opt(unit_info.id).is_some() {
+            scene.remove_unit(unit_info.id);
+        }
+        show_unit_at(db, state, scene, unit_info, mesh_id, marker_mesh_id);
+        Box::new(EventCaptureVisualizer)
+    }
+}
+
+impl EventVisualizer for EventCaptureVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
 #[derive(Clone, Debug)]
 pub struct EventLoadUnitVisualizer {
     passenger_id: UnitId,
@@ -686,12 +981,10 @@ impl EventSmokeVisualizer {
     pub fn new(
         scene: &mut Scene,
         pos: MapPos,
-        _: Option<UnitId>, // TODO
         object_id: ObjectId,
         smoke_mesh_id: MeshId,
         map_text: &mut MapTextManager,
     ) -> Box<EventVisualizer> {
-        // TODO: show shell animation
         map_text.add_text(pos, "smoke");
         let z_step = 0.45; // TODO: magic
         let mut node = SceneNode {
@@ -774,6 +1067,224 @@ impl EventVisualizer for EventRemoveSmokeVisualizer {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct EventCreateObjectVisualizer;
+
+impl EventCreateObjectVisualizer {
+    pub fn new(
+        scene: &mut Scene,
+        object_id: ObjectId,
+        object: &Object,
+        node: SceneNode,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        if object.class == ObjectClass::Rubble {
+            map_text.add_text(object.pos.map_pos, "collapsed");
+        } else if object.class == ObjectClass::Bridge {
+            map_text.add_text(object.pos.map_pos, "bridge built");
+        }
+        scene.add_object(object_id, node);
+        Box::new(EventCreateObjectVisualizer)
+    }
+}
+
+impl EventVisualizer for EventCreateObjectVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+#[derive(Clone, Debug)]
+pub struct EventRemoveObjectVisualizer {
+    object_id: ObjectId,
+}
+
+impl EventRemoveObjectVisualizer {
+    pub fn new(
+        state: &State,
+        object_id: ObjectId,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        let object = &state.objects()[&object_id];
+        if object.class == ObjectClass::Bridge {
+            map_text.add_text(object.pos.map_pos, "bridge destroyed");
+        }
+        Box::new(EventRemoveObjectVisualizer {
+            object_id: object_id,
+        })
+    }
+}
+
+impl EventVisualizer for EventRemoveObjectVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, scene: &mut Scene, _: &State) {
+        scene.remove_object(self.object_id);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EventDamageObjectVisualizer;
+
+impl EventDamageObjectVisualizer {
+    pub fn new(
+        state: &State,
+        object_id: ObjectId,
+        damage: i32,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        let object = &state.objects()[&object_id];
+        let name = if object.class == ObjectClass::Bridge {
+            "bridge"
+        } else {
+            "building"
+        };
+        let text = format!("{} damaged: {}", name, damage);
+        map_text.add_text(object.pos.map_pos, &text);
+        Box::new(EventDamageObjectVisualizer)
+    }
+}
+
+impl EventVisualizer for EventDamageObjectVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+// The GUI label is updated by the tactical screen
+#[derive(Clone, Debug)]
+pub struct EventWeatherChangedVisualizer;
+
+impl EventWeatherChangedVisualizer {
+    pub fn new() -> Box<EventVisualizer> {
+        Box::new(EventWeatherChangedVisualizer)
+    }
+}
+
+impl EventVisualizer for EventWeatherChangedVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+#[derive(Clone, Debug)]
+pub struct EventEntrenchVisualizer;
+
+impl EventEntrenchVisualizer {
+    pub fn new(
+        state: &State,
+        unit_id: UnitId,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        let unit_pos = state.unit(unit_id).pos.map_pos;
+        map_text.add_text(unit_pos, "entrenched");
+        Box::new(EventEntrenchVisualizer)
+    }
+}
+
+impl EventVisualizer for EventEntrenchVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+#[derive(Clone, Debug)]
+pub struct EventEngineeringWorkVisualizer;
+
+impl EventEngineeringWorkVisualizer {
+    pub fn new(
+        state: &State,
+        unit_id: UnitId,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        let unit_pos = state.unit(unit_id).pos.map_pos;
+        map_text.add_text(unit_pos, "engineering");
+        Box::new(EventEngineeringWorkVisualizer)
+    }
+}
+
+impl EventVisualizer for EventEngineeringWorkVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+// TODO: show shell animation
+#[derive(Clone, Debug)]
+pub struct EventFireSmokeVisualizer;
+
+impl EventFireSmokeVisualizer {
+    pub fn new(
+        state: &State,
+        unit_id: UnitId,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        let unit_pos = state.unit(unit_id).pos.map_pos;
+        map_text.add_text(unit_pos, "firing smoke");
+        Box::new(EventFireSmokeVisualizer)
+    }
+}
+
+impl EventVisualizer for EventFireSmokeVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
+#[derive(Clone, Debug)]
+pub struct EventMoveOrderVisualizer;
+
+impl EventMoveOrderVisualizer {
+    pub fn new(
+        state: &State,
+        unit_id: UnitId,
+        text: &str,
+        map_text: &mut MapTextManager,
+    ) -> Box<EventVisualizer> {
+        let unit_pos = state.unit(unit_id).pos.map_pos;
+        map_text.add_text(unit_pos, text);
+        Box::new(EventMoveOrderVisualizer)
+    }
+}
+
+impl EventVisualizer for EventMoveOrderVisualizer {
+    fn is_finished(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, _: &mut Scene, _: Time) {}
+
+    fn end(&mut self, _: &mut Scene, _: &State) {}
+}
+
 pub struct EventAttachVisualizer {
     transporter_id: UnitId,
     attached_unit_id: UnitId,
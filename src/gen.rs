@@ -59,6 +59,10 @@ pub fn generate_sector_mesh(context: &mut Context, sector: &Sector, tex: Texture
 pub fn generate_map_mesh(context: &mut Context, state: &State, tex: Texture) -> Mesh {
     let mut normal_positions = Vec::new();
     for tile_pos in state.map().get_iter() {
+        // holes and irregular board edges get no ground
+        if state.map().is_void(tile_pos) {
+            continue;
+        }
         if *state.map().tile(tile_pos) != Terrain::Water {
             normal_positions.push(tile_pos);
         }
@@ -187,6 +191,60 @@ pub fn get_road_mesh(context: &mut Context) -> Mesh {
     Mesh::new(context, &vertices, &indices, texture)
 }
 
+pub fn get_hexside_mesh(context: &mut Context) -> Mesh {
+    let w = geom::HEX_EX_RADIUS / 2.0;
+    let l = geom::HEX_EX_RADIUS * 0.08;
+    let h = geom::MIN_LIFT_HEIGHT * 3.0;
+    let vertices = [
+        Vertex{pos: [-w, -l, h], uv: [0.0, 0.0]},
+        Vertex{pos: [-w, l, h], uv: [0.0, 1.0]},
+        Vertex{pos: [w, l, h], uv: [1.0, 1.0]},
+        Vertex{pos: [w, -l, h], uv: [1.0, 0.0]},
+    ];
+    let indices = [0, 1, 2, 2, 3, 0];
+    let texture_data = fs::load("white.png").into_inner();
+    let texture = load_texture(context, &texture_data);
+    Mesh::new(context, &vertices, &indices, texture)
+}
+
+pub fn get_bridge_mesh(context: &mut Context) -> Mesh {
+    let w = geom::HEX_EX_RADIUS * 0.35;
+    let l = geom::HEX_EX_RADIUS * 0.9;
+    let h = geom::MIN_LIFT_HEIGHT * 2.0;
+    let vertices = [
+        Vertex{pos: [-w, -l, h], uv: [0.0, 0.0]},
+        Vertex{pos: [-w, l, h], uv: [0.0, 1.0]},
+        Vertex{pos: [w, l, h], uv: [1.0, 1.0]},
+        Vertex{pos: [w, -l, h], uv: [1.0, 0.0]},
+    ];
+    let indices = [0, 1, 2, 2, 3, 0];
+    let texture_data = fs::load("white.png").into_inner();
+    let texture = load_texture(context, &texture_data);
+    Mesh::new(context, &vertices, &indices, texture)
+}
+
+pub fn get_rubble_mesh(context: &mut Context, scale: f32) -> Mesh {
+    let h = geom::MIN_LIFT_HEIGHT * 2.0;
+    let mut vertices = Vec::new();
+    for dir in dirs() {
+        let mut vertex = geom::index_to_hex_vertex_s(scale, dir.to_int());
+        vertex.v.z = h;
+        vertices.push(Vertex {
+            pos: vertex.v.into(),
+            uv: [0.5, 0.5],
+        });
+    }
+    let indices = [
+        0, 1, 2,
+        0, 2, 3,
+        0, 3, 5,
+        3, 4, 5,
+    ];
+    let texture_data = fs::load("white.png").into_inner();
+    let texture = load_texture(context, &texture_data);
+    Mesh::new(context, &vertices, &indices, texture)
+}
+
 pub fn get_marker<P: AsRef<Path>>(context: &mut Context, tex_path: P) -> Mesh {
     let n = 0.2;
     let vertices = [
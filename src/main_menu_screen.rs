@@ -1,3 +1,4 @@
+use rand::{thread_rng, Rng};
 use cgmath::{Vector2};
 use glutin::{self, Event, MouseButton, VirtualKeyCode};
 use glutin::ElementState::{Released};
@@ -6,7 +7,7 @@ use tactical_screen::{TacticalScreen};
 use core;
 use context::{Context};
 use gui::{ButtonManager, Button, ButtonId, is_tap};
-use types::{ScreenPos, Time};
+use types::{Size2, ScreenPos, Time};
 
 #[derive(Clone, Debug)]
 pub struct MainMenuScreen {
@@ -14,13 +15,74 @@ pub struct MainMenuScreen {
     button_start_vs_ai_id: ButtonId,
     button_map_id: ButtonId,
     button_manager: ButtonManager,
+    button_zoc_rule_id: ButtonId,
+    button_weather_id: ButtonId,
+    button_time_of_day_id: ButtonId,
+    button_map_size_id: ButtonId,
+    button_map_style_id: ButtonId,
+    button_seed_id: ButtonId,
     map_names: Vec<&'static str>,
     selected_map_index: usize,
+    zoc_rule: core::ZocRule,
+    weather: core::Weather,
+    time_of_day: core::TimeOfDay,
+    map_sizes: Vec<Size2>,
+    selected_map_size_index: usize,
+    map_style: core::MapStyle,
+    seed: u32,
+}
+
+fn zoc_rule_text(rule: core::ZocRule) -> String {
+    let name = match rule {
+        core::ZocRule::Disabled => "disabled",
+        core::ZocRule::Tax => "tax",
+        core::ZocRule::Stop => "stop",
+    };
+    format!("zone of control: {}", name)
+}
+
+fn weather_text(weather: core::Weather) -> String {
+    let name = match weather {
+        core::Weather::Clear => "clear",
+        core::Weather::Rain => "rain",
+        core::Weather::Fog => "fog",
+        core::Weather::Snow => "snow",
+    };
+    format!("weather: {}", name)
+}
+
+fn time_of_day_text(time_of_day: core::TimeOfDay) -> String {
+    let name = match time_of_day {
+        core::TimeOfDay::Day => "day",
+        core::TimeOfDay::Dusk => "dusk",
+        core::TimeOfDay::Night => "night",
+        core::TimeOfDay::Dawn => "dawn",
+    };
+    format!("time of day: {}", name)
+}
+
+fn map_size_text(size: Size2) -> String {
+    format!("generated map size: {}x{}", size.w, size.h)
+}
+
+fn map_style_text(style: core::MapStyle) -> String {
+    let name = match style {
+        core::MapStyle::Mixed => "mixed",
+        core::MapStyle::Forest => "forest",
+        core::MapStyle::Lakes => "lakes",
+        core::MapStyle::Urban => "urban",
+    };
+    format!("generated map style: {}", name)
+}
+
+fn seed_text(seed: u32) -> String {
+    format!("generated map seed: {}", seed)
 }
 
 impl MainMenuScreen {
     pub fn new(context: &mut Context) -> MainMenuScreen {
-        let map_names = vec!["map01", "map02", "map03", "map04", "map05"];
+        let map_names = vec![
+            "map01", "map02", "map03", "map04", "map05", "map06", "generated"];
         let selected_map_index = 0;
         let mut button_manager = ButtonManager::new();
         // TODO: Use relative coords in ScreenPos - x: [0.0, 1.0], y: [0.0, 1.0]
@@ -45,13 +107,74 @@ impl MainMenuScreen {
             &format!("map: {}", map_names[selected_map_index]),
             button_pos,
         ));
+        button_pos.v.y += vstep;
+        let zoc_rule = core::ZocRule::Disabled;
+        let button_zoc_rule_id = button_manager.add_button(Button::new(
+            context,
+            &zoc_rule_text(zoc_rule),
+            button_pos,
+        ));
+        button_pos.v.y += vstep;
+        let weather = core::Weather::Clear;
+        let button_weather_id = button_manager.add_button(Button::new(
+            context,
+            &weather_text(weather),
+            button_pos,
+        ));
+        button_pos.v.y += vstep;
+        let time_of_day = core::TimeOfDay::Day;
+        let button_time_of_day_id = button_manager.add_button(Button::new(
+            context,
+            &time_of_day_text(time_of_day),
+            button_pos,
+        ));
+        button_pos.v.y += vstep * 2;
+        // map height must be even for the generator
+        let map_sizes = vec![
+            Size2{w: 12, h: 14},
+            Size2{w: 16, h: 18},
+            Size2{w: 20, h: 22},
+        ];
+        let selected_map_size_index = 0;
+        let button_map_size_id = button_manager.add_button(Button::new(
+            context,
+            &map_size_text(map_sizes[selected_map_size_index]),
+            button_pos,
+        ));
+        button_pos.v.y += vstep;
+        let map_style = core::MapStyle::Mixed;
+        let button_map_style_id = button_manager.add_button(Button::new(
+            context,
+            &map_style_text(map_style),
+            button_pos,
+        ));
+        button_pos.v.y += vstep;
+        let seed = thread_rng().gen();
+        let button_seed_id = button_manager.add_button(Button::new(
+            context,
+            &seed_text(seed),
+            button_pos,
+        ));
         MainMenuScreen {
             button_manager: button_manager,
             button_start_hotseat_id: button_start_hotseat_id,
             button_start_vs_ai_id: button_start_vs_ai_id,
             button_map_id: button_map_id,
+            button_zoc_rule_id: button_zoc_rule_id,
+            button_weather_id: button_weather_id,
+            button_time_of_day_id: button_time_of_day_id,
+            button_map_size_id: button_map_size_id,
+            button_map_style_id: button_map_style_id,
+            button_seed_id: button_seed_id,
             map_names: map_names,
             selected_map_index: selected_map_index,
+            zoc_rule: zoc_rule,
+            weather: weather,
+            time_of_day: time_of_day,
+            map_sizes: map_sizes,
+            selected_map_size_index: selected_map_size_index,
+            map_style: map_style,
+            seed: seed,
         }
     }
 
@@ -70,10 +193,20 @@ impl MainMenuScreen {
         button_id: ButtonId
     ) {
         let map_name = self.map_names[self.selected_map_index].to_string();
+        let map_gen_params = if map_name == "generated" {
+            let size = self.map_sizes[self.selected_map_size_index];
+            Some(core::MapGenParams::with_style(self.seed, size, self.map_style))
+        } else {
+            None
+        };
         let mut core_options = core::Options {
             game_type: core::GameType::Hotseat,
             map_name: map_name,
             players_count: 2,
+            weather: self.weather,
+            time_of_day: self.time_of_day,
+            zoc_rule: self.zoc_rule,
+            map_gen_params: map_gen_params,
         };
         if button_id == self.button_start_hotseat_id {
             let tactical_screen = Box::new(
@@ -94,6 +227,71 @@ impl MainMenuScreen {
             let button_map = Button::new(context, text, pos);
             self.button_manager.remove_button(self.button_map_id);
             self.button_map_id = self.button_manager.add_button(button_map);
+        } else if button_id == self.button_zoc_rule_id {
+            self.zoc_rule = match self.zoc_rule {
+                core::ZocRule::Disabled => core::ZocRule::Tax,
+                core::ZocRule::Tax => core::ZocRule::Stop,
+                core::ZocRule::Stop => core::ZocRule::Disabled,
+            };
+            let text = &zoc_rule_text(self.zoc_rule);
+            let pos = self.button_manager.buttons()[&self.button_zoc_rule_id].pos();
+            let button_zoc_rule = Button::new(context, text, pos);
+            self.button_manager.remove_button(self.button_zoc_rule_id);
+            self.button_zoc_rule_id = self.button_manager.add_button(button_zoc_rule);
+        } else if button_id == self.button_weather_id {
+            self.weather = match self.weather {
+                core::Weather::Clear => core::Weather::Rain,
+                core::Weather::Rain => core::Weather::Fog,
+                core::Weather::Fog => core::Weather::Snow,
+                core::Weather::Snow => core::Weather::Clear,
+            };
+            let text = &weather_text(self.weather);
+            let pos = self.button_manager.buttons()[&self.button_weather_id].pos();
+            let button_weather = Button::new(context, text, pos);
+            self.button_manager.remove_button(self.button_weather_id);
+            self.button_weather_id = self.button_manager.add_button(button_weather);
+        } else if button_id == self.button_time_of_day_id {
+            self.time_of_day = match self.time_of_day {
+                core::TimeOfDay::Day => core::TimeOfDay::Dusk,
+                core::TimeOfDay::Dusk => core::TimeOfDay::Night,
+                core::TimeOfDay::Night => core::TimeOfDay::Dawn,
+                core::TimeOfDay::Dawn => core::TimeOfDay::Day,
+            };
+            let text = &time_of_day_text(self.time_of_day);
+            let pos = self.button_manager.buttons()[&self.button_time_of_day_id].pos();
+            let button_time_of_day = Button::new(context, text, pos);
+            self.button_manager.remove_button(self.button_time_of_day_id);
+            self.button_time_of_day_id = self.button_manager.add_button(button_time_of_day);
+        } else if button_id == self.button_map_size_id {
+            self.selected_map_size_index += 1;
+            if self.selected_map_size_index == self.map_sizes.len() {
+                self.selected_map_size_index = 0;
+            }
+            let text = &map_size_text(self.map_sizes[self.selected_map_size_index]);
+            let pos = self.button_manager.buttons()[&self.button_map_size_id].pos();
+            let button_map_size = Button::new(context, text, pos);
+            self.button_manager.remove_button(self.button_map_size_id);
+            self.button_map_size_id = self.button_manager.add_button(button_map_size);
+        } else if button_id == self.button_map_style_id {
+            self.map_style = match self.map_style {
+                core::MapStyle::Mixed => core::MapStyle::Forest,
+                core::MapStyle::Forest => core::MapStyle::Lakes,
+                core::MapStyle::Lakes => core::MapStyle::Urban,
+                core::MapStyle::Urban => core::MapStyle::Mixed,
+            };
+            let text = &map_style_text(self.map_style);
+            let pos = self.button_manager.buttons()[&self.button_map_style_id].pos();
+            let button_map_style = Button::new(context, text, pos);
+            self.button_manager.remove_button(self.button_map_style_id);
+            self.button_map_style_id = self.button_manager.add_button(button_map_style);
+        } else if button_id == self.button_seed_id {
+            // the same seed gives the same map, so a map can be replayed
+            self.seed = thread_rng().gen();
+            let text = &seed_text(self.seed);
+            let pos = self.button_manager.buttons()[&self.button_seed_id].pos();
+            let button_seed = Button::new(context, text, pos);
+            self.button_manager.remove_button(self.button_seed_id);
+            self.button_seed_id = self.button_manager.add_button(button_seed);
         } else {
             panic!("Bad button id: {}", button_id.id);
         }
@@ -15,7 +15,11 @@ pub struct MeshIdManager {
     pub building_mesh_id: MeshId,
     pub big_building_mesh_w_id: MeshId,
     pub building_mesh_w_id: MeshId,
+    pub big_rubble_mesh_id: MeshId,
+    pub rubble_mesh_id: MeshId,
     pub road_mesh_id: MeshId,
+    pub hexside_mesh_id: MeshId,
+    pub bridge_mesh_id: MeshId,
     pub trees_mesh_id: MeshId,
     pub shell_mesh_id: MeshId,
     pub marker_mesh_id: MeshId,
@@ -64,9 +68,13 @@ impl MeshIdManager {
             load_object_mesh(context, "big_building_wire"));
         let building_mesh_w_id = meshes.add(
             load_object_mesh(context, "building_wire"));
+        let big_rubble_mesh_id = meshes.add(gen::get_rubble_mesh(context, 0.8));
+        let rubble_mesh_id = meshes.add(gen::get_rubble_mesh(context, 0.3));
         let trees_mesh_id = meshes.add(load_object_mesh(context, "trees"));
         let shell_mesh_id = meshes.add(gen::get_shell_mesh(context));
         let road_mesh_id = meshes.add(gen::get_road_mesh(context));
+        let hexside_mesh_id = meshes.add(gen::get_hexside_mesh(context));
+        let bridge_mesh_id = meshes.add(gen::get_bridge_mesh(context));
         let marker_mesh_id = meshes.add(gen::get_marker(context, "white.png"));
         let walkable_mesh_id = meshes.add(gen::empty_mesh(context));
         let targets_mesh_id = meshes.add(gen::empty_mesh(context));
@@ -75,8 +83,12 @@ impl MeshIdManager {
             building_mesh_id: building_mesh_id,
             big_building_mesh_w_id: big_building_mesh_w_id,
             building_mesh_w_id: building_mesh_w_id,
+            big_rubble_mesh_id: big_rubble_mesh_id,
+            rubble_mesh_id: rubble_mesh_id,
             trees_mesh_id: trees_mesh_id,
             road_mesh_id: road_mesh_id,
+            hexside_mesh_id: hexside_mesh_id,
+            bridge_mesh_id: bridge_mesh_id,
             shell_mesh_id: shell_mesh_id,
             marker_mesh_id: marker_mesh_id,
             walkable_mesh_id: walkable_mesh_id,
@@ -3,7 +3,7 @@ use std::rc::{Rc};
 use cgmath::{Vector2, Vector3};
 use core::game_state::{State};
 use core::pathfinder::{Pathfinder};
-use core::map::{Map};
+use core::map::{Map, Terrain};
 use core::db::{Db};
 use core::{self, PlayerId, MapPos};
 use context::{Context};
@@ -31,9 +31,9 @@ pub struct FowInfo {
 }
 
 impl FowInfo {
-    pub fn new(map_size: Size2) -> FowInfo {
+    pub fn new(map: &Map<Terrain>) -> FowInfo {
         FowInfo {
-            map: Map::new(map_size),
+            map: Map::new_like(map),
             vanishing_node_ids: HashMap::new(),
             forthcoming_node_ids: HashMap::new(),
         }
@@ -63,20 +63,20 @@ impl PlayerInfoManager {
         camera.set_max_pos(get_max_camera_pos(map_size));
         camera.set_pos(get_initial_camera_pos(map_size));
         m.insert(PlayerId{id: 0}, PlayerInfo {
+            pathfinder: Pathfinder::new(db.clone(), state.map()),
+            fow_info: FowInfo::new(state.map()),
             game_state: state,
-            pathfinder: Pathfinder::new(db.clone(), map_size),
             scene: Scene::new(),
             camera: camera.clone(),
-            fow_info: FowInfo::new(map_size),
         });
         if options.game_type == core::GameType::Hotseat {
             let state2 = State::new_partial(db.clone(), options, PlayerId{id: 1});
             m.insert(PlayerId{id: 1}, PlayerInfo {
+                pathfinder: Pathfinder::new(db, state2.map()),
+                fow_info: FowInfo::new(state2.map()),
                 game_state: state2,
-                pathfinder: Pathfinder::new(db, map_size),
                 scene: Scene::new(),
                 camera: camera,
-                fow_info: FowInfo::new(map_size),
             });
         }
         PlayerInfoManager{info: m}
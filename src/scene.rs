@@ -32,6 +32,7 @@ impl Ord for Z {
 #[derive(Clone, Debug)]
 pub struct Scene {
     unit_id_to_node_id_map: HashMap<UnitId, NodeId>,
+    ghost_id_to_node_id_map: HashMap<UnitId, NodeId>,
     sector_id_to_node_id_map: HashMap<SectorId, NodeId>,
     object_id_to_node_id_map: HashMap<ObjectId, HashSet<NodeId>>,
     nodes: HashMap<NodeId, SceneNode>,
@@ -43,6 +44,7 @@ impl Scene {
     pub fn new() -> Scene {
         Scene {
             unit_id_to_node_id_map: HashMap::new(),
+            ghost_id_to_node_id_map: HashMap::new(),
             sector_id_to_node_id_map: HashMap::new(),
             object_id_to_node_id_map: HashMap::new(),
             nodes: HashMap::new(),
@@ -111,6 +113,23 @@ impl Scene {
         node_id
     }
 
+    pub fn ghost_ids(&self) -> Vec<UnitId> {
+        self.ghost_id_to_node_id_map.keys().cloned().collect()
+    }
+
+    pub fn add_ghost(&mut self, unit_id: UnitId, node: SceneNode) -> NodeId {
+        let node_id = self.add_node(node);
+        assert!(!self.ghost_id_to_node_id_map.contains_key(&unit_id));
+        self.ghost_id_to_node_id_map.insert(unit_id, node_id);
+        node_id
+    }
+
+    pub fn remove_ghost(&mut self, unit_id: UnitId) {
+        let node_id = self.ghost_id_to_node_id_map.remove(&unit_id)
+            .expect("Bad ghost id");
+        self.remove_node(node_id);
+    }
+
     pub fn add_sector(&mut self, sector_id: SectorId, node: SceneNode) -> NodeId {
         let node_id = self.add_node(node);
         assert!(!self.sector_id_to_node_id_map.contains_key(&sector_id));
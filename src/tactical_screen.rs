@@ -10,6 +10,7 @@ use core::map::{Terrain};
 use core::game_state::{State};
 use core::{self, CoreEvent, Command, UnitId, PlayerId, MapPos, ExactPos, SlotId, Object};
 use core::unit::{UnitTypeId};
+use core::pathfinder::{PathMode};
 use core::misc::{opt_rx_collect};
 use gui::{ButtonManager, Button, ButtonId, is_tap};
 use scene::{Scene, NodeId, SceneNode};
@@ -36,6 +37,12 @@ use mesh_manager::{MeshIdManager, MeshManager};
 
 const FOW_FADING_TIME: f32 = 0.6;
 
+const RUBBLE_COLOR: [f32; 4] = [0.35, 0.3, 0.25, 1.0];
+
+const BRIDGE_COLOR: [f32; 4] = [0.45, 0.3, 0.15, 1.0];
+
+const GHOST_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.4];
+
 // TODO: get from Core
 fn target_score() -> core::Score {
     core::Score{n: 5}
@@ -58,6 +65,38 @@ fn reinforcement_points_text(state: &State, player_id: PlayerId) -> String {
     format!("reinforcements: {} (+{})", rp, rp_per_turn)
 }
 
+fn hexside_color(class: core::ObjectClass) -> [f32; 4] {
+    match class {
+        core::ObjectClass::River => [0.2, 0.4, 0.9, 1.0],
+        core::ObjectClass::Wall => [0.5, 0.5, 0.5, 1.0],
+        core::ObjectClass::Hedge => [0.1, 0.4, 0.1, 1.0],
+        _ => unimplemented!(),
+    }
+}
+
+fn weather_text(state: &State) -> String {
+    let weather = match state.weather() {
+        core::Weather::Clear => "clear",
+        core::Weather::Rain => "rain",
+        core::Weather::Fog => "fog",
+        core::Weather::Snow => "snow",
+    };
+    let time_of_day = match state.time_of_day() {
+        core::TimeOfDay::Day => "day",
+        core::TimeOfDay::Dusk => "dusk",
+        core::TimeOfDay::Night => "night",
+        core::TimeOfDay::Dawn => "dawn",
+    };
+    format!("weather: {}, {}", weather, time_of_day)
+}
+
+fn path_mode_text(mode: PathMode) -> &'static str {
+    match mode {
+        PathMode::Fastest => "[fastest route]",
+        PathMode::Safe => "[safe route]",
+    }
+}
+
 fn building_mesh_id(mesh_ids: &MeshIdManager, object: &Object) -> MeshId {
     let slot_id = object.pos.slot_id;
     match slot_id {
@@ -76,6 +115,67 @@ fn wireframe_building_mesh_id(mesh_ids: &MeshIdManager, object: &Object) -> Mesh
     }
 }
 
+fn rubble_mesh_id(mesh_ids: &MeshIdManager, object: &Object) -> MeshId {
+    let slot_id = object.pos.slot_id;
+    match slot_id {
+        SlotId::Id(_) => mesh_ids.rubble_mesh_id,
+        SlotId::WholeTile => mesh_ids.big_rubble_mesh_id,
+        _ => unimplemented!(),
+    }
+}
+
+// Bridges are aligned with the road that crosses them
+fn bridge_rot(state: &State, pos: MapPos) -> Rad<f32> {
+    for object in state.objects().values() {
+        if object.class != core::ObjectClass::Road {
+            continue;
+        }
+        if !object.pos.map_pos_iter().any(|road_pos| road_pos == pos) {
+            continue;
+        }
+        if let SlotId::TwoTiles(dir) = object.pos.slot_id {
+            return Rad(dir.to_int() as f32 * PI / 3.0 + PI / 6.0);
+        }
+    }
+    Rad(0.0)
+}
+
+fn object_scene_node(
+    state: &State,
+    mesh_ids: &MeshIdManager,
+    object: &Object,
+) -> SceneNode {
+    let (mesh_id, color) = match object.class {
+        core::ObjectClass::Building => {
+            (building_mesh_id(mesh_ids, object), [1.0, 1.0, 1.0, 1.0])
+        },
+        core::ObjectClass::Rubble => {
+            (rubble_mesh_id(mesh_ids, object), RUBBLE_COLOR)
+        },
+        core::ObjectClass::Bridge => {
+            (mesh_ids.bridge_mesh_id, BRIDGE_COLOR)
+        },
+        core::ObjectClass::Road |
+        core::ObjectClass::Smoke |
+        core::ObjectClass::ReinforcementSector |
+        core::ObjectClass::River |
+        core::ObjectClass::Wall |
+        core::ObjectClass::Hedge => unimplemented!(),
+    };
+    let rot = if object.class == core::ObjectClass::Bridge {
+        bridge_rot(state, object.pos.map_pos)
+    } else {
+        Rad(thread_rng().gen_range(0.0, PI * 2.0))
+    };
+    SceneNode {
+        pos: geom::exact_pos_to_world_pos(state, object.pos),
+        rot: rot,
+        mesh_id: Some(mesh_id),
+        color: color,
+        children: Vec::new(),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Gui {
     button_manager: ButtonManager,
@@ -85,9 +185,11 @@ pub struct Gui {
     button_prev_unit_id: ButtonId,
     button_zoom_in_id: ButtonId,
     button_zoom_out_id: ButtonId,
+    button_path_mode_id: ButtonId,
     label_unit_info_id: Option<ButtonId>,
     label_score_id: ButtonId,
     label_reinforcement_points_id: ButtonId,
+    label_weather_id: ButtonId,
 }
 
 impl Gui {
@@ -113,6 +215,10 @@ impl Gui {
         pos.v.x += button_manager.buttons()[&button_prev_unit_id].size().w;
         let button_zoom_out_id = button_manager.add_button(
             Button::new(context, "[-]", pos));
+        pos.v.y += ystep;
+        pos.v.x = 10;
+        let button_path_mode_id = button_manager.add_button(
+            Button::new(context, path_mode_text(PathMode::Fastest), pos));
         let label_score_id = {
             let vp_pos = ScreenPos{v: Vector2 {
                 x: context.win_size().w - 10,
@@ -138,6 +244,20 @@ impl Gui {
             button.set_pos(pos);
             button_manager.add_button(button)
         };
+        let label_weather_id = {
+            let rp_label_h = button_manager.buttons()[&label_reinforcement_points_id]
+                .size().h;
+            let vp_pos = ScreenPos{v: Vector2 {
+                x: context.win_size().w - 10,
+                y: 10 + rp_label_h,
+            }};
+            let text = weather_text(state);
+            let mut button = Button::new_small(context, &text, vp_pos);
+            let mut pos = button.pos();
+            pos.v.x -= button.size().w;
+            button.set_pos(pos);
+            button_manager.add_button(button)
+        };
         Gui {
             button_manager: button_manager,
             button_end_turn_id: button_end_turn_id,
@@ -146,9 +266,11 @@ impl Gui {
             button_next_unit_id: button_next_unit_id,
             button_zoom_in_id: button_zoom_in_id,
             button_zoom_out_id: button_zoom_out_id,
+            button_path_mode_id: button_path_mode_id,
             label_unit_info_id: None,
             label_score_id: label_score_id,
             label_reinforcement_points_id: label_reinforcement_points_id,
+            label_weather_id: label_weather_id,
         }
     }
 }
@@ -226,19 +348,33 @@ fn make_scene(state: &State, mesh_ids: &MeshIdManager) -> Scene {
                     children: Vec::new(),
                 });
             },
-            core::ObjectClass::Building => {
+            core::ObjectClass::Building |
+            core::ObjectClass::Rubble |
+            core::ObjectClass::Bridge => {
+                let node = object_scene_node(state, mesh_ids, object);
+                scene.add_object(object_id, node);
+            }
+            core::ObjectClass::Road => {
                 let pos = geom::exact_pos_to_world_pos(state, object.pos);
-                let rot = Rad(thread_rng().gen_range(0.0, PI * 2.0));
-                scene.add_object(object_id, SceneNode {
+                let rot = match object.pos.slot_id {
+                    SlotId::TwoTiles(dir) => {
+                        Rad(dir.to_int() as f32 * PI / 3.0 + PI / 6.0)
+                    },
+                    _ => panic!(),
+                };
+                scene.add_node(SceneNode {
                     pos: pos,
                     rot: rot,
-                    mesh_id: Some(building_mesh_id(mesh_ids, object)),
+                    mesh_id: Some(mesh_ids.road_mesh_id),
                     color: [1.0, 1.0, 1.0, 1.0],
                     children: Vec::new(),
                 });
             }
-            core::ObjectClass::Road => {
+            core::ObjectClass::River |
+            core::ObjectClass::Wall |
+            core::ObjectClass::Hedge => {
                 let pos = geom::exact_pos_to_world_pos(state, object.pos);
+                // the mesh lies along the edge, across the line between tiles
                 let rot = match object.pos.slot_id {
                     SlotId::TwoTiles(dir) => {
                         Rad(dir.to_int() as f32 * PI / 3.0 + PI / 6.0)
@@ -248,8 +384,8 @@ fn make_scene(state: &State, mesh_ids: &MeshIdManager) -> Scene {
                 scene.add_node(SceneNode {
                     pos: pos,
                     rot: rot,
-                    mesh_id: Some(mesh_ids.road_mesh_id),
-                    color: [1.0, 1.0, 1.0, 1.0],
+                    mesh_id: Some(mesh_ids.hexside_mesh_id),
+                    color: hexside_color(object.class),
                     children: Vec::new(),
                 });
             }
@@ -270,6 +406,8 @@ pub struct TacticalScreen {
     meshes: MeshManager,
     unit_type_visual_info: UnitTypeVisualInfoManager,
     selected_unit_id: Option<UnitId>,
+    /// Units that move together with the selected one
+    group_unit_ids: Vec<UnitId>,
     selection_manager: SelectionManager,
     context_menu_popup_rx: Option<Receiver<context_menu_popup::Command>>,
     reinforcements_popup_rx: Option<Receiver<(UnitTypeId, ExactPos)>>,
@@ -304,6 +442,7 @@ impl TacticalScreen {
             meshes: meshes,
             unit_type_visual_info: unit_type_visual_info,
             selected_unit_id: None,
+            group_unit_ids: Vec::new(),
             selection_manager: selection_manager,
             map_text_manager: map_text_manager,
             context_menu_popup_rx: None,
@@ -432,6 +571,7 @@ impl TacticalScreen {
             self.gui.button_manager.remove_button(label_id);
         }
         self.selected_unit_id = None;
+        self.group_unit_ids.clear();
         self.hide_selected_unit_meshes(context);
     }
 
@@ -457,6 +597,7 @@ impl TacticalScreen {
             &self.core,
             self.current_player_info(),
             self.selected_unit_id,
+            &self.group_unit_ids,
             pos,
         );
         if options == context_menu_popup::Options::new() {
@@ -480,9 +621,20 @@ impl TacticalScreen {
     // TODO: add ability to select enemy units
     fn select_unit(&mut self, context: &mut Context, unit_id: UnitId) {
         if self.selected_unit_id.is_some() {
-            self.deselect_unit(context);
+            self.hide_selected_unit_meshes(context);
         }
         self.selected_unit_id = Some(unit_id);
+        // group members may have been killed or loaded
+        self.group_unit_ids = {
+            let state = self.current_state();
+            self.group_unit_ids.iter().cloned()
+                .filter(|&id| id != unit_id)
+                .filter(|&id| state.unit_opt(id).map_or(false, |unit| {
+                    unit.is_alive && !core::is_loaded_or_attached(unit)
+                }))
+                .collect()
+        };
+        let group_size = self.group_unit_ids.len();
         let mut player_info = self.player_info.get_mut(self.core.player_id());
         let state = &player_info.game_state;
         let pf = &mut player_info.pathfinder;
@@ -502,7 +654,7 @@ impl TacticalScreen {
                 let unit = state.unit(unit_id);
                 let unit_type = self.core.db().unit_type(unit.type_id);
                 // TODO: core.rs: print_unit_info
-                format!("MP={}/{}, AP={}/{}, RAP={}/{}, C={}, M={}",
+                format!("MP={}/{}, AP={}/{}, RAP={}/{}, C={}, M={}{}{}{}{}{}",
                     if let Some(mp) = unit.move_points { mp.n } else { 0 },
                     unit_type.move_points.n,
                     if let Some(ap) = unit.attack_points { ap.n } else { 0 },
@@ -511,6 +663,15 @@ impl TacticalScreen {
                     unit_type.reactive_attack_points.n,
                     unit.count,
                     unit.morale,
+                    if unit.is_entrenched { ", entrenched" } else { "" },
+                    if unit.is_immobilized { ", immobilized" } else { "" },
+                    if unit.is_weapon_disabled { ", weapon disabled" } else { "" },
+                    if unit.is_crew_shaken { ", crew shaken" } else { "" },
+                    if group_size != 0 {
+                        format!(", group of {}", group_size + 1)
+                    } else {
+                        "".to_owned()
+                    },
                 )
                 // TODO: print info about unit type and weapon
             };
@@ -662,18 +823,41 @@ impl TacticalScreen {
             if let Some(id) = self.selected_unit_id {
                 let prev_id = core::find_prev_player_unit_id(
                     self.current_state(), self.core.player_id(), id);
+                self.group_unit_ids.clear();
                 self.select_unit(context, prev_id);
             }
         } else if button_id == self.gui.button_next_unit_id {
             if let Some(id) = self.selected_unit_id {
                 let next_id = core::find_next_player_unit_id(
                     self.current_state(), self.core.player_id(), id);
+                self.group_unit_ids.clear();
                 self.select_unit(context, next_id);
             }
         } else if button_id == self.gui.button_zoom_in_id {
             self.current_player_info_mut().camera.change_zoom(0.7);
         } else if button_id == self.gui.button_zoom_out_id {
             self.current_player_info_mut().camera.change_zoom(1.3);
+        } else if button_id == self.gui.button_path_mode_id {
+            self.switch_path_mode(context);
+        }
+    }
+
+    /// Switches all players between the fastest and the safe routes
+    fn switch_path_mode(&mut self, context: &mut Context) {
+        let mode = match self.current_player_info().pathfinder.mode() {
+            PathMode::Fastest => PathMode::Safe,
+            PathMode::Safe => PathMode::Fastest,
+        };
+        for player_info in self.player_info.info.values_mut() {
+            player_info.pathfinder.set_mode(mode);
+        }
+        let pos = self.gui.button_manager.buttons()[&self.gui.button_path_mode_id].pos();
+        let button = Button::new(context, path_mode_text(mode), pos);
+        self.gui.button_manager.remove_button(self.gui.button_path_mode_id);
+        self.gui.button_path_mode_id = self.gui.button_manager.add_button(button);
+        // the walkable area depends on the route mode
+        if let Some(unit_id) = self.selected_unit_id {
+            self.select_unit(context, unit_id);
         }
     }
 
@@ -877,6 +1061,9 @@ impl TacticalScreen {
                     &mut self.map_text_manager,
                 )
             }
+            CoreEvent::WeatherChanged{..} => {
+                event_visualizer::EventWeatherChangedVisualizer::new()
+            }
             CoreEvent::VictoryPoint{pos, count, ..} => {
                 event_visualizer::EventVictoryPointVisualizer::new(
                     pos,
@@ -884,23 +1071,167 @@ impl TacticalScreen {
                     &mut self.map_text_manager,
                 )
             }
-            CoreEvent::Smoke{pos, unit_id, id} => {
-                event_visualizer::EventSmokeVisualizer::new(
-                    scene,
-                    pos,
-                    unit_id,
+            CoreEvent::CreateObject{id, ref object} => {
+                if object.class == core::ObjectClass::Smoke {
+                    event_visualizer::EventSmokeVisualizer::new(
+                        scene,
+                        object.pos.map_pos,
+                        id,
+                        self.mesh_ids.smoke_mesh_id,
+                        &mut self.map_text_manager,
+                    )
+                } else {
+                    let node = object_scene_node(state, &self.mesh_ids, object);
+                    event_visualizer::EventCreateObjectVisualizer::new(
+                        scene,
+                        id,
+                        object,
+                        node,
+                        &mut self.map_text_manager,
+                    )
+                }
+            }
+            CoreEvent::RemoveObject{id} => {
+                if state.objects()[&id].class == core::ObjectClass::Smoke {
+                    event_visualizer::EventRemoveSmokeVisualizer::new(
+                        state,
+                        id,
+                        &mut self.map_text_manager,
+                    )
+                } else {
+                    event_visualizer::EventRemoveObjectVisualizer::new(
+                        state,
+                        id,
+                        &mut self.map_text_manager,
+                    )
+                }
+            }
+            CoreEvent::DamageObject{id, damage, ..} => {
+                event_visualizer::EventDamageObjectVisualizer::new(
+                    state,
                     id,
-                    self.mesh_ids.smoke_mesh_id,
+                    damage,
                     &mut self.map_text_manager,
                 )
             }
-            CoreEvent::RemoveSmoke{id} => {
-                event_visualizer::EventRemoveSmokeVisualizer::new(
+            CoreEvent::Entrench{unit_id} => {
+                event_visualizer::EventEntrenchVisualizer::new(
                     state,
-                    id,
+                    unit_id,
+                    &mut self.map_text_manager,
+                )
+            }
+            CoreEvent::EngineeringWork{unit_id} => {
+                event_visualizer::EventEngineeringWorkVisualizer::new(
+                    state,
+                    unit_id,
+                    &mut self.map_text_manager,
+                )
+            }
+            CoreEvent::FireSmoke{unit_id} => {
+                event_visualizer::EventFireSmokeVisualizer::new(
+                    state,
+                    unit_id,
+                    &mut self.map_text_manager,
+                )
+            }
+            CoreEvent::SetMoveOrder{unit_id, ref order} => {
+                let text = format!("waypoints: {}", order.waypoints.len());
+                event_visualizer::EventMoveOrderVisualizer::new(
+                    state,
+                    unit_id,
+                    &text,
+                    &mut self.map_text_manager,
+                )
+            }
+            CoreEvent::CancelMoveOrder{unit_id} => {
+                event_visualizer::EventMoveOrderVisualizer::new(
+                    state,
+                    unit_id,
+                    "move order ended",
                     &mut self.map_text_manager,
                 )
             }
+            CoreEvent::BailOut{ref unit_info, from, ..} => {
+                let unit_type_visual_info
+                    = self.unit_type_visual_info.get(unit_info.type_id);
+                let mesh_id = self.unit_type_visual_info
+                    .get(unit_info.type_id).mesh_id;
+                event_visualizer::EventBailOutVisualizer::new(
+                    self.core.db(),
+                    state,
+                    scene,
+                    unit_info,
+                    mesh_id,
+                    self.mesh_ids.marker_mesh_id,
+                    from,
+                    unit_type_visual_info,
+                    &mut self.map_text_manager,
+                )
+            },
+            CoreEvent::KillPassenger{transporter_id, ..} => {
+                event_visualizer::EventKillPassengerVisualizer::new(
+                    state,
+                    transporter_id,
+                    &mut self.map_text_manager,
+                )
+            },
+            CoreEvent::Split{unit_id, count, unit_info: None} => {
+                event_visualizer::EventHiddenSplitVisualizer::new(
+                    state,
+                    scene,
+                    unit_id,
+                    count,
+                    &mut self.map_text_manager,
+                )
+            },
+            CoreEvent::Split{unit_id, unit_info: Some(ref unit_info), ..} => {
+                let unit_type_visual_info
+                    = self.unit_type_visual_info.get(unit_info.type_id);
+                let mesh_id = unit_type_visual_info.mesh_id;
+                event_visualizer::EventSplitVisualizer::new(
+                    self.core.db(),
+                    state,
+                    scene,
+                    unit_id,
+                    unit_info,
+                    mesh_id,
+                    self.mesh_ids.marker_mesh_id,
+                    unit_type_visual_info,
+                    &mut self.map_text_manager,
+                )
+            },
+            CoreEvent::Merge{unit_id, ref unit_info, ..} => {
+                let unit_type_visual_info
+                    = self.unit_type_visual_info.get(unit_info.type_id);
+                let mesh_id = unit_type_visual_info.mesh_id;
+                event_visualizer::EventMergeVisualizer::new(
+                    self.core.db(),
+                    state,
+                    scene,
+                    unit_id,
+                    unit_info,
+                    mesh_id,
+                    self.mesh_ids.marker_mesh_id,
+                    unit_type_visual_info,
+                    &mut self.map_text_manager,
+                )
+            },
+            CoreEvent::Capture{unit_id, crew_count, ref unit_info, ..} => {
+                let mesh_id = self.unit_type_visual_info
+                    .get(unit_info.type_id).mesh_id;
+                event_visualizer::EventCaptureVisualizer::new(
+                    self.core.db(),
+                    state,
+                    scene,
+                    unit_id,
+                    crew_count,
+                    unit_info,
+                    mesh_id,
+                    self.mesh_ids.marker_mesh_id,
+                    &mut self.map_text_manager,
+                )
+            },
             CoreEvent::Reveal{..} => unreachable!(),
         }
     }
@@ -959,6 +1290,19 @@ impl TacticalScreen {
         self.gui.label_score_id = self.gui.button_manager.add_button(label_score);
     }
 
+    fn update_weather_label(&mut self, context: &mut Context) {
+        let id = self.gui.label_weather_id;
+        let pos = self.gui.button_manager.buttons()[&id].pos();
+        let text = weather_text(self.current_state());
+        let mut label = Button::new_small(context, &text, pos);
+        // keep the label aligned to the right edge of the screen
+        let mut pos = label.pos();
+        pos.v.x = context.win_size().w - 10 - label.size().w;
+        label.set_pos(pos);
+        self.gui.button_manager.remove_button(id);
+        self.gui.label_weather_id = self.gui.button_manager.add_button(label);
+    }
+
     fn update_reinforcement_points_label(&mut self, context: &mut Context) {
         let id = self.gui.label_reinforcement_points_id;
         let pos = self.gui.button_manager.buttons()[&id].pos();
@@ -998,6 +1342,44 @@ impl TacticalScreen {
         }
     }
 
+    /// Last known positions of lost enemies are drawn semi-transparent
+    fn update_ghosts(&mut self) {
+        let player_info = self.player_info.get_mut(self.core.player_id());
+        let scene = &mut player_info.scene;
+        let state = &player_info.game_state;
+        for unit_id in scene.ghost_ids() {
+            if state.ghosts().get(&unit_id).is_none() {
+                scene.remove_ghost(unit_id);
+            }
+        }
+        let ghost_ids = scene.ghost_ids();
+        for (&unit_id, ghost) in state.ghosts() {
+            if ghost_ids.contains(&unit_id) {
+                continue;
+            }
+            let mut marker_color = gen::get_player_color(ghost.player_id);
+            marker_color[3] = GHOST_COLOR[3];
+            let mesh_id = self.unit_type_visual_info.get(ghost.type_id).mesh_id;
+            scene.add_ghost(unit_id, SceneNode {
+                pos: geom::exact_pos_to_world_pos(state, ghost.pos),
+                rot: Rad(0.0),
+                mesh_id: Some(mesh_id),
+                color: GHOST_COLOR,
+                children: vec![SceneNode {
+                    pos: WorldPos{v: Vector3 {
+                        x: 0.0,
+                        y: 0.0,
+                        z: geom::HEX_EX_RADIUS / 2.0,
+                    }},
+                    rot: Rad(0.0),
+                    mesh_id: Some(self.mesh_ids.marker_mesh_id),
+                    color: marker_color,
+                    children: Vec::new(),
+                }],
+            });
+        }
+    }
+
     fn end_event_visualization(&mut self, context: &mut Context) {
         self.attacker_died_from_reaction_fire();
         {
@@ -1008,6 +1390,7 @@ impl TacticalScreen {
             state.apply_event(self.event.as_ref().unwrap());
         }
         self.switch_wireframe();
+        self.update_ghosts();
         if let Some(label_id) = self.gui.label_unit_info_id.take() {
             self.gui.button_manager.remove_button(label_id);
         }
@@ -1016,6 +1399,9 @@ impl TacticalScreen {
             self.update_score_labels(context);
             self.check_game_end(context);
         }
+        if let Some(CoreEvent::WeatherChanged{..}) = self.event {
+            self.update_weather_label(context);
+        }
         self.regenerate_fow();
         self.event_visualizer = None;
         self.event = None;
@@ -1042,6 +1428,7 @@ impl TacticalScreen {
         command: context_menu_popup::Command,
     ) {
         if let context_menu_popup::Command::Select{id} = command {
+            self.group_unit_ids.clear();
             self.select_unit(context, id);
             return;
         }
@@ -1049,12 +1436,35 @@ impl TacticalScreen {
             context_menu_popup::Command::Select{id} => {
                 self.select_unit(context, id);
             },
+            context_menu_popup::Command::AddToGroup{id} => {
+                self.group_unit_ids.push(id);
+                // update the unit info label
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                self.select_unit(context, selected_unit_id);
+            },
             context_menu_popup::Command::Move{pos} => {
                 self.move_unit(pos, core::MoveMode::Fast);
             },
             context_menu_popup::Command::Hunt{pos} => {
                 self.move_unit(pos, core::MoveMode::Hunt);
             },
+            context_menu_popup::Command::SafeMove{path} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                self.core.do_command(Command::Move {
+                    unit_id: selected_unit_id,
+                    path: path,
+                    mode: core::MoveMode::Fast,
+                });
+            },
+            context_menu_popup::Command::GroupMove{pos} => {
+                let mut unit_ids = vec![self.selected_unit_id.unwrap()];
+                unit_ids.extend_from_slice(&self.group_unit_ids);
+                self.core.do_command(Command::GroupMove {
+                    unit_ids: unit_ids,
+                    destination: pos,
+                    mode: core::MoveMode::Fast,
+                });
+            },
             context_menu_popup::Command::Attack{id} => {
                 let selected_unit_id = self.selected_unit_id.unwrap();
                 self.core.do_command(Command::AttackUnit {
@@ -1062,6 +1472,13 @@ impl TacticalScreen {
                     defender_id: id,
                 });
             },
+            context_menu_popup::Command::CloseAssault{id} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                self.core.do_command(Command::CloseAssault {
+                    attacker_id: selected_unit_id,
+                    defender_id: id,
+                });
+            },
             context_menu_popup::Command::LoadUnit{passenger_id} => {
                 let selected_unit_id = self.selected_unit_id.unwrap();
                 self.core.do_command(Command::LoadUnit {
@@ -1114,6 +1531,87 @@ impl TacticalScreen {
                     pos: pos,
                 });
             },
+            context_menu_popup::Command::Entrench{id} => {
+                self.core.do_command(Command::Entrench {
+                    unit_id: id,
+                });
+            },
+            context_menu_popup::Command::Split{pos} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                let count = self.current_state().unit(selected_unit_id).count / 2;
+                self.core.do_command(Command::Split {
+                    unit_id: selected_unit_id,
+                    count: count,
+                    pos: pos,
+                });
+            },
+            context_menu_popup::Command::Merge{into_id} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                self.core.do_command(Command::Merge {
+                    unit_id: selected_unit_id,
+                    into_id: into_id,
+                });
+                // the selected squad is going to disappear
+                self.selected_unit_id = Some(into_id);
+            },
+            context_menu_popup::Command::Capture{target_id} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                let count = self.current_state().unit(selected_unit_id).count;
+                self.core.do_command(Command::Capture {
+                    unit_id: selected_unit_id,
+                    target_id: target_id,
+                });
+                // the whole squad is going to become the crew
+                if count <= core::CREW_COUNT {
+                    self.selected_unit_id = Some(target_id);
+                }
+            },
+            context_menu_popup::Command::Demolish{object_id} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                self.core.do_command(Command::Demolish {
+                    unit_id: selected_unit_id,
+                    object_id: object_id,
+                });
+            },
+            context_menu_popup::Command::Shell{object_id} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                self.core.do_command(Command::AttackObject {
+                    attacker_id: selected_unit_id,
+                    object_id: object_id,
+                });
+            },
+            context_menu_popup::Command::BuildBridge{pos} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                self.core.do_command(Command::BuildBridge {
+                    unit_id: selected_unit_id,
+                    pos: pos,
+                });
+            },
+            context_menu_popup::Command::SetMoveOrder{pos} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                self.core.do_command(Command::SetMoveOrder {
+                    unit_id: selected_unit_id,
+                    order: core::MoveOrder {
+                        waypoints: vec![pos],
+                        mode: core::MoveMode::Fast,
+                    },
+                });
+            },
+            context_menu_popup::Command::AddWaypoint{pos} => {
+                let selected_unit_id = self.selected_unit_id.unwrap();
+                let mut order = self.current_state().move_orders()
+                    [&selected_unit_id].clone();
+                order.waypoints.push(pos);
+                self.core.do_command(Command::SetMoveOrder {
+                    unit_id: selected_unit_id,
+                    order: order,
+                });
+            },
+            context_menu_popup::Command::CancelMoveOrder{id} => {
+                self.core.do_command(Command::CancelMoveOrder {
+                    unit_id: id,
+                });
+            },
             context_menu_popup::Command::CallReiforcements{pos} => {
                 self.show_reinforcements_menu(context, pos);
             },
@@ -44,6 +44,7 @@ pub fn get_unit_type_visual_info(
         ("smg", "submachine", 2.0),
         ("scout", "scout", 2.5),
         ("mortar", "mortar", 1.5),
+        ("engineer", "soldier", 2.0),
         ("field_gun", "field_gun", 1.5),
         ("light_spg", "light_spg", 3.0),
         ("light_tank", "light_tank", 3.0),